@@ -0,0 +1,39 @@
+//! Evaluates the mlox base warning rules at increasing plugin counts and prints the timings as csv.
+//!
+//! Run with `cargo bench --bench evaluate` for the serial path
+//! and with `cargo bench --bench evaluate --features parallel` for the parallel path
+
+use std::time::Instant;
+
+use plox::{debug_get_mods_from_order_rules, parser};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+fn main() {
+    let mut parser = parser::new_tes3_parser();
+    parser
+        .init_from_file("./tests/mlox/mlox_base.txt")
+        .expect("could not read rules");
+    let mut mods = debug_get_mods_from_order_rules(&parser.order_rules);
+
+    // use a fixed seed so runs are comparable
+    let mut rng = StdRng::seed_from_u64(42);
+    mods.shuffle(&mut rng);
+
+    let mode = if cfg!(feature = "parallel") {
+        "parallel"
+    } else {
+        "serial"
+    };
+
+    println!("n,mode,warnings,millis");
+    for n in [64, 128, 256, 512, 1024] {
+        let max = std::cmp::min(n, mods.len());
+        let mods_rnd = mods.iter().take(max).cloned().collect::<Vec<_>>();
+
+        let now = Instant::now();
+        parser.evaluate_plugins(&mods_rnd);
+        let elapsed = now.elapsed().as_millis();
+
+        println!("{},{},{},{}", max, mode, parser.warnings.len(), elapsed);
+    }
+}
@@ -0,0 +1,53 @@
+//! Sorts the mlox base rules at increasing plugin counts and prints the timings as csv.
+//!
+//! Run with `cargo bench --bench sort`
+
+use std::time::Instant;
+
+use plox::{
+    debug_get_mods_from_order_rules, parser,
+    sorter::{ESortType, Sorter},
+    ESupportedGame,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+fn main() {
+    let mut parser = parser::new_tes3_parser();
+    parser
+        .init_from_file("./tests/mlox/mlox_base.txt")
+        .expect("could not read rules");
+    let mut mods = debug_get_mods_from_order_rules(&parser.order_rules);
+
+    // use a fixed seed so runs are comparable
+    let mut rng = StdRng::seed_from_u64(42);
+    mods.shuffle(&mut rng);
+
+    println!("n,sort_type,presort_edges,millis");
+    for n in [64, 128, 256, 512, 1024] {
+        let max = std::cmp::min(n, mods.len());
+        let mods_rnd = mods.iter().take(max).cloned().collect::<Vec<_>>();
+
+        for (sort_type, max_iterations) in
+            [(ESortType::StableOpt, 100), (ESortType::StableFull, 1000)]
+        {
+            for presort_edges in [true, false] {
+                let mut sorter =
+                    Sorter::new(sort_type, max_iterations).with_presort_edges(presort_edges);
+
+                let now = Instant::now();
+                let result = sorter.topo_sort(
+                    ESupportedGame::Morrowind,
+                    &mods_rnd,
+                    &parser.order_rules,
+                    &parser.warning_rules,
+                );
+                let elapsed = now.elapsed().as_millis();
+
+                match result {
+                    Ok(_) => println!("{},{:?},{},{}", max, sort_type, presort_edges, elapsed),
+                    Err(e) => println!("{},{:?},{},error: {}", max, sort_type, presort_edges, e),
+                }
+            }
+        }
+    }
+}
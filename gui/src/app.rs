@@ -7,23 +7,35 @@ use std::{
 use egui::{Color32, Label, Sense};
 
 use log::{error, info, LevelFilter};
-use plox::{rules::EWarningRule, update_new_load_order};
+use plox::{
+    get_load_order_path, get_problem_plugins, get_undo_path, normalize_name,
+    parser::RuleParseError,
+    rules::EWarningRule,
+    settings::{Settings, PLOX_SETTINGS_FILE},
+    undo_load_order, update_new_load_order, write_report, EConflictStrategy,
+};
 use simplelog::WriteLogger;
 
-use crate::{init_parser, AppData, AppSettings, ELoadStatus, ETheme};
+use crate::{
+    get_cycle_issue_url, get_cycle_report, get_my_rules_path, get_order_violations, init_parser,
+    save_my_rules, AppData, ELoadStatus, ETheme,
+};
 
-#[derive(PartialEq)]
+#[derive(PartialEq, serde::Deserialize, serde::Serialize)]
 pub enum EModListView {
     NewOrder,
     LoadOrder,
+    /// the new order, only plugins with warnings
+    Problems,
 }
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct TemplateApp {
+    // always read from plox.toml, never persisted
     #[serde(skip)]
-    settings: AppSettings,
+    settings: Settings,
     #[serde(skip)]
     modal_open: bool,
     #[serde(skip)]
@@ -34,15 +46,38 @@ pub struct TemplateApp {
     show_conflicts: bool,
     show_requires: bool,
     show_patches: bool,
+    text_filter: String,
 
-    #[serde(skip)]
+    // view
     mod_list_view: EModListView,
-    #[serde(skip)]
-    text_filter: String,
+
+    // plugin selections only apply to the current load order
     #[serde(skip)]
     plugin_filter: String,
     #[serde(skip)]
     plugin_hover_filter: Vec<String>,
+    #[serde(skip)]
+    plugin_search: String,
+    #[serde(skip)]
+    scroll_to_search: bool,
+    #[serde(skip)]
+    pending_move: Option<(usize, usize)>,
+    #[serde(skip)]
+    order_violations: Vec<String>,
+
+    // user rules editor
+    #[serde(skip)]
+    rules_editor_open: bool,
+    #[serde(skip)]
+    rules_editor_path: PathBuf,
+    #[serde(skip)]
+    rules_editor_text: String,
+    #[serde(skip)]
+    rules_editor_errors: Vec<RuleParseError>,
+    #[serde(skip)]
+    rules_editor_saved: bool,
+    #[serde(skip)]
+    undo_prompt_open: bool,
 
     // ui
     theme: Option<ETheme>,
@@ -65,7 +100,7 @@ impl Default for TemplateApp {
         let (tx2, rx2) = std::sync::mpsc::channel();
 
         Self {
-            settings: AppSettings::default(),
+            settings: Settings::default(),
             app_data: None,
             modal_open: false,
             show_notes: true,
@@ -77,6 +112,16 @@ impl Default for TemplateApp {
             text_filter: String::new(),
             plugin_filter: String::new(),
             plugin_hover_filter: vec![],
+            plugin_search: String::new(),
+            scroll_to_search: false,
+            pending_move: None,
+            order_violations: vec![],
+            rules_editor_open: false,
+            rules_editor_path: PathBuf::new(),
+            rules_editor_text: String::new(),
+            rules_editor_errors: vec![],
+            rules_editor_saved: false,
+            undo_prompt_open: false,
             async_log: String::new(),
             rx,
             tx,
@@ -102,8 +147,7 @@ impl TemplateApp {
         };
 
         // deserialize settings from plox.toml
-        let settings = AppSettings::from_file(&PathBuf::from("plox.toml"));
-        app.settings = settings.clone();
+        let mut settings = Settings::from_file(&PathBuf::from(PLOX_SETTINGS_FILE));
 
         // init logger
         let log_level = settings.log_level.clone().unwrap_or("info".to_string());
@@ -124,8 +168,17 @@ impl TemplateApp {
 
         info!("PLOX v{}", crate::CARGO_PKG_VERSION);
 
+        // there is no console to ask which conflicting plugins to keep
+        if settings.conflict_strategy == EConflictStrategy::Interactive {
+            error!(
+                "The Interactive conflict strategy is not supported in the GUI, using KeepFirst"
+            );
+            settings.conflict_strategy = EConflictStrategy::KeepFirst;
+        }
+        app.settings = settings.clone();
+
         // remove this when not in debug
-        // if let Ok(s) = toml::to_string_pretty(&AppSettings {
+        // if let Ok(s) = toml::to_string_pretty(&Settings {
         //     game: Some(plox::ESupportedGame::OpenMW),
         //     no_rules_download: true,
         //     config: Some(std::path::PathBuf::from("openmw.cfg")),
@@ -204,6 +257,53 @@ impl eframe::App for TemplateApp {
                     ui.menu_button("File", |ui| {
                         ui.hyperlink_to("PLOX on GitHub", "https://github.com/rfuzzo/plox");
 
+                        ui.separator();
+                        ui.add_enabled_ui(self.app_data.is_some(), |ui| {
+                            if ui.button("Export report").clicked() {
+                                if let Some(data) = &self.app_data {
+                                    let path = PathBuf::from("plox_report.md");
+                                    match write_report(
+                                        &path,
+                                        &data.warnings,
+                                        &data.old_order,
+                                        &data.new_order,
+                                    ) {
+                                        Ok(_) => info!("Report written to {}", path.display()),
+                                        Err(e) => error!("Could not write report: {}", e),
+                                    }
+                                }
+                                ui.close_menu();
+                            }
+                            if ui.button("Edit my rules").clicked() {
+                                if let Some(data) = &self.app_data {
+                                    let path = get_my_rules_path(&self.settings, data.game);
+                                    // a missing file is created on save
+                                    self.rules_editor_text =
+                                        std::fs::read_to_string(&path).unwrap_or_default();
+                                    self.rules_editor_path = path;
+                                    self.rules_editor_errors.clear();
+                                    self.rules_editor_saved = false;
+                                    self.rules_editor_open = true;
+                                }
+                                ui.close_menu();
+                            }
+                            let can_undo = self.app_data.as_ref().is_some_and(|data| {
+                                get_undo_path(&get_load_order_path(
+                                    data.game,
+                                    &data.root,
+                                    self.settings.config.as_ref(),
+                                ))
+                                .exists()
+                            });
+                            if ui
+                                .add_enabled(can_undo, egui::Button::new("Undo last sort"))
+                                .clicked()
+                            {
+                                self.undo_prompt_open = true;
+                                ui.close_menu();
+                            }
+                        });
+
                         ui.separator();
                         if ui.button("Quit").clicked() {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
@@ -237,6 +337,137 @@ impl eframe::App for TemplateApp {
             return;
         }
 
+        // apply manual reordering and re-validate the order
+        if let Some((from, to)) = self.pending_move.take() {
+            if let Some(data) = &mut self.app_data {
+                let plugin = data.new_order.remove(from);
+                data.new_order.insert(to, plugin);
+                self.order_violations = get_order_violations(&data.new_order, &data.order_rules);
+            }
+        }
+
+        // undo prompt
+        if self.undo_prompt_open {
+            let mut undone = false;
+            egui::Window::new("Undo last sort")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("Restore the load order from before the last sort?");
+                    ui.horizontal(|ui| {
+                        if ui.button("Restore").clicked() {
+                            if let Some(data) = &self.app_data {
+                                match undo_load_order(
+                                    data.game,
+                                    &data.root,
+                                    self.settings.config.clone(),
+                                ) {
+                                    Ok(applied) => {
+                                        info!("Restored {}", applied.path.display());
+                                        undone = true;
+                                    }
+                                    Err(e) => error!("Could not undo the load order: {}", e),
+                                }
+                            }
+                            self.undo_prompt_open = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.undo_prompt_open = false;
+                        }
+                    });
+                });
+
+            // reload the restored order
+            if undone {
+                let tx = self.tx.clone();
+                let tx2 = self.tx2.clone();
+                let settings = self.settings.clone();
+                self.modal_open = true;
+
+                std::thread::spawn(move || {
+                    let result = pollster::block_on(async { init_parser(settings, tx.clone()) });
+                    // send result to app
+                    let _ = tx.send("App initialized".to_string());
+                    let _ = tx2.send(result);
+                });
+            }
+        }
+
+        // user rules editor
+        if self.rules_editor_open {
+            let mut open = true;
+            let mut rerun = false;
+            egui::Window::new("My rules")
+                .open(&mut open)
+                .default_size([600_f32, 400_f32])
+                .show(ctx, |ui| {
+                    ui.label(self.rules_editor_path.display().to_string());
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            if let Some(data) = &self.app_data {
+                                match save_my_rules(
+                                    &self.rules_editor_path,
+                                    data.game,
+                                    &self.rules_editor_text,
+                                ) {
+                                    Ok(errors) => {
+                                        self.rules_editor_saved = errors.is_empty();
+                                        self.rules_editor_errors = errors;
+                                        if self.rules_editor_saved {
+                                            info!(
+                                                "Rules written to {}",
+                                                self.rules_editor_path.display()
+                                            );
+                                        }
+                                    }
+                                    Err(e) => error!("Could not write rules: {}", e),
+                                }
+                            }
+                        }
+                        if self.rules_editor_saved && ui.button("Sort again").clicked() {
+                            rerun = true;
+                        }
+                    });
+
+                    // the file is only written if all rules parse
+                    if !self.rules_editor_errors.is_empty() {
+                        ui.colored_label(Color32::RED, "Rules were not saved:");
+                        for e in &self.rules_editor_errors {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("line {}: {}", e.line, e.reason),
+                            );
+                        }
+                    }
+                    ui.separator();
+
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let response = ui.add_sized(
+                            ui.available_size(),
+                            egui::TextEdit::multiline(&mut self.rules_editor_text).code_editor(),
+                        );
+                        if response.changed() {
+                            self.rules_editor_saved = false;
+                        }
+                    });
+                });
+            self.rules_editor_open = open && !rerun;
+
+            if rerun {
+                let tx = self.tx.clone();
+                let tx2 = self.tx2.clone();
+                let settings = self.settings.clone();
+                self.modal_open = true;
+
+                std::thread::spawn(move || {
+                    let result = pollster::block_on(async { init_parser(settings, tx.clone()) });
+                    // send result to app
+                    let _ = tx.send("App initialized".to_string());
+                    let _ = tx2.send(result);
+                });
+            }
+        }
+
         // Update the counter with the async response.
         if let Some(data) = &self.app_data {
             // side panel
@@ -247,6 +478,7 @@ impl eframe::App for TemplateApp {
                     ui.horizontal(|ui| {
                         ui.radio_value(&mut self.mod_list_view, EModListView::LoadOrder, "Old");
                         ui.radio_value(&mut self.mod_list_view, EModListView::NewOrder, "New");
+                        ui.radio_value(&mut self.mod_list_view, EModListView::Problems, "Problems");
                     });
                     ui.separator();
 
@@ -275,7 +507,7 @@ impl eframe::App for TemplateApp {
                             });
                         }
                     } else if data.status == ELoadStatus::Cycle {
-                        let text = "Cycle detected in rules, please contact the rules maintainers.";
+                        let text = "Mods were not sorted, the rules contain a cycle.";
                         // red text
                         ui.colored_label(Color32::RED, text);
                     } else if data.status == ELoadStatus::Success {
@@ -289,8 +521,10 @@ impl eframe::App for TemplateApp {
                                 // apply sorting
                                 match update_new_load_order(
                                     data.game,
+                                    &data.root,
                                     &data.new_order,
                                     self.settings.config.clone(),
+                                    false,
                                 ) {
                                     Ok(_) => {
                                         info!("Update successful");
@@ -310,21 +544,52 @@ impl eframe::App for TemplateApp {
                         });
                     }
 
+                    if !self.order_violations.is_empty() {
+                        ui.colored_label(Color32::RED, "Manual order violates order rules.");
+                    }
+
                     ui.separator();
 
                     ui.add_space(4_f32);
 
+                    // plugin search
+                    let search = ui.add_sized(
+                        [ui.available_width(), 0_f32],
+                        egui::TextEdit::singleline(&mut self.plugin_search)
+                            .hint_text("Search plugins"),
+                    );
+                    if search.changed() {
+                        self.scroll_to_search = true;
+                    }
+                    ui.add_space(4_f32);
+
                     // mod list
+                    let problems;
                     let order = match self.mod_list_view {
                         EModListView::NewOrder => &data.new_order,
                         EModListView::LoadOrder => &data.old_order,
+                        EModListView::Problems => {
+                            problems =
+                                get_problem_plugins(&data.new_order, &data.plugin_warning_map);
+                            &problems
+                        }
                     };
+                    // copy the shown order
+                    let copy = egui::Button::new("Copy to clipboard");
+                    if ui.add_sized([ui.available_width(), 0_f32], copy).clicked() {
+                        ctx.copy_text(order.join("\n"));
+                    }
+                    ui.add_space(4_f32);
+
+                    // only the new order can be rearranged
+                    let can_drag = self.mod_list_view == EModListView::NewOrder
+                        && data.status == ELoadStatus::Success;
                     egui::ScrollArea::vertical().show(ui, |ui| {
-                        for mod_name in order {
+                        for (idx, mod_name) in order.iter().enumerate() {
                             let notes: Vec<_> = data
                                 .plugin_warning_map
                                 .iter()
-                                .filter(|(p, _)| p.to_lowercase() == *mod_name.to_lowercase())
+                                .filter(|(p, _)| normalize_name(p) == normalize_name(mod_name))
                                 .collect();
 
                             // get color for background
@@ -336,9 +601,22 @@ impl eframe::App for TemplateApp {
                             } else {
                                 Color32::TRANSPARENT
                             };
+                            // highlight plugins matching the search
+                            let is_search_match = !self.plugin_search.is_empty()
+                                && mod_name
+                                    .to_lowercase()
+                                    .contains(&self.plugin_search.to_lowercase());
+                            if is_search_match {
+                                bg_color = Color32::GOLD.gamma_multiply(0.5);
+                            }
+                            // mark plugins violating order rules
+                            if can_drag && self.order_violations.contains(&normalize_name(mod_name))
+                            {
+                                bg_color = Color32::RED.gamma_multiply(0.5);
+                            }
                             // override background color if mod is in plugin_filter with light blue
                             if !self.plugin_filter.is_empty()
-                                && mod_name.to_lowercase() == self.plugin_filter.to_lowercase()
+                                && normalize_name(mod_name) == normalize_name(&self.plugin_filter)
                             {
                                 bg_color = Color32::LIGHT_BLUE;
                                 if ctx.style().visuals.dark_mode {
@@ -346,7 +624,7 @@ impl eframe::App for TemplateApp {
                                 }
                             };
                             // override the background color if mod is hovered
-                            if self.plugin_hover_filter.contains(&mod_name.to_lowercase()) {
+                            if self.plugin_hover_filter.contains(&normalize_name(mod_name)) {
                                 bg_color = Color32::LIGHT_BLUE;
                                 if ctx.style().visuals.dark_mode {
                                     bg_color = Color32::DARK_BLUE;
@@ -355,9 +633,29 @@ impl eframe::App for TemplateApp {
 
                             // item view
                             egui::Frame::none().fill(bg_color).show(ui, |ui| {
-                                let label = Label::new(mod_name).sense(Sense::click());
+                                let sense = if can_drag {
+                                    Sense::click_and_drag()
+                                } else {
+                                    Sense::click()
+                                };
+                                let label = Label::new(mod_name).sense(sense);
 
                                 let r = ui.add_sized([ui.available_width(), 0_f32], label);
+                                // drag and drop reordering
+                                if can_drag {
+                                    r.dnd_set_drag_payload(idx);
+                                    if let Some(from) = r.dnd_release_payload::<usize>() {
+                                        if *from != idx {
+                                            self.pending_move = Some((*from, idx));
+                                            ctx.request_repaint();
+                                        }
+                                    }
+                                }
+                                // jump to the first match
+                                if is_search_match && self.scroll_to_search {
+                                    r.scroll_to_me(Some(egui::Align::Center));
+                                    self.scroll_to_search = false;
+                                }
                                 if r.clicked() {
                                     // unselect if clicked again
                                     if self.plugin_filter == mod_name.clone() {
@@ -376,11 +674,39 @@ impl eframe::App for TemplateApp {
             egui::CentralPanel::default().show(ctx, |ui| {
                 // The central panel the region left after adding TopPanel's and SidePanel's
                 ui.heading(format!(
-                    "PLOX v{} - {:?}",
+                    "PLOX v{} - {:?} - Rules: {}",
                     crate::CARGO_PKG_VERSION,
-                    data.game
+                    data.game,
+                    data.rules_version.as_deref().unwrap_or("unknown")
                 ));
 
+                // cycles in the order rules
+                if data.status == ELoadStatus::Cycle {
+                    ui.separator();
+                    ui.colored_label(Color32::RED, "Cycle detected in the order rules");
+                    if data.cycles.is_empty() {
+                        ui.label("The sorter ran out of iterations, the plugins are unknown.");
+                    } else {
+                        for (i, cycle) in data.cycles.iter().enumerate() {
+                            ui.label(format!("Cycle {}: {}", i + 1, cycle.join(", ")));
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Copy cycles").clicked() {
+                                ctx.copy_text(get_cycle_report(&data.cycles));
+                            }
+                            ui.hyperlink_to(
+                                "Report to the rules maintainers",
+                                get_cycle_issue_url(
+                                    data.game,
+                                    data.rules_version.as_deref(),
+                                    &data.cycles,
+                                ),
+                            );
+                        });
+                    }
+                    ui.separator();
+                }
+
                 // filters
                 ui.horizontal(|ui| {
                     ui.toggle_value(&mut self.show_notes, "Notes");
@@ -393,20 +719,48 @@ impl eframe::App for TemplateApp {
                     ui.add(egui::TextEdit::singleline(&mut self.text_filter).hint_text("Filter"));
                 });
 
+                let (show_notes, show_conflicts, show_requires, show_patches) = (
+                    self.show_notes,
+                    self.show_conflicts,
+                    self.show_requires,
+                    self.show_patches,
+                );
+                let is_shown = |rule: &EWarningRule| match rule {
+                    EWarningRule::Note(_) => show_notes,
+                    EWarningRule::Conflict(_) => show_conflicts,
+                    EWarningRule::Requires(_) => show_requires,
+                    EWarningRule::Patch(_) => show_patches,
+                };
+
+                // summary of the shown warnings
+                let count = |name: &str| {
+                    data.warnings
+                        .iter()
+                        .filter(|w| is_shown(&w.rule) && w.get_rule_name() == name)
+                        .count()
+                };
+                let mut plugins = data
+                    .plugin_warning_map
+                    .iter()
+                    .filter(|(_, i)| is_shown(&data.warnings[*i].rule))
+                    .map(|(p, _)| normalize_name(p))
+                    .collect::<Vec<_>>();
+                plugins.sort();
+                plugins.dedup();
+                ui.label(format!(
+                    "{} Notes, {} Conflicts, {} Requires, {} Patches - {} plugins with warnings",
+                    count("Note"),
+                    count("Conflict"),
+                    count("Requires"),
+                    count("Patch"),
+                    plugins.len()
+                ));
+
                 // display warnings
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     for (i, w) in data.warnings.iter().enumerate() {
                         //filters
-                        if !self.show_notes && matches!(w.rule, EWarningRule::Note(_)) {
-                            continue;
-                        }
-                        if !self.show_conflicts && matches!(w.rule, EWarningRule::Conflict(_)) {
-                            continue;
-                        }
-                        if !self.show_requires && matches!(w.rule, EWarningRule::Requires(_)) {
-                            continue;
-                        }
-                        if !self.show_patches && matches!(w.rule, EWarningRule::Patch(_)) {
+                        if !is_shown(&w.rule) {
                             continue;
                         }
 
@@ -427,7 +781,7 @@ impl eframe::App for TemplateApp {
                         if !self.plugin_filter.is_empty() {
                             let mut found = false;
                             for p in &w.get_plugins() {
-                                if p.to_lowercase() == self.plugin_filter.to_lowercase() {
+                                if normalize_name(p) == normalize_name(&self.plugin_filter) {
                                     found = true;
                                     break;
                                 }
@@ -449,8 +803,18 @@ impl eframe::App for TemplateApp {
 
                             frame.content_ui.push_id(i, |ui| {
                                 ui.collapsing("Plugins Affected", |ui| {
-                                    for plugin in &w.get_plugins() {
-                                        ui.label(plugin);
+                                    if let EWarningRule::Conflict(c) = &w.rule {
+                                        // one side per conflicting group
+                                        for (name, group) in c.get_groups() {
+                                            ui.strong(format!("Group {}", name));
+                                            for plugin in group {
+                                                ui.label(plugin);
+                                            }
+                                        }
+                                    } else {
+                                        for plugin in &w.get_plugins() {
+                                            ui.label(plugin);
+                                        }
                                     }
                                 });
                             });
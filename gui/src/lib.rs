@@ -4,6 +4,7 @@ mod app;
 
 use std::{
     env,
+    io::Cursor,
     path::{Path, PathBuf},
     sync::mpsc::Sender,
 };
@@ -11,9 +12,15 @@ use std::{
 pub use app::TemplateApp;
 use log::{error, warn};
 use plox::{
-    conflict2, detect_game, download_latest_rules, gather_mods, get_default_rules_dir,
-    parser::{self, Warning},
-    sorter::new_stable_sorter,
+    check_order, conflict2, detect_game, download_latest_rules, gather_mods, get_default_rules_dir,
+    get_game_version, get_ordering_from_order_rules, get_plugin_warning_map, get_rules_version,
+    normalize_name,
+    parser::{self, RuleParseError, Warning},
+    resolve_conflicts, restore_ignored_plugins,
+    rules::{EOrderRule, Order},
+    settings::Settings,
+    sorter::{new_stable_sorter, SortError},
+    wild_contains, ESupportedGame,
 };
 
 const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -24,45 +31,6 @@ enum ETheme {
     Light,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, Default)]
-struct AppSettings {
-    /// Specifies an openmw config file to use
-    config: Option<PathBuf>,
-
-    /// Specifies the game to use
-    game: Option<plox::ESupportedGame>,
-
-    /// set to not download rules
-    no_rules_download: bool,
-
-    /// log level
-    log_level: Option<String>,
-
-    /// use a log file bool
-    log_to_file: bool,
-
-    /// ignore warnings
-    ignore_warnings: bool,
-}
-impl AppSettings {
-    fn from_file(arg: &Path) -> Self {
-        // deserialize from toml file
-        match std::fs::read_to_string(arg) {
-            Ok(s) => match toml::from_str(&s) {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("Error deserializing settings: {}", e);
-                    AppSettings::default()
-                }
-            },
-            Err(e) => {
-                error!("Error reading settings file: {}", e);
-                AppSettings::default()
-            }
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ELoadStatus {
     Conflicts,
@@ -73,23 +41,127 @@ pub enum ELoadStatus {
 #[derive(Debug, Clone)]
 struct AppData {
     game: plox::ESupportedGame,
+    root: PathBuf,
+    rules_version: Option<String>,
     old_order: Vec<String>,
     new_order: Vec<String>,
     warnings: Vec<Warning>,
     plugin_warning_map: Vec<(String, usize)>,
+    order_rules: Vec<EOrderRule>,
     status: ELoadStatus,
+    /// The plugins of each cycle in the order rules, empty if the sort succeeded
+    cycles: Vec<Vec<String>>,
 }
 
-fn init_parser(settings: AppSettings, tx: Sender<String>) -> Option<AppData> {
+/// Returns all plugins in the order that are part of a violated order rule
+fn get_order_violations(order: &[String], order_rules: &[EOrderRule]) -> Vec<String> {
+    let order = order.iter().map(|p| normalize_name(p)).collect::<Vec<_>>();
+    let mut violations = vec![];
+    for (a, b) in get_ordering_from_order_rules(order_rules) {
+        if check_order(&order, &[Order::from(&a, &b).into()]) {
+            continue;
+        }
+
+        warn!("Manual order violates rule: {} -> {}", a, b);
+        for name in [a, b] {
+            if let Some(results) = wild_contains(&order, &name) {
+                for result in results {
+                    if !violations.contains(&result) {
+                        violations.push(result);
+                    }
+                }
+            }
+        }
+    }
+    violations
+}
+
+/// Formats the cycles as text, one cycle per line
+fn get_cycle_report(cycles: &[Vec<String>]) -> String {
+    cycles
+        .iter()
+        .enumerate()
+        .map(|(i, c)| format!("Cycle {}: {}", i + 1, c.join(", ")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Gets a link to a new issue in the rules repository of the game, pre-populated with the cycles
+fn get_cycle_issue_url(
+    game: ESupportedGame,
+    rules_version: Option<&str>,
+    cycles: &[Vec<String>],
+) -> String {
+    let repo = match game {
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => {
+            "https://github.com/DanaePlays/mlox-rules"
+        }
+        ESupportedGame::Cyberpunk => "https://github.com/rfuzzo/cmop-rules",
+    };
+    let body = format!(
+        "PLOX v{} found a cycle in the order rules (rules version: {}):\n\n```\n{}\n```",
+        CARGO_PKG_VERSION,
+        rules_version.unwrap_or("unknown"),
+        get_cycle_report(cycles)
+    );
+    format!(
+        "{}/issues/new?title={}&body={}",
+        repo,
+        encode_url_component("Cycle in order rules"),
+        encode_url_component(&body)
+    )
+}
+
+/// Percent-encodes everything but the unreserved characters of a url
+fn encode_url_component(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Gets the path of the user's own rules file in the rules dir
+fn get_my_rules_path(settings: &Settings, game: ESupportedGame) -> PathBuf {
+    let rules_dir = settings
+        .rules_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_default_rules_dir(game));
+    match game {
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => rules_dir.join("mlox_my_rules.txt"),
+        ESupportedGame::Cyberpunk => rules_dir.join("plox_my_rules.txt"),
+    }
+}
+
+/// Parses the rules and only writes them to the file if all of them are valid.
+/// Returns the rules that could not be parsed
+fn save_my_rules(
+    path: &Path,
+    game: ESupportedGame,
+    text: &str,
+) -> std::io::Result<Vec<RuleParseError>> {
+    let parser = parser::get_parser(game);
+    let (_, errors) = parser.parse_rules_from_reader_with_errors(Cursor::new(text.as_bytes()))?;
+    if errors.is_empty() {
+        std::fs::write(path, text)?;
+    }
+    Ok(errors)
+}
+
+fn init_parser(settings: Settings, tx: Sender<String>) -> Option<AppData> {
     // game
-    let game = if let Some(game) = settings.game {
+    let (game, root) = if let Some(game) = settings.game {
         let _ = tx.send(format!("Using game: {:?}", game));
-        game
+        (game, env::current_dir().expect("No current working dir"))
     } else {
         match detect_game() {
-            Some(g) => {
-                let _ = tx.send(format!("Detected game: {:?}", g));
-                g
+            Some((g, root)) => {
+                let _ = tx.send(format!("Detected game: {:?} in {}", g, root.display()));
+                (g, root)
             }
             None => {
                 let _ = tx.send("No game detected".to_string());
@@ -98,40 +170,59 @@ fn init_parser(settings: AppSettings, tx: Sender<String>) -> Option<AppData> {
         }
     };
 
-    let root = env::current_dir().expect("No current working dir");
-
     // rules
-    let rules_dir = get_default_rules_dir(game);
+    let rules_dir = settings
+        .rules_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_default_rules_dir(game));
     if !settings.no_rules_download {
         let _ = tx.send("Downloading rules".to_string());
-        download_latest_rules(game, &rules_dir);
+        download_latest_rules(game, &rules_dir, settings.rules_url.as_deref());
     } else {
         let _ = tx.send("Skipping rules download".to_string());
     }
 
+    let rules_version = get_rules_version(game, &rules_dir);
+    if let Some(version) = &rules_version {
+        let _ = tx.send(format!("Rules version: {}", version));
+    }
+
     // mods
     let _ = tx.send("Gathering mods".to_string());
     let mods = gather_mods(&root, game, settings.config);
 
     // parser
     let mut parser = parser::get_parser(game);
+    parser.set_game_version(get_game_version(game, &root));
     let _ = tx.send("Initializing parser".to_string());
     if let Err(e) = parser.parse(rules_dir) {
         error!("Parser init failed: {}", e);
         let _ = tx.send(format!("Parser init failed: {}", e));
         return None;
     }
+    // the built-in order alone is not a reason to sort
+    if !parser.order_rules.is_empty() {
+        parser
+            .order_rules
+            .extend(parser::get_builtin_order_rules(game));
+    }
 
     // evaluate
     let _ = tx.send("Evaluating plugins".to_string());
     parser.evaluate_plugins(&mods);
-    let warnings = parser.warnings.clone();
-    let mut plugin_warning_map = vec![];
-    for (i, w) in warnings.iter().enumerate() {
-        for p in &w.get_plugins() {
-            plugin_warning_map.push((p.clone(), i));
-        }
+    if game == ESupportedGame::Morrowind {
+        parser
+            .warnings
+            .extend(parser::get_unregistered_bsa_warnings(&root, &mods));
     }
+    if let Some(limit) = parser::get_default_plugin_limit(game) {
+        parser
+            .warnings
+            .extend(parser::get_plugin_count_warnings(&mods, limit));
+    }
+    let warnings = parser.warnings.clone();
+    let plugin_warning_map = get_plugin_warning_map(&warnings);
 
     // check if there are any conflicts
     let mut has_conflicts = false;
@@ -147,34 +238,53 @@ fn init_parser(settings: AppSettings, tx: Sender<String>) -> Option<AppData> {
         }
     }
 
-    // ignore warnings
+    // ignore warnings, conflicting plugins are not sorted and keep their position
+    let mut sort_mods = mods.clone();
+    let mut not_sorted = vec![];
     if settings.ignore_warnings {
+        if has_conflicts {
+            // the gui has no console, interactive is rejected when the settings are read
+            let strategy = settings.conflict_strategy;
+            let (remaining, removed) =
+                resolve_conflicts(game, &mods, &parser.warning_rules, |c, p| {
+                    strategy.get_group(c, p).unwrap_or(0)
+                });
+            for r in &removed {
+                let _ = tx.send(format!("Not sorting {}: {}", r.name, r.reason));
+            }
+            not_sorted = removed.into_iter().map(|r| r.name).collect::<Vec<_>>();
+            sort_mods = remaining;
+        }
         has_conflicts = false;
     }
 
     let status;
+    let mut cycles = vec![];
     // sort
-    let mut new_order = mods.iter().map(|m| m.name.clone()).collect();
+    let old_order = mods.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+    let mut new_order = old_order.clone();
     if !has_conflicts {
         if !&parser.order_rules.is_empty() {
             let mut sorter = new_stable_sorter();
             let _ = tx.send("Sorting mods".to_string());
 
-            match sorter.topo_sort(game, &mods, &parser.order_rules, &parser.warning_rules) {
+            match sorter.topo_sort(game, &sort_mods, &parser.order_rules, &parser.warning_rules) {
                 Ok(new) => {
-                    new_order = new;
+                    new_order = restore_ignored_plugins(&old_order, &new, &not_sorted);
                     status = ELoadStatus::Success;
                 }
                 Err(e) => {
-                    let error_msg = format!("{e:?}");
-                    error!("error sorting: {error_msg}");
+                    error!("error sorting: {e}");
 
-                    // TODO better
-                    if error_msg.contains("Out of iterations") {
+                    if let SortError::Cycle(c) = e {
+                        let _ = tx.send("Cycle detected, skipping sort.".to_string());
+                        cycles = c;
+                        status = ELoadStatus::Cycle;
+                    } else if matches!(e, SortError::OutOfIterations) {
                         let _ = tx.send("Cycle detected, skipping sort.".to_string());
                         status = ELoadStatus::Cycle;
                     } else {
-                        let _ = tx.send(format!("error sorting: {e:?}"));
+                        let _ = tx.send(format!("error sorting: {e}"));
                         // exit
                         return None;
                     }
@@ -191,11 +301,15 @@ fn init_parser(settings: AppSettings, tx: Sender<String>) -> Option<AppData> {
 
     let r = AppData {
         game,
-        old_order: mods.iter().map(|m| m.name.clone()).collect(),
+        root,
+        rules_version,
+        old_order,
         new_order,
         warnings,
         plugin_warning_map,
+        order_rules: parser.order_rules,
         status,
+        cycles,
     };
 
     Some(r)
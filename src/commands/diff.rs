@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::ExitCode;
+
+use log::{error, info};
+
+use crate::{
+    rules::{EOrderRule, ERule, EWarningRule, TWarningRule},
+    *,
+};
+
+/// The rules that differ between two rules files
+#[derive(Debug, Default)]
+pub struct RulesDiff {
+    pub added: Vec<ERule>,
+    pub removed: Vec<ERule>,
+    /// rules with the same plugins but a different comment, as (old, new)
+    pub changed: Vec<(ERule, ERule)>,
+}
+
+impl RulesDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+const RULE_TYPES: [&str; 8] = [
+    "Order",
+    "NearStart",
+    "NearEnd",
+    "Override",
+    "Note",
+    "Conflict",
+    "Requires",
+    "Patch",
+];
+
+/// Reports the rules added, removed and changed between two rules files
+pub fn diff(game: ESupportedGame, old: &Path, new: &Path) -> ExitCode {
+    let parser = parser::get_parser(game);
+    let parse = |path: &Path| {
+        File::open(path)
+            .and_then(|f| parser.parse_rules_from_reader(BufReader::new(f)))
+            .map_err(|e| error!("Could not read {}: {}", path.display(), e))
+    };
+    let (Ok(old_rules), Ok(new_rules)) = (parse(old), parse(new)) else {
+        return ExitCode::FAILURE;
+    };
+
+    let diff = diff_rules(&old_rules, &new_rules);
+    if diff.is_empty() {
+        info!("No rules changed");
+        return ExitCode::SUCCESS;
+    }
+
+    for rule_type in RULE_TYPES {
+        let added = diff
+            .added
+            .iter()
+            .filter(|r| get_rule_type(r) == rule_type)
+            .collect::<Vec<_>>();
+        let removed = diff
+            .removed
+            .iter()
+            .filter(|r| get_rule_type(r) == rule_type)
+            .collect::<Vec<_>>();
+        let changed = diff
+            .changed
+            .iter()
+            .filter(|(r, _)| get_rule_type(r) == rule_type)
+            .collect::<Vec<_>>();
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
+            continue;
+        }
+
+        info!(
+            "[{}] {} added, {} removed, {} changed",
+            rule_type,
+            added.len(),
+            removed.len(),
+            changed.len()
+        );
+        for rule in added {
+            println!("+ {}", rule);
+        }
+        for rule in removed {
+            println!("- {}", rule);
+        }
+        for (old_rule, new_rule) in changed {
+            println!("- {}", old_rule);
+            println!("+ {}", new_rule);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Compares two lists of rules.
+/// Rules are matched by their type and plugins, a rule with a different comment counts as changed.
+pub fn diff_rules(old: &[ERule], new: &[ERule]) -> RulesDiff {
+    let group = |rules: &[ERule]| {
+        let mut map: BTreeMap<String, Vec<ERule>> = BTreeMap::new();
+        for rule in rules {
+            map.entry(get_rule_key(rule))
+                .or_default()
+                .push(rule.clone());
+        }
+        map
+    };
+    let old = group(old);
+    let mut new = group(new);
+
+    let mut diff = RulesDiff::default();
+    for (key, mut old_rules) in old {
+        let mut new_rules = new.remove(&key).unwrap_or_default();
+
+        // identical rules are unchanged
+        old_rules.retain(|o| {
+            let serialized = serde_json::to_string(o).ok();
+            if let Some(i) = new_rules
+                .iter()
+                .position(|n| serde_json::to_string(n).ok() == serialized)
+            {
+                new_rules.remove(i);
+                false
+            } else {
+                true
+            }
+        });
+
+        // the remaining rules with the same key only differ in their comment
+        let changed = old_rules.len().min(new_rules.len());
+        let removed = old_rules.split_off(changed);
+        let added = new_rules.split_off(changed);
+        diff.changed.extend(old_rules.into_iter().zip(new_rules));
+        diff.removed.extend(removed);
+        diff.added.extend(added);
+    }
+    diff.added.extend(new.into_values().flatten());
+
+    diff
+}
+
+fn get_rule_type(rule: &ERule) -> &'static str {
+    match rule {
+        ERule::EOrderRule(EOrderRule::Order(_)) => "Order",
+        ERule::EOrderRule(EOrderRule::NearStart(_)) => "NearStart",
+        ERule::EOrderRule(EOrderRule::NearEnd(_)) => "NearEnd",
+        ERule::EOrderRule(EOrderRule::Override(_)) => "Override",
+        ERule::EWarningRule(EWarningRule::Note(_)) => "Note",
+        ERule::EWarningRule(EWarningRule::Conflict(_)) => "Conflict",
+        ERule::EWarningRule(EWarningRule::Requires(_)) => "Requires",
+        ERule::EWarningRule(EWarningRule::Patch(_)) => "Patch",
+    }
+}
+
+/// Identifies a rule by its serialized form without the comment
+fn get_rule_key(rule: &ERule) -> String {
+    let mut rule = rule.clone();
+    match &mut rule {
+        ERule::EOrderRule(EOrderRule::Order(o)) => o.comment.clear(),
+        ERule::EOrderRule(EOrderRule::NearStart(o)) => o.comment.clear(),
+        ERule::EOrderRule(EOrderRule::NearEnd(o)) => o.comment.clear(),
+        ERule::EOrderRule(EOrderRule::Override(_)) => {}
+        ERule::EWarningRule(w) => w.set_comment(String::new()),
+    }
+    serde_json::to_string(&rule).unwrap_or_default()
+}
@@ -0,0 +1,32 @@
+use std::process::ExitCode;
+
+use crate::sorter::SortError;
+
+/// The exit codes of all commands. Scripts may rely on them, never change their values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EExitCode {
+    Success = 0,
+    /// Any other error, e.g. file io or invalid rules
+    Failure = 1,
+    /// No plugins were found in the game folder or config
+    NoMods = 2,
+    /// No rules were found in the rules dir (verify and lookup only, sort succeeds without rules)
+    NoRules = 3,
+    /// The order rules contain a cycle
+    Cycle = 4,
+}
+
+impl From<EExitCode> for ExitCode {
+    fn from(code: EExitCode) -> Self {
+        ExitCode::from(code as u8)
+    }
+}
+
+impl From<&SortError> for EExitCode {
+    fn from(error: &SortError) -> Self {
+        match error {
+            SortError::Cycle(_) | SortError::OutOfIterations => EExitCode::Cycle,
+            _ => EExitCode::Failure,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::process::ExitCode;
+use std::{env, path::PathBuf};
+
+use log::{error, info, warn};
+
+use crate::sorter::EEdgeOrigin;
+use crate::*;
+
+/// Explains the placement of a single plugin: its order rules, masters and warnings
+#[allow(clippy::too_many_arguments)]
+pub fn explain(
+    game: ESupportedGame,
+    plugin: &str,
+    game_folder: &Option<PathBuf>,
+    rules_path: &Option<String>,
+    mod_list: &Option<PathBuf>,
+    mod_list_format: EModListFormat,
+    config: Option<PathBuf>,
+    game_version: Option<semver::Version>,
+) -> ExitCode {
+    // get game root
+    let root = match game_folder {
+        Some(path) => path.clone(),
+        None => env::current_dir().expect("No current working dir"),
+    };
+
+    // get default rules dir
+    let rules_dir = if let Some(path) = rules_path {
+        PathBuf::from(path)
+    } else {
+        get_default_rules_dir(game)
+    };
+    log_resolved_paths(game, Some(&root), &config, Some(&rules_dir));
+
+    // the game version is read from the game folder if not given
+    let game_version = game_version.or_else(|| get_game_version(game, &root));
+
+    // gather mods (optionally from a list)
+    let mods: Vec<PluginData>;
+    if let Some(modlist_path) = mod_list {
+        mods = read_mod_list(modlist_path, mod_list_format, &root, game, &config);
+    } else {
+        mods = gather_mods(&root, game, config);
+        if mods.is_empty() {
+            error!("No plugins found");
+            return EExitCode::NoMods.into();
+        }
+    }
+
+    let mut parser = parser::get_parser(game);
+    parser.set_game_version(game_version);
+    if let Err(e) = parser.parse(rules_dir) {
+        error!("Parser init failed: {}", e);
+        return ExitCode::FAILURE;
+    }
+    parser
+        .order_rules
+        .extend(parser::get_builtin_order_rules(game));
+    parser.evaluate_plugins(&mods);
+
+    let data = sorter::get_graph_data(&mods, &parser.order_rules, &parser.warning_rules);
+    let Some(edges) = data.get_plugin_edges(plugin) else {
+        error!("{} is not in the load order", plugin);
+        return ExitCode::FAILURE;
+    };
+
+    // placement after sorting
+    match sorter::new_stable_sorter().topo_sort(
+        game,
+        &mods,
+        &parser.order_rules,
+        &parser.warning_rules,
+    ) {
+        Ok(result) => {
+            if let Some(pos) = result.iter().position(|m| m.eq_ignore_ascii_case(plugin)) {
+                println!("{} is at position {} of {}", plugin, pos + 1, result.len());
+            }
+        }
+        Err(e) => warn!("Could not sort: {}", e),
+    }
+
+    println!("\nLoads after:");
+    for (name, origin) in &edges.incoming {
+        println!("  {} ({})", name, format_edge_origin(origin));
+    }
+    println!("\nLoads before:");
+    for (name, origin) in &edges.outgoing {
+        println!("  {} ({})", name, format_edge_origin(origin));
+    }
+
+    println!("\nWarnings:");
+    for warning in parser.warnings.iter().filter(|w| {
+        w.get_plugins()
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(plugin))
+    }) {
+        println!(
+            "  [{}] {}",
+            warning.get_rule_name(),
+            warning.get_comment().trim()
+        );
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn format_edge_origin(origin: &EEdgeOrigin) -> String {
+    match origin {
+        EEdgeOrigin::Order(comment) if comment.is_empty() => "order rule".to_owned(),
+        EEdgeOrigin::Order(comment) => format!("order rule: {}", comment),
+        EEdgeOrigin::Master => "master".to_owned(),
+    }
+}
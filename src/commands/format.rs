@@ -0,0 +1,102 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use log::{error, info};
+
+use crate::{rules::ERule, *};
+
+/// Writes a rules file in the canonical rule format to the output file, or to stdout if there is none.
+/// The input file is never rewritten, the parser lowercases all rules and drops `;` comments.
+pub fn format(game: ESupportedGame, file: &Path, output: &Option<PathBuf>) -> ExitCode {
+    let parser = parser::get_parser(game);
+    let (rules, errors) = match File::open(file)
+        .and_then(|f| parser.parse_rules_from_reader_with_errors(BufReader::new(f)))
+    {
+        Ok(it) => it,
+        Err(e) => {
+            error!("Could not read {}: {}", file.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // never drop rules that could not be parsed
+    if !errors.is_empty() {
+        for e in &errors {
+            error!("Malformed rule at line {}: {}", e.line, e.reason);
+        }
+        error!(
+            "Not formatting {}, fix the malformed rules first",
+            file.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    // tags are not part of the parsed rules and would be dropped
+    let tagged = fs::read_to_string(file)
+        .map(|text| text.lines().any(|l| !parser::get_rule_tags(l).is_empty()))
+        .unwrap_or_default();
+    if tagged {
+        error!(
+            "Not formatting {}, tagged rules are not supported by the formatter",
+            file.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let formatted = format_rules(&rules);
+    if !parses_back(&parser, &formatted) {
+        error!(
+            "Not formatting {}, the formatted rules would parse to different rules",
+            file.display()
+        );
+        return ExitCode::FAILURE;
+    }
+    let Some(path) = output else {
+        print!("{}", formatted);
+        return ExitCode::SUCCESS;
+    };
+    if is_same_file(path, file) {
+        error!(
+            "Not rewriting {}, its comments and casing would be lost. Write to another file",
+            file.display()
+        );
+        return ExitCode::FAILURE;
+    }
+    match fs::write(path, formatted) {
+        Ok(_) => {
+            info!("Formatted {} rules to {}", rules.len(), path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Could not write {}: {}", path.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Checks if both paths point to the same existing file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Checks that formatted rules parse back to the same rules
+pub fn parses_back(parser: &parser::Parser, formatted: &str) -> bool {
+    match parser.parse_rules_from_reader_with_errors(io::Cursor::new(formatted.as_bytes())) {
+        Ok((rules, errors)) => errors.is_empty() && format_rules(&rules) == formatted,
+        Err(_) => false,
+    }
+}
+
+/// Formats rules in their canonical form, separated by an empty line
+pub fn format_rules(rules: &[ERule]) -> String {
+    rules
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -6,12 +6,16 @@ use petgraph::dot::{Config, Dot};
 
 use crate::*;
 
+#[allow(clippy::too_many_arguments)]
 pub fn graph(
     game: ESupportedGame,
     game_folder: &Option<PathBuf>,
     rules_path: &Option<String>,
     mod_list: &Option<PathBuf>,
+    mod_list_format: EModListFormat,
     config: Option<PathBuf>,
+    game_version: Option<semver::Version>,
+    dump_edges: &Option<PathBuf>,
 ) -> ExitCode {
     // get game root
     let root = match game_folder {
@@ -25,32 +29,46 @@ pub fn graph(
     } else {
         get_default_rules_dir(game)
     };
+    log_resolved_paths(game, Some(&root), &config, Some(&rules_dir));
+
+    // the game version is read from the game folder if not given
+    let game_version = game_version.or_else(|| get_game_version(game, &root));
 
     // gather mods (optionally from a list)
     let mods: Vec<PluginData>;
     if let Some(modlist_path) = mod_list {
-        mods = read_file_as_list(modlist_path);
+        mods = read_mod_list(modlist_path, mod_list_format, &root, game, &config);
     } else {
-        mods = match game {
-            ESupportedGame::Morrowind => gather_tes3_mods(&root),
-            ESupportedGame::Cyberpunk => gather_cp77_mods(&root),
-            ESupportedGame::Openmw => gather_openmw_mods(&config),
-        };
+        mods = gather_mods(&root, game, config.clone());
         if mods.is_empty() {
-            info!("No mods found");
-            return ExitCode::FAILURE;
+            error!("No plugins found");
+            return EExitCode::NoMods.into();
         }
     }
 
     let mut parser = parser::get_parser(game);
+    parser.set_game_version(game_version);
     if let Err(e) = parser.parse(rules_dir) {
         error!("Parser init failed: {}", e);
         return ExitCode::FAILURE;
     }
+    parser
+        .order_rules
+        .extend(parser::get_builtin_order_rules(game));
 
     let data = sorter::get_graph_data(&mods, &parser.order_rules, &parser.warning_rules);
     let g = sorter::build_graph(&data);
 
+    if let Some(path) = dump_edges {
+        match write_edges(path, &data) {
+            Ok(_) => info!("Edges written to {}", path.display()),
+            Err(e) => {
+                error!("Could not write edges to {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
     {
         let viz = Dot::with_config(&g, &[Config::EdgeNoLabel]);
         // write to file
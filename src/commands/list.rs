@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 use std::process::ExitCode;
 
-use log::info;
+use log::{error, info};
 
 use crate::*;
 
@@ -10,6 +10,10 @@ pub fn list_mods(
     root: &Option<PathBuf>,
     game: ESupportedGame,
     config: Option<PathBuf>,
+    output: &Option<PathBuf>,
+    problems_only: bool,
+    mark_referenced: bool,
+    rules_path: &Option<String>,
 ) -> ExitCode {
     info!("Printing active mods...");
 
@@ -17,10 +21,67 @@ pub fn list_mods(
         Some(path) => path.clone(),
         None => env::current_dir().expect("No current working dir"),
     };
+    let rules_dir = (problems_only || mark_referenced).then(|| match rules_path {
+        Some(path) => PathBuf::from(path),
+        None => get_default_rules_dir(game),
+    });
+    log_resolved_paths(game, Some(&root), &config, rules_dir.as_deref());
 
-    for m in gather_mods(&root, game, config) {
-        println!("{}", m.name);
-        //info!("{}", m);
+    let mods = gather_mods(&root, game, config);
+    if mods.is_empty() {
+        error!("No plugins found");
+        return EExitCode::NoMods.into();
+    }
+    let mut names = mods.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+
+    let mut referenced = None;
+    if let Some(rules_dir) = rules_dir {
+        let mut parser = parser::get_parser(game);
+        if let Err(e) = parser.parse(rules_dir) {
+            error!("Parser init failed: {}", e);
+            return ExitCode::FAILURE;
+        }
+        if problems_only {
+            // only keep plugins with warnings
+            parser.evaluate_plugins(&mods);
+            if game == ESupportedGame::Morrowind {
+                parser
+                    .warnings
+                    .extend(parser::get_unregistered_bsa_warnings(&root, &mods));
+            }
+            names = get_problem_plugins(&names, &get_plugin_warning_map(&parser.warnings));
+        }
+        if mark_referenced {
+            referenced = Some(get_referenced_plugins(&parser, &names));
+        }
+    }
+
+    for name in &names {
+        // plugins that any rule references are marked with a *
+        match &referenced {
+            Some(referenced) if referenced.contains(&normalize_name(name)) => {
+                println!("* {}", name)
+            }
+            Some(_) => println!("  {}", name),
+            None => println!("{}", name),
+        }
+    }
+    if let Some(referenced) = &referenced {
+        info!(
+            "{} of {} plugins are referenced by rules",
+            referenced.len(),
+            names.len()
+        );
+    }
+
+    if let Some(path) = output {
+        match write_file_as_list(path, &names) {
+            Ok(_) => info!("Load order written to {}", path.display()),
+            Err(e) => {
+                error!("Could not write load order to {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
     }
 
     ExitCode::SUCCESS
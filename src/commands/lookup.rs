@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use log::{error, info, warn};
+
+use crate::parser::{Parser, RuleSource};
+use crate::*;
+
+/// Prints every rule that references the given plugin, with the file and line it was parsed from
+pub fn lookup_rules(game: ESupportedGame, plugin: &str, rules_path: &Option<String>) -> ExitCode {
+    // get default rules dir
+    let rules_dir = if let Some(path) = rules_path {
+        PathBuf::from(path)
+    } else {
+        get_default_rules_dir(game)
+    };
+    log_resolved_paths(game, None, &None, Some(&rules_dir));
+
+    let mut parser = parser::get_parser(game);
+    if let Err(e) = parser.parse(rules_dir) {
+        error!("Parser init failed: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    if parser.order_rules.is_empty() && parser.warning_rules.is_empty() {
+        warn!("No rules found in {}", rules_dir.display());
+        return EExitCode::NoRules.into();
+    }
+
+    let rules = get_referencing_rules(&parser, plugin);
+    if rules.is_empty() {
+        info!("No rules reference {}", plugin);
+        return ExitCode::SUCCESS;
+    }
+
+    for (rule, source) in &rules {
+        if let Some(source) = source {
+            println!("{}:{}", source.file, source.line);
+        }
+        println!("{}", rule);
+    }
+    info!("{} rules reference {}", rules.len(), plugin);
+
+    ExitCode::SUCCESS
+}
+
+/// Gets all order and warning rules whose names or expressions match the plugin, wildcards included
+pub fn get_referencing_rules(parser: &Parser, plugin: &str) -> Vec<(ERule, Option<RuleSource>)> {
+    let names = [normalize_name(plugin)];
+    let references = |name: &str| wild_contains(&names, &name.to_owned()).is_some();
+
+    let mut result = vec![];
+    for (idx, rule) in parser.order_rules.iter().enumerate() {
+        if rule.get_names().iter().any(|n| references(n)) {
+            result.push((rule.clone().into(), parser.order_sources.get(idx).cloned()));
+        }
+    }
+    for (idx, rule) in parser.warning_rules.iter().enumerate() {
+        let mut atomics = vec![];
+        for expression in rule.get_expressions() {
+            expression.collect_atomics(&mut atomics);
+        }
+        if atomics.iter().any(|n| references(n)) {
+            result.push((
+                rule.clone().into(),
+                parser.warning_sources.get(idx).cloned(),
+            ));
+        }
+    }
+    result
+}
+
+/// Gets the plugins that are referenced by at least one order or warning rule, wildcards included.
+/// Returns the normalized names in the order of the plugins
+pub fn get_referenced_plugins(parser: &Parser, plugins: &[String]) -> Vec<String> {
+    let names = plugins
+        .iter()
+        .map(|p| normalize_name(p))
+        .collect::<Vec<_>>();
+
+    let mut patterns = vec![];
+    for rule in &parser.order_rules {
+        patterns.extend(rule.get_names().iter().cloned());
+    }
+    for rule in &parser.warning_rules {
+        for expression in rule.get_expressions() {
+            expression.collect_atomics(&mut patterns);
+        }
+    }
+    patterns.sort();
+    patterns.dedup();
+
+    let mut referenced = HashSet::new();
+    for pattern in &patterns {
+        if let Some(matches) = wild_contains(&names, pattern) {
+            referenced.extend(matches);
+        }
+    }
+    names
+        .into_iter()
+        .filter(|n| referenced.contains(n))
+        .collect()
+}
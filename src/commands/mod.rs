@@ -1,3 +1,6 @@
+pub mod exit_code;
+pub use exit_code::*;
+
 pub mod graph;
 pub use graph::*;
 
@@ -9,3 +12,18 @@ pub use verify::*;
 
 pub mod list;
 pub use list::*;
+
+pub mod explain;
+pub use explain::*;
+
+pub mod format;
+pub use format::*;
+
+pub mod diff;
+pub use diff::*;
+
+pub mod lookup;
+pub use lookup::*;
+
+pub mod undo;
+pub use undo::*;
@@ -1,3 +1,4 @@
+use std::io::BufRead;
 use std::process::ExitCode;
 use std::{env, path::PathBuf};
 
@@ -10,10 +11,31 @@ pub struct CliSortOptions {
     pub game_folder: Option<PathBuf>,
     pub rules_dir: Option<String>,
     pub mod_list: Option<PathBuf>,
+    pub mod_list_format: EModListFormat,
     pub dry_run: bool,
-    pub unstable: bool,
+    pub stdout: bool,
+    pub quiet: bool,
+    pub no_redate: bool,
+    pub no_builtin_order: bool,
+    pub no_esm_hoist: bool,
+    pub plugin_limit: Option<usize>,
+    pub sort_type: sorter::ESortType,
     pub no_download: bool,
     pub config: Option<PathBuf>,
+    pub only: Option<String>,
+    pub since: Option<u64>,
+    pub report: Option<PathBuf>,
+    pub rules_url: Option<String>,
+    pub game_version: Option<semver::Version>,
+    pub groundcover: bool,
+    pub verify_sort: bool,
+    pub ignore: Option<PathBuf>,
+    pub conflict_strategy: Option<EConflictStrategy>,
+    pub rule_log: Option<PathBuf>,
+    pub loot_userlist: Option<PathBuf>,
+    pub enable_tags: Vec<String>,
+    pub disable_tags: Vec<String>,
+    pub check: bool,
 }
 
 /// Sorts the current mod load order according to specified rules
@@ -22,10 +44,34 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
     let root = options.game_folder;
     let rules_path = options.rules_dir;
     let mod_list = options.mod_list;
-    let dry_run = options.dry_run;
-    let unstable = options.unstable;
+    let mod_list_format = options.mod_list_format;
+    // printing the order to stdout never writes files
+    let stdout = options.stdout;
+    let dry_run = options.dry_run || stdout;
+    let quiet = options.quiet;
+    let no_redate = options.no_redate;
+    let no_builtin_order = options.no_builtin_order;
+    let no_esm_hoist = options.no_esm_hoist;
+    let plugin_limit = options
+        .plugin_limit
+        .or_else(|| parser::get_default_plugin_limit(game));
+    let sort_type = options.sort_type;
     let no_download = options.no_download;
     let config = options.config;
+    let only = options.only;
+    let since = options.since;
+    let report = options.report;
+    let rules_url = options.rules_url;
+    let game_version = options.game_version;
+    let groundcover = options.groundcover;
+    let verify_sort = options.verify_sort;
+    let ignore = options.ignore;
+    let conflict_strategy = options.conflict_strategy;
+    let rule_log = options.rule_log;
+    let loot_userlist = options.loot_userlist;
+    let enable_tags = options.enable_tags;
+    let disable_tags = options.disable_tags;
+    let check = options.check;
 
     // get game root
     let root = match root {
@@ -39,34 +85,68 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
     } else {
         get_default_rules_dir(game)
     };
+    log_resolved_paths(game, Some(&root), &config, Some(&rules_dir));
+
+    // the game version is read from the game folder if not given
+    let game_version = game_version.or_else(|| get_game_version(game, &root));
 
     if !no_download {
-        download_latest_rules(game, &rules_dir);
+        download_latest_rules(game, &rules_dir, rules_url.as_deref());
     } else {
         info!("Skipping downloading latest rules")
     }
 
     // gather mods (optionally from a list)
-    let mods: Vec<PluginData>;
+    let all_mods: Vec<PluginData>;
     if let Some(modlist_path) = mod_list {
-        mods = read_file_as_list(modlist_path);
+        all_mods = read_mod_list(modlist_path, mod_list_format, &root, game, &config);
     } else {
-        mods = match game {
-            ESupportedGame::Morrowind => gather_tes3_mods(&root),
-            ESupportedGame::Cyberpunk => gather_cp77_mods(&root),
-            ESupportedGame::Openmw => gather_openmw_mods(&config),
-        };
-        if mods.is_empty() {
-            info!("No mods found");
-            return ExitCode::FAILURE;
+        all_mods = gather_mods(&root, game, config.clone());
+        // no plugins is an error, no rules for the plugins is not
+        if all_mods.is_empty() {
+            error!("No plugins found, nothing to sort");
+            return EExitCode::NoMods.into();
+        }
+    }
+
+    // ignored plugins are not evaluated or sorted and keep their position
+    let ignore_path = match ignore {
+        Some(path) => {
+            if !path.exists() {
+                warn!("Ignore list not found at {}", path.display());
+            }
+            path
         }
+        None => rules_dir.join(PLOX_IGNORE_FILE),
+    };
+    let mut ignored = get_ignored_plugins(&all_mods, &read_ignore_list(&ignore_path));
+    if !ignored.is_empty() {
+        info!("Ignoring {} plugins: {:?}", ignored.len(), ignored);
     }
+    let mut mods = all_mods
+        .iter()
+        .filter(|p| !ignored.contains(&normalize_name(&p.name)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let all_order = all_mods
+        .iter()
+        .map(|f| f.name.to_owned())
+        .collect::<Vec<_>>();
 
     let mut parser = parser::get_parser(game);
+    parser.set_game_version(game_version);
+    parser.set_tags(&enable_tags, &disable_tags);
     if let Err(e) = parser.parse(rules_dir) {
         error!("Parser init failed: {}", e);
         return ExitCode::FAILURE;
     }
+    // the built-in order alone is not a reason to sort
+    let has_order_rules = !parser.order_rules.is_empty();
+    if has_order_rules && !no_builtin_order {
+        parser
+            .order_rules
+            .extend(parser::get_builtin_order_rules(game));
+    }
 
     // Print Warnings and Notes
     if parser.warning_rules.is_empty() {
@@ -76,16 +156,30 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
         debug!("{:?}", &mods);
 
         parser.evaluate_plugins(&mods);
-        for warning in parser.warnings {
-            let rule = warning.rule;
-            match rule {
+        if game == ESupportedGame::Morrowind {
+            parser
+                .warnings
+                .extend(parser::get_unregistered_bsa_warnings(&root, &mods));
+        }
+        // ignored plugins are still loaded by the game
+        if let Some(limit) = plugin_limit {
+            parser
+                .warnings
+                .extend(parser::get_plugin_count_warnings(&all_mods, limit));
+        }
+        for warning in &parser.warnings {
+            match &warning.rule {
+                // notes are not actionable, only print them when not quiet
+                EWarningRule::Note(_) if quiet => continue,
                 EWarningRule::Note(n) => {
                     info!("[NOTE]\n{}", n.get_comment());
                     info!("Reference: [{}]", n.plugins.join(";"));
                 }
                 EWarningRule::Conflict(c) => {
                     warn!("[CONFLICT]\n{}", c.get_comment());
-                    info!("Reference: [{}]", c.plugins.join(";"));
+                    for (name, group) in c.get_groups() {
+                        info!("Group {}: [{}]", name, group.join(";"));
+                    }
                 }
                 EWarningRule::Requires(r) => {
                     error!("[REQUIRES]\n{}", r.get_comment());
@@ -96,43 +190,124 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
                     info!("Reference: [{}]", p.plugins.join(";"));
                 }
             }
-            println!();
+            // keep stdout clean for the order
+            if !stdout {
+                println!();
+            }
         }
+        export_rule_log(&rule_log, &parser.warnings);
     }
 
+    // Sort groundcover (OpenMW only)
+    if groundcover {
+        if game != ESupportedGame::Openmw {
+            warn!("Groundcover sorting is only supported for OpenMW");
+        } else if !sort_groundcover(&parser, &config, sort_type, dry_run) {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // conflicting plugins are not sorted and keep their position like ignored plugins
+    if let Some(strategy) = conflict_strategy {
+        let (_, removed) = resolve_conflicts(game, &mods, &parser.warning_rules, |c, p| {
+            strategy
+                .get_group(c, p)
+                .unwrap_or_else(|| prompt_conflict_group(c, &mut std::io::stdin().lock()))
+        });
+        ignored.extend(removed.into_iter().map(|r| r.name));
+        mods.retain(|p| !ignored.contains(&normalize_name(&p.name)));
+    }
+
+    let old_order = mods.iter().map(|f| f.name.to_owned()).collect::<Vec<_>>();
+
     // Sort
-    if parser.order_rules.is_empty() {
-        warn!("No rules found to sort");
+    if !has_order_rules {
+        warn!("No order rules found, the load order is left untouched");
+        export_report(&report, &parser.warnings, &all_order, &all_order);
+        if stdout {
+            print_order(&all_order);
+        }
         ExitCode::SUCCESS
+    } else if check {
+        // only check the current order
+        let order = mods
+            .iter()
+            .map(|f| normalize_name(&f.name))
+            .collect::<Vec<_>>();
+        let violations = check_order_detailed(&order, &parser.order_rules);
+        if violations.is_empty() {
+            info!("Mods are in correct order, no sorting needed.");
+            return ExitCode::SUCCESS;
+        }
+        for (a, b) in &violations {
+            warn!("{} should load before {}", a, b);
+        }
+        error!("Found {} order violations", violations.len());
+        ExitCode::FAILURE
     } else {
         info!("Sorting mods...");
-        let mut sorter = if unstable {
-            sorter::new_unstable_sorter()
-        } else {
-            sorter::new_stable_sorter()
-        };
-
-        // check order first
-        // match check_order(&mods, &parser.order_rules) {
-        //     true => {
-        //         // exit
-        //         info!("Mods are in correct order, no sorting needed.");
-        //         return ExitCode::SUCCESS;
-        //     }
-        //     false => {}
-        // }
-
-        match sorter.topo_sort(game, &mods, &parser.order_rules, &parser.warning_rules) {
+        // Morrowind always loads masters first, only OpenMW can load them mid-list
+        let hoist_masters = !no_esm_hoist || game != ESupportedGame::Openmw;
+        if no_esm_hoist && hoist_masters {
+            warn!("Masters are always moved to the top for {:?}", game);
+        }
+        let mut sorter = sorter::new_sorter(sort_type).with_hoist_masters(hoist_masters);
+
+        if let Some(only) = &only {
+            info!("Only sorting plugins matching {}", only);
+        }
+
+        // plugins older than the reference keep their position
+        let mut locked = vec![];
+        if let Some(since) = since {
+            if game == ESupportedGame::Morrowind {
+                locked = get_tes3_plugins_modified_before(&root, &mods, since);
+                info!("Locking {} plugins modified before {}", locked.len(), since);
+            } else {
+                warn!("Sorting only new plugins is only supported for Morrowind");
+            }
+        }
+
+        if verify_sort {
+            verify_stable_sorters(game, &mods, &only, &locked, &parser, hoist_masters);
+        }
+
+        let sort_result = run_sorter(&mut sorter, game, &mods, &only, &locked, &parser);
+
+        match sort_result {
             Ok(result) => {
+                // explain why plugins were moved
+                let data =
+                    sorter::get_graph_data(&mods, &parser.order_rules, &parser.warning_rules);
+                if data.edges.is_empty() {
+                    info!("No order rules apply to the {} plugins", mods.len());
+                }
+                if !hoist_masters {
+                    for name in sorter::get_misplaced_masters(&result) {
+                        warn!("{} is not at the top of the load order", name);
+                    }
+                }
+                for (a, b, comment) in data.get_move_reasons(&old_order) {
+                    if comment.is_empty() {
+                        debug!("Moved {} before {}", a, b);
+                    } else {
+                        info!("Moved {} before {} because: {}", a, b, comment);
+                    }
+                }
+                export_loot_userlist(&loot_userlist, &data);
+
+                let result = restore_ignored_plugins(&all_order, &result, &ignored);
+                export_report(&report, &parser.warnings, &all_order, &result);
+
                 if dry_run {
                     info!("Dry run...");
 
-                    debug!("Old:\n{:?}", &mods);
+                    debug!("Old:\n{:?}", &all_mods);
                     debug!("New:\n{:?}", result);
 
-                    if mods
+                    if all_mods
                         .iter()
-                        .map(|f| f.name.to_lowercase())
+                        .map(|f| normalize_name(&f.name))
                         .collect::<Vec<_>>()
                         .eq(&result)
                     {
@@ -140,14 +315,17 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
                     } else {
                         info!("New order:\n{:?}", result);
                     }
+                    if stdout {
+                        print_order(&result);
+                    }
 
                     ExitCode::SUCCESS
                 } else {
-                    info!("Current:\n{:?}", &mods);
+                    info!("Current:\n{:?}", &all_mods);
 
-                    if mods
+                    if all_mods
                         .iter()
-                        .map(|f| f.name.to_lowercase())
+                        .map(|f| normalize_name(&f.name))
                         .collect::<Vec<_>>()
                         .eq(&result)
                     {
@@ -156,7 +334,7 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
                     } else {
                         info!("New:\n{:?}", result);
 
-                        match update_new_load_order(game, &result, config) {
+                        match update_new_load_order(game, &root, &result, config, no_redate) {
                             Ok(_) => {
                                 info!("Update successful");
                                 ExitCode::SUCCESS
@@ -170,9 +348,204 @@ pub fn sort(options: CliSortOptions) -> ExitCode {
                 }
             }
             Err(e) => {
-                error!("error sorting: {e:?}");
-                ExitCode::FAILURE
+                error!("error sorting: {e}");
+                EExitCode::from(&e).into()
             }
         }
     }
 }
+
+/// Asks on the console which group of a conflict to keep, keeps the first group if there is no input
+pub fn prompt_conflict_group<R: BufRead>(conflict: &Conflict, input: &mut R) -> usize {
+    println!("[CONFLICT] {}", conflict.comment);
+    for (i, group) in conflict.conflicts.iter().enumerate() {
+        println!("  {}: {}", i + 1, group.join(", "));
+    }
+    loop {
+        println!(
+            "Which plugins do you want to keep? [1-{}]",
+            conflict.conflicts.len()
+        );
+        let mut buffer = String::new();
+        if !matches!(input.read_line(&mut buffer), Ok(n) if n > 0) {
+            return 0;
+        }
+        match buffer.trim().parse::<usize>() {
+            Ok(i) if (1..=conflict.conflicts.len()).contains(&i) => return i - 1,
+            _ => println!("Invalid selection"),
+        }
+    }
+}
+
+/// Prints the order to stdout, one plugin per line
+fn print_order(order: &[String]) {
+    for name in order {
+        println!("{}", name);
+    }
+}
+
+fn run_sorter(
+    sorter: &mut sorter::Sorter,
+    game: ESupportedGame,
+    mods: &[PluginData],
+    only: &Option<String>,
+    locked: &[String],
+    parser: &parser::Parser,
+) -> Result<Vec<String>, sorter::SortError> {
+    if let Some(only) = only {
+        sorter.topo_sort_only(game, mods, only, &parser.order_rules, &parser.warning_rules)
+    } else if !locked.is_empty() {
+        sorter.topo_sort_locked(
+            game,
+            mods,
+            locked,
+            &parser.order_rules,
+            &parser.warning_rules,
+        )
+    } else {
+        sorter.topo_sort(game, mods, &parser.order_rules, &parser.warning_rules)
+    }
+}
+
+/// Runs the optimized and the full stable sorter and reports if their results differ
+fn verify_stable_sorters(
+    game: ESupportedGame,
+    mods: &[PluginData],
+    only: &Option<String>,
+    locked: &[String],
+    parser: &parser::Parser,
+    hoist_masters: bool,
+) {
+    info!("Verifying the optimized sorter against the full sorter...");
+    let opt = run_sorter(
+        &mut sorter::new_stable_sorter().with_hoist_masters(hoist_masters),
+        game,
+        mods,
+        only,
+        locked,
+        parser,
+    );
+    let full = run_sorter(
+        &mut sorter::new_full_sorter().with_hoist_masters(hoist_masters),
+        game,
+        mods,
+        only,
+        locked,
+        parser,
+    );
+
+    match (opt, full) {
+        (Ok(opt), Ok(full)) => {
+            if let Some(i) = opt.iter().zip(&full).position(|(a, b)| a != b) {
+                warn!(
+                    "The optimized and the full sorter disagree at position {}: {} vs {}",
+                    i, opt[i], full[i]
+                );
+                warn!("Optimized:\n{:?}", opt);
+                warn!("Full:\n{:?}", full);
+            } else {
+                info!("The optimized and the full sorter agree");
+            }
+        }
+        (opt, full) => {
+            if opt.is_ok() != full.is_ok() {
+                warn!("The optimized and the full sorter disagree");
+            }
+            if let Err(e) = opt {
+                warn!("Optimized sorter failed: {e}");
+            }
+            if let Err(e) = full {
+                warn!("Full sorter failed: {e}");
+            }
+        }
+    }
+}
+
+/// Sorts the groundcover entries of the openmw.cfg with the same rules as the content entries
+fn sort_groundcover(
+    parser: &parser::Parser,
+    config: &Option<PathBuf>,
+    sort_type: sorter::ESortType,
+    dry_run: bool,
+) -> bool {
+    let groundcover = gather_openmw_groundcover(config);
+    if groundcover.is_empty() {
+        info!("No groundcover found");
+        return true;
+    }
+
+    info!("Sorting groundcover...");
+    let mut sorter = sorter::new_sorter(sort_type);
+    let result = match sorter.topo_sort(
+        ESupportedGame::Openmw,
+        &groundcover,
+        &parser.order_rules,
+        &parser.warning_rules,
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            error!("error sorting groundcover: {e}");
+            return false;
+        }
+    };
+
+    if groundcover
+        .iter()
+        .map(|f| normalize_name(&f.name))
+        .collect::<Vec<_>>()
+        .eq(&result)
+    {
+        info!("Groundcover is in correct order, no sorting needed.");
+        return true;
+    }
+
+    info!("New groundcover order:\n{:?}", result);
+    if dry_run {
+        return true;
+    }
+    match update_openmw_groundcover(&result, config.as_ref()) {
+        Ok(_) => {
+            info!("Groundcover update successful");
+            true
+        }
+        Err(e) => {
+            error!("Could not update groundcover order: {}", e);
+            false
+        }
+    }
+}
+
+/// Writes a report of the warnings and load orders if a path was specified
+fn export_report(
+    path: &Option<PathBuf>,
+    warnings: &[parser::Warning],
+    old_order: &[String],
+    new_order: &[String],
+) {
+    if let Some(path) = path {
+        match write_report(path, warnings, old_order, new_order) {
+            Ok(_) => info!("Report written to {}", path.display()),
+            Err(e) => error!("Could not write report to {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Writes a log of all fired warning rules if a path was specified
+fn export_rule_log(path: &Option<PathBuf>, warnings: &[parser::Warning]) {
+    if let Some(path) = path {
+        match write_rule_log(path, warnings) {
+            Ok(_) => info!("Rule log written to {}", path.display()),
+            Err(e) => error!("Could not write rule log to {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Writes the order edges as a LOOT userlist if a path was specified
+fn export_loot_userlist(path: &Option<PathBuf>, data: &sorter::GraphData) {
+    if let Some(path) = path {
+        match write_loot_userlist(path, data) {
+            Ok(_) => info!("LOOT userlist written to {}", path.display()),
+            Err(e) => error!("Could not write LOOT userlist to {}: {}", path.display(), e),
+        }
+    }
+}
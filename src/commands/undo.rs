@@ -0,0 +1,53 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use log::{error, info};
+
+use crate::*;
+
+/// Restores the load order from before the last sort in the game folder
+pub fn undo(
+    game: ESupportedGame,
+    game_folder: &Option<PathBuf>,
+    config: Option<PathBuf>,
+) -> ExitCode {
+    let root = match game_folder {
+        Some(path) => path.clone(),
+        None => env::current_dir().expect("No current working dir"),
+    };
+
+    let path = get_load_order_path(game, &root, config.as_ref());
+    if !get_undo_path(&path).exists() {
+        error!("No applied load order found for {}", path.display());
+        return ExitCode::FAILURE;
+    }
+
+    match undo_load_order(game, &root, config) {
+        Ok(applied) => {
+            if applied.created {
+                info!(
+                    "Removed {}, it did not exist before",
+                    applied.path.display()
+                );
+            } else {
+                info!(
+                    "Restored {} from {}",
+                    applied.path.display(),
+                    get_backup_path(&applied.path).display()
+                );
+            }
+            if !applied.file_times.is_empty() {
+                info!(
+                    "Restored the file times of {} plugins",
+                    applied.file_times.len()
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("Could not undo the load order: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
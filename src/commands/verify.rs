@@ -2,20 +2,83 @@ use std::path::PathBuf;
 use std::process::ExitCode;
 
 use log::{error, info, warn};
+use serde::Serialize;
 
-use crate::*;
+use crate::{
+    expressions::*,
+    parser::{RuleParseError, RuleSource},
+    *,
+};
+
+/// Machine-readable result of a rules verification
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub success: bool,
+    pub order: usize,
+    pub nearstart: usize,
+    pub nearend: usize,
+    pub overrides: usize,
+    pub notes: usize,
+    pub conflicts: usize,
+    pub requires: usize,
+    pub patches: usize,
+    pub malformed_rules: Vec<RuleParseError>,
+    pub invalid_expressions: Vec<InvalidExpression>,
+    pub unknown_keywords: Vec<UnknownKeyword>,
+    /// only checked when a game folder is given
+    pub uninstalled_references: Vec<UninstalledReference>,
+}
+
+/// An expression whose regex or version can't be parsed
+#[derive(Debug, Serialize)]
+pub struct InvalidExpression {
+    pub expression: String,
+    pub reason: String,
+}
+
+/// A rule keyword that is not supported, with the first rule using it
+#[derive(Debug, Serialize)]
+pub struct UnknownKeyword {
+    pub keyword: String,
+    pub count: usize,
+    pub file: String,
+    pub line: usize,
+}
+
+/// A rule that references plugins which match nothing installed
+#[derive(Debug, Serialize)]
+pub struct UninstalledReference {
+    pub rule: String,
+    pub source: Option<RuleSource>,
+    pub plugins: Vec<String>,
+}
 
 /// Verifies integrity of the specified rules
-pub fn verify(game: ESupportedGame, rules_path: &Option<String>) -> ExitCode {
+pub fn verify(
+    game: ESupportedGame,
+    game_folder: &Option<PathBuf>,
+    rules_path: &Option<String>,
+    game_version: Option<semver::Version>,
+    json: bool,
+) -> ExitCode {
     let rules_dir = if let Some(path) = rules_path {
         PathBuf::from(path)
     } else {
         get_default_rules_dir(game)
     };
+    log_resolved_paths(game, game_folder.as_deref(), &None, Some(&rules_dir));
+
+    // the game version is read from the game folder if not given
+    let game_version = game_version.or_else(|| {
+        game_folder
+            .as_deref()
+            .and_then(|root| get_game_version(game, root))
+    });
 
     info!("Verifying rules from {} ...", rules_dir.display());
 
     let mut parser = parser::get_parser(game);
+    parser.set_game_version(game_version);
     if let Err(e) = parser.parse(rules_dir) {
         error!("Parser init failed: {}", e);
         return ExitCode::FAILURE;
@@ -23,11 +86,11 @@ pub fn verify(game: ESupportedGame, rules_path: &Option<String>) -> ExitCode {
 
     if parser.warning_rules.is_empty() {
         warn!("No rules found to evaluate");
-        return ExitCode::FAILURE;
+        return EExitCode::NoRules.into();
     }
 
     let mods = debug_get_mods_from_order_rules(&parser.order_rules);
-    match sorter::new_unstable_sorter().topo_sort(
+    let code = match sorter::new_unstable_sorter().topo_sort(
         game,
         &mods,
         &parser.order_rules,
@@ -35,11 +98,211 @@ pub fn verify(game: ESupportedGame, rules_path: &Option<String>) -> ExitCode {
     ) {
         Ok(_) => {
             info!("Verify SUCCESS");
-            ExitCode::SUCCESS
+            EExitCode::Success
         }
-        Err(_) => {
+        Err(e) => {
             error!("Verify FAILURE");
-            ExitCode::FAILURE
+            EExitCode::from(&e)
+        }
+    };
+    let success = code == EExitCode::Success;
+
+    for unknown in get_unknown_keywords(&parser.parse_errors) {
+        warn!(
+            "Unsupported rule [{}] used {} times, first in {}:{}",
+            unknown.keyword, unknown.count, unknown.file, unknown.line
+        );
+    }
+
+    // stale references need the installed plugins
+    let mut uninstalled_references = vec![];
+    if let Some(root) = game_folder {
+        let installed = gather_mods(root, game, None);
+        uninstalled_references = get_uninstalled_references(&parser, &installed);
+        for reference in &uninstalled_references {
+            let source = reference
+                .source
+                .as_ref()
+                .map(|s| format!("{}:{} ", s.file, s.line))
+                .unwrap_or_default();
+            warn!(
+                "{}[{}] references plugins that are not installed: {}",
+                source,
+                reference.rule,
+                reference.plugins.join(", ")
+            );
+        }
+        info!(
+            "{} rules reference plugins that are not installed",
+            uninstalled_references.len()
+        );
+    }
+
+    if json {
+        let mut report = get_verify_report(&parser, success);
+        report.uninstalled_references = uninstalled_references;
+        match serde_json::to_string_pretty(&report) {
+            Ok(s) => println!("{}", s),
+            Err(e) => error!("Could not serialize verify report: {}", e),
+        }
+    }
+
+    code.into()
+}
+
+/// Collects statistics and problems of the parsed rules
+pub fn get_verify_report(parser: &parser::Parser, success: bool) -> VerifyReport {
+    let mut report = VerifyReport {
+        success,
+        malformed_rules: parser.parse_errors.clone(),
+        unknown_keywords: get_unknown_keywords(&parser.parse_errors),
+        ..Default::default()
+    };
+
+    for rule in &parser.order_rules {
+        match rule {
+            EOrderRule::Order(_) => report.order += 1,
+            EOrderRule::NearStart(_) => report.nearstart += 1,
+            EOrderRule::NearEnd(_) => report.nearend += 1,
+            EOrderRule::Override(_) => report.overrides += 1,
+        }
+    }
+
+    for rule in &parser.warning_rules {
+        let expressions = match rule {
+            EWarningRule::Note(x) => {
+                report.notes += 1;
+                x.expressions.iter().collect::<Vec<_>>()
+            }
+            EWarningRule::Conflict(x) => {
+                report.conflicts += 1;
+                x.expressions.iter().collect::<Vec<_>>()
+            }
+            EWarningRule::Requires(x) => {
+                report.requires += 1;
+                x.expression_a.iter().chain(x.expression_b.iter()).collect()
+            }
+            EWarningRule::Patch(x) => {
+                report.patches += 1;
+                x.expression_a.iter().chain(x.expression_b.iter()).collect()
+            }
+        };
+        for expression in expressions {
+            collect_invalid_expressions(expression, &mut report.invalid_expressions);
+        }
+    }
+
+    report
+}
+
+/// Counts the rules that failed to parse because their keyword is not supported, in order of first occurrence
+pub fn get_unknown_keywords(parse_errors: &[RuleParseError]) -> Vec<UnknownKeyword> {
+    let mut result: Vec<UnknownKeyword> = vec![];
+    for error in parse_errors {
+        let Some(keyword) = parser::get_unknown_keyword(&error.text) else {
+            continue;
+        };
+        match result.iter_mut().find(|k| k.keyword == keyword) {
+            Some(unknown) => unknown.count += 1,
+            None => result.push(UnknownKeyword {
+                keyword,
+                count: 1,
+                file: error.file.clone(),
+                line: error.line,
+            }),
+        }
+    }
+    result
+}
+
+/// Gets all rules that reference plugins matching none of the installed plugins, wildcards included
+pub fn get_uninstalled_references(
+    parser: &parser::Parser,
+    installed: &[PluginData],
+) -> Vec<UninstalledReference> {
+    let names = installed
+        .iter()
+        .map(|p| normalize_name(&p.name))
+        .collect::<Vec<_>>();
+    let uninstalled = |references: Vec<String>| {
+        let mut result = vec![];
+        for name in references {
+            if wild_contains(&names, &name).is_none() && !result.contains(&name) {
+                result.push(name);
+            }
+        }
+        result
+    };
+
+    let mut result = vec![];
+    for (idx, rule) in parser.order_rules.iter().enumerate() {
+        let rule_name = match rule {
+            EOrderRule::Order(_) => "Order",
+            EOrderRule::NearStart(_) => "NearStart",
+            EOrderRule::NearEnd(_) => "NearEnd",
+            EOrderRule::Override(_) => "Override",
+        };
+        let plugins = uninstalled(rule.get_names().to_vec());
+        if !plugins.is_empty() {
+            result.push(UninstalledReference {
+                rule: rule_name.to_owned(),
+                source: parser.order_sources.get(idx).cloned(),
+                plugins,
+            });
+        }
+    }
+    for (idx, rule) in parser.warning_rules.iter().enumerate() {
+        let rule_name = match rule {
+            EWarningRule::Note(_) => "Note",
+            EWarningRule::Conflict(_) => "Conflict",
+            EWarningRule::Requires(_) => "Requires",
+            EWarningRule::Patch(_) => "Patch",
+        };
+        let mut atomics = vec![];
+        for expression in rule.get_expressions() {
+            expression.collect_atomics(&mut atomics);
+        }
+        let plugins = uninstalled(atomics);
+        if !plugins.is_empty() {
+            result.push(UninstalledReference {
+                rule: rule_name.to_owned(),
+                source: parser.warning_sources.get(idx).cloned(),
+                plugins,
+            });
+        }
+    }
+    result
+}
+
+/// Checks the regexes and versions of an expression recursively
+fn collect_invalid_expressions(expression: &Expression, result: &mut Vec<InvalidExpression>) {
+    match expression {
+        Expression::Atomic(_) | Expression::SIZE(_) => {}
+        Expression::ALL(x) => x
+            .expressions
+            .iter()
+            .for_each(|e| collect_invalid_expressions(e, result)),
+        Expression::ANY(x) => x
+            .expressions
+            .iter()
+            .for_each(|e| collect_invalid_expressions(e, result)),
+        Expression::NOT(x) => collect_invalid_expressions(&x.expression, result),
+        Expression::IF(x) => collect_invalid_expressions(&x.expression, result),
+        Expression::DESC(x) => {
+            if let Err(e) = regex::Regex::new(&x.regex) {
+                result.push(InvalidExpression {
+                    expression: expression.to_string(),
+                    reason: e.to_string(),
+                });
+            }
+        }
+        Expression::VER(x) => {
+            if let Err(e) = semver::Version::parse(&x.version) {
+                result.push(InvalidExpression {
+                    expression: expression.to_string(),
+                    reason: e.to_string(),
+                });
+            }
         }
     }
 }
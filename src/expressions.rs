@@ -7,11 +7,32 @@ use std::fmt::Display;
 use semver::VersionReq;
 use serde::{Deserialize, Serialize};
 
-use crate::{wild_contains, wild_contains_data, PluginData};
+use crate::{wild_contains, wild_contains_data, ESupportedGame, PluginData};
+
+/// The context expressions are evaluated in
+#[derive(Clone, Debug)]
+pub struct EvalContext<'a> {
+    pub game: ESupportedGame,
+    pub game_version: Option<semver::Version>,
+    pub plugins: &'a [PluginData],
+}
+impl<'a> EvalContext<'a> {
+    pub fn new(
+        game: ESupportedGame,
+        game_version: Option<semver::Version>,
+        plugins: &'a [PluginData],
+    ) -> Self {
+        Self {
+            game,
+            game_version,
+            plugins,
+        }
+    }
+}
 
 // An expression may be evaluated against a load order
 pub trait TExpression {
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>>;
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>>;
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -23,6 +44,7 @@ pub enum Expression {
     DESC(DESC),
     SIZE(SIZE),
     VER(VER),
+    IF(IF),
 }
 
 // pass-through
@@ -36,19 +58,41 @@ impl Display for Expression {
             Expression::DESC(x) => x.fmt(f),
             Expression::SIZE(x) => x.fmt(f),
             Expression::VER(x) => x.fmt(f),
+            Expression::IF(x) => x.fmt(f),
         }
     }
 }
 impl TExpression for Expression {
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
+        match self {
+            Expression::Atomic(x) => x.eval(ctx),
+            Expression::ALL(x) => x.eval(ctx),
+            Expression::ANY(x) => x.eval(ctx),
+            Expression::NOT(x) => x.eval(ctx),
+            Expression::DESC(x) => x.eval(ctx),
+            Expression::SIZE(x) => x.eval(ctx),
+            Expression::VER(x) => x.eval(ctx),
+            Expression::IF(x) => x.eval(ctx),
+        }
+    }
+}
+
+impl Expression {
+    /// Pushes the plugin names of all atomics in this expression, recursively
+    pub fn collect_atomics(&self, out: &mut Vec<String>) {
         match self {
-            Expression::Atomic(x) => x.eval(items),
-            Expression::ALL(x) => x.eval(items),
-            Expression::ANY(x) => x.eval(items),
-            Expression::NOT(x) => x.eval(items),
-            Expression::DESC(x) => x.eval(items),
-            Expression::SIZE(x) => x.eval(items),
-            Expression::VER(x) => x.eval(items),
+            Expression::Atomic(x) => out.push(x.item.clone()),
+            Expression::ALL(x) => x.expressions.iter().for_each(|e| e.collect_atomics(out)),
+            Expression::ANY(x) => x.expressions.iter().for_each(|e| e.collect_atomics(out)),
+            Expression::NOT(x) => x.expression.collect_atomics(out),
+            Expression::DESC(x) => out.push(x.expression.item.clone()),
+            Expression::SIZE(x) => out.push(x.expression.item.clone()),
+            Expression::VER(x) => {
+                if x.expression.item != VER_GAME {
+                    out.push(x.expression.item.clone())
+                }
+            }
+            Expression::IF(x) => x.expression.collect_atomics(out),
         }
     }
 }
@@ -89,6 +133,11 @@ impl From<VER> for Expression {
         Expression::VER(val)
     }
 }
+impl From<IF> for Expression {
+    fn from(val: IF) -> Self {
+        Expression::IF(val)
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////
 // IMPLEMENTATIONS
@@ -111,9 +160,12 @@ impl Atomic {
 }
 impl TExpression for Atomic {
     /// atomics evaluate as true if the input list contains the item
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
         wild_contains(
-            &items.iter().map(|f| f.name.to_owned()).collect::<Vec<_>>(),
+            &ctx.plugins
+                .iter()
+                .map(|f| f.name.to_owned())
+                .collect::<Vec<_>>(),
             &self.item,
         )
     }
@@ -152,12 +204,12 @@ impl ALL {
 }
 impl TExpression for ALL {
     /// ALL evaluates as true if all expressions evaluate as true
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
         let mut result = true;
         let mut results: Vec<String> = vec![];
 
         for e in &self.expressions {
-            if let Some(plugins) = e.eval(items) {
+            if let Some(plugins) = e.eval(ctx) {
                 results.extend(plugins);
             } else {
                 // any failure can set it to false
@@ -203,12 +255,12 @@ impl ANY {
 }
 impl TExpression for ANY {
     // ANY evaluate as true if any expressions evaluates as true
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
         let mut result = false;
         let mut results: Vec<String> = vec![];
 
         for e in &self.expressions {
-            if let Some(plugins) = e.eval(items) {
+            if let Some(plugins) = e.eval(ctx) {
                 result = true;
                 results.extend(plugins);
             }
@@ -254,8 +306,8 @@ impl NOT {
 }
 impl TExpression for NOT {
     // NOT evaluates as true if the wrapped expression evaluates as true
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
-        if let Some(_plugins) = self.expression.eval(items) {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
+        if let Some(_plugins) = self.expression.eval(ctx) {
             None
         } else {
             // NOT and resolving names
@@ -298,14 +350,14 @@ impl DESC {
     }
 }
 impl TExpression for DESC {
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
         // check the version
-        if let Some(plugins) = wild_contains_data(items, &self.expression.item) {
+        if let Some(plugins) = wild_contains_data(ctx.plugins, &self.expression.item) {
             let mut results = vec![];
             for p in &plugins {
                 if let Some(description) = &p.description {
                     if let Ok(pattern) = regex::Regex::new(&self.regex) {
-                        let description = &description.to_lowercase();
+                        let description = &description.to_ascii_lowercase();
                         match self.is_negated {
                             true => {
                                 if !pattern.is_match(description) {
@@ -371,9 +423,9 @@ impl SIZE {
     }
 }
 impl TExpression for SIZE {
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
         // check the size
-        if let Some(plugins) = wild_contains_data(items, &self.expression.item) {
+        if let Some(plugins) = wild_contains_data(ctx.plugins, &self.expression.item) {
             let mut results = vec![];
             for p in &plugins {
                 if self.is_negated {
@@ -433,10 +485,14 @@ impl Display for EVerOperator {
     }
 }
 
+/// The name that makes a VER expression compare the game version
+pub const VER_GAME: &str = "<game>";
+
 /// The Ver predicate is a special predicate that first tries to match the version number string stored in the plugin header,
 /// and if that fails it tries to match the version number from the plugin filename.
 /// If a version number is found, it can be used in a comparison.
 /// Syntax: [VER operator version plugin.esp]
+/// [VER operator version <game>] compares the game version instead, it is false if the game version is unknown.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VER {
     pub expression: Atomic,
@@ -451,35 +507,37 @@ impl VER {
             version,
         }
     }
+
+    /// Checks if the version matches the rule version with the operator
+    fn matches(&self, version: &semver::Version) -> bool {
+        // we can unwrap here because we know the version is valid
+        let semversion = semver::Version::parse(&self.version).unwrap();
+        let req = match self.operator {
+            EVerOperator::Less => VersionReq::parse(format!("<{}", semversion).as_str()).unwrap(),
+            EVerOperator::Equal => VersionReq::parse(format!("={}", semversion).as_str()).unwrap(),
+            EVerOperator::Greater => {
+                VersionReq::parse(format!(">{}", semversion).as_str()).unwrap()
+            }
+        };
+        req.matches(version)
+    }
 }
 impl TExpression for VER {
-    fn eval(&self, items: &[PluginData]) -> Option<Vec<String>> {
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
+        // check the game version
+        if self.expression.item == VER_GAME {
+            return match &ctx.game_version {
+                Some(game_version) if self.matches(game_version) => Some(vec![self.to_string()]),
+                _ => None,
+            };
+        }
+
         // check the version
-        if let Some(plugins) = wild_contains_data(items, &self.expression.item) {
+        if let Some(plugins) = wild_contains_data(ctx.plugins, &self.expression.item) {
             let mut results = vec![];
             for p in &plugins {
                 if let Some(plugin_version) = &p.version {
-                    // we can unwrap here because we know the version is valid
-                    let semversion = semver::Version::parse(&self.version).unwrap();
-                    let matches = match self.operator {
-                        EVerOperator::Less => {
-                            let req =
-                                VersionReq::parse(format!("<{}", semversion).as_str()).unwrap();
-                            req.matches(plugin_version)
-                        }
-                        EVerOperator::Equal => {
-                            let req =
-                                VersionReq::parse(format!("={}", semversion).as_str()).unwrap();
-                            req.matches(plugin_version)
-                        }
-                        EVerOperator::Greater => {
-                            let req =
-                                VersionReq::parse(format!(">{}", semversion).as_str()).unwrap();
-                            req.matches(plugin_version)
-                        }
-                    };
-
-                    if matches {
+                    if self.matches(plugin_version) {
                         results.push(p.name.clone());
                     }
                 }
@@ -514,3 +572,52 @@ impl Display for VER {
         )
     }
 }
+
+////////////////////////////////////////////////////////////////////////
+// IF
+
+/// The IF expression
+/// IF evaluates as true if the rules are evaluated for the given game and the wrapped expression evaluates as true
+/// [IF openmw A.esp] or [IF morrowind A.esp]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IF {
+    pub game: ESupportedGame,
+    pub expression: Box<Expression>,
+}
+impl IF {
+    pub fn new(game: ESupportedGame, expression: Expression) -> Self {
+        Self {
+            game,
+            expression: Box::new(expression),
+        }
+    }
+}
+impl TExpression for IF {
+    // IF evaluates as true if the game matches and the wrapped expression evaluates as true
+    fn eval(&self, ctx: &EvalContext) -> Option<Vec<String>> {
+        if ctx.game == self.game {
+            self.expression.eval(ctx)
+        } else {
+            None
+        }
+    }
+}
+impl Clone for IF {
+    fn clone(&self) -> Self {
+        Self {
+            game: self.game,
+            expression: self.expression.clone(),
+        }
+    }
+}
+
+impl Display for IF {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[IF {} {}]",
+            format!("{:?}", self.game).to_lowercase(),
+            self.expression.clone()
+        )
+    }
+}
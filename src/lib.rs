@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::{self, BufRead, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, vec};
 
 use clap::ValueEnum;
@@ -13,6 +14,7 @@ pub use commands::*;
 pub mod expressions;
 pub mod parser;
 pub mod rules;
+pub mod settings;
 pub mod sorter;
 
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -52,21 +54,66 @@ pub enum ESupportedGame {
     Cyberpunk,
 }
 pub const PLOX_RULES_BASE: &str = "plox_base.txt";
+/// Plugins matching a line of this file in the rules dir are not evaluated or sorted
+pub const PLOX_IGNORE_FILE: &str = "plox_ignore.txt";
 
 ////////////////////////////////////////////////////////////////////////
-/// GAMES
+// GAMES
 ////////////////////////////////////////////////////////////////////////
 
-/// Detect game from current working directory
-pub fn detect_game() -> Option<ESupportedGame> {
-    if PathBuf::from("Morrowind.exe").exists() {
+/// How many parent directories of the current working dir are searched for a game
+pub const DETECT_GAME_MAX_DEPTH: usize = 3;
+
+/// Detects the game in the current working dir or one of its parents.
+/// Falls back to OpenMW if an openmw.cfg exists at the default config path.
+/// Returns the game and its root directory.
+pub fn detect_game() -> Option<(ESupportedGame, PathBuf)> {
+    let cwd = env::current_dir().ok()?;
+    detect_game_in(&cwd).or_else(|| detect_openmw_from_config(&cwd, &config_path()))
+}
+
+/// Detects OpenMW from the given openmw.cfg unless the dir is part of a Morrowind or Cyberpunk install
+fn detect_openmw_from_config(dir: &Path, config: &Path) -> Option<(ESupportedGame, PathBuf)> {
+    if dir
+        .ancestors()
+        .take(DETECT_GAME_MAX_DEPTH + 1)
+        .any(is_game_folder)
+    {
+        return None;
+    }
+    if !config.exists() {
+        return None;
+    }
+    info!("Found openmw.cfg at {}", config.display());
+    Some((ESupportedGame::Openmw, dir.to_path_buf()))
+}
+
+/// Checks a directory for signs of a Morrowind or Cyberpunk install without their executables
+fn is_game_folder(dir: &Path) -> bool {
+    dir.file_name().is_some_and(|name| name == "Data Files")
+        || dir.join("Morrowind.ini").exists()
+        || dir.join("Data Files").exists()
+        || dir.join("archive").join("pc").exists()
+}
+
+/// Detects the game in the given dir or one of its parents
+pub fn detect_game_in(dir: &Path) -> Option<(ESupportedGame, PathBuf)> {
+    for root in dir.ancestors().take(DETECT_GAME_MAX_DEPTH + 1) {
+        if let Some(game) = detect_game_at(root) {
+            return Some((game, root.to_path_buf()));
+        }
+    }
+    None
+}
+
+/// Checks a single directory for the game marker files
+fn detect_game_at(root: &Path) -> Option<ESupportedGame> {
+    if root.join("Morrowind.exe").exists() {
         Some(ESupportedGame::Morrowind)
-    } else if PathBuf::from("openmw.cfg").exists() {
+    } else if root.join("openmw.cfg").exists() {
         Some(ESupportedGame::Openmw)
-    } else if PathBuf::from("bin")
-        .join("x64")
-        .join("Cyberpunk2077")
-        .exists()
+    } else if root.join("bin").join("x64").join("Cyberpunk2077").exists()
+        || get_cp77_exe_path(root).exists()
     {
         Some(ESupportedGame::Cyberpunk)
     } else {
@@ -74,6 +121,55 @@ pub fn detect_game() -> Option<ESupportedGame> {
     }
 }
 
+/// Gets the path of the Cyberpunk 2077 exe in the game root
+pub fn get_cp77_exe_path(root: &Path) -> PathBuf {
+    root.join("bin").join("x64").join("Cyberpunk2077.exe")
+}
+
+/// Gets the version of the game installed in the root folder.
+/// For Cyberpunk the product version of bin/x64/Cyberpunk2077.exe is read, other games return None
+pub fn get_game_version(game: ESupportedGame, root: &Path) -> Option<Version> {
+    match game {
+        ESupportedGame::Cyberpunk => {
+            let exe_path = get_cp77_exe_path(root);
+            let bytes = fs::read(&exe_path).ok()?;
+            let version = read_product_version(&bytes)?;
+            match parse_game_version(&version) {
+                Ok(v) => {
+                    info!("Read game version {} from {}", v, exe_path.display());
+                    Some(v)
+                }
+                Err(e) => {
+                    warn!(
+                        "Invalid game version {} in {}: {}",
+                        version,
+                        exe_path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        }
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => None,
+    }
+}
+
+/// Reads the ProductVersion string from the version resource of an exe
+fn read_product_version(bytes: &[u8]) -> Option<String> {
+    // the key and value are utf-16 strings, the value is aligned to 4 bytes
+    let key = "ProductVersion\0"
+        .encode_utf16()
+        .flat_map(|c| c.to_le_bytes())
+        .collect::<Vec<_>>();
+    let start = bytes.windows(key.len()).position(|w| w == key.as_slice())?;
+    let mut chars = bytes[start + key.len()..]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .skip_while(|c| *c == 0);
+    let value = chars.by_ref().take_while(|c| *c != 0).collect::<Vec<_>>();
+    String::from_utf16(&value).ok().filter(|v| !v.is_empty())
+}
+
 /// flattens a list of ordered mod pairs into a list of mod names
 pub fn debug_get_mods_from_order_rules(order_rules: &[EOrderRule]) -> Vec<PluginData> {
     debug_get_mods_from_ordering(&get_ordering_from_order_rules(order_rules))
@@ -117,23 +213,169 @@ pub fn get_default_rules_dir(game: ESupportedGame) -> PathBuf {
     }
 }
 
-/// Download latest rules from the internet
-pub fn download_latest_rules(game: ESupportedGame, rules_dir: &PathBuf) {
+/// Gets the openmw.cfg that is used: the given config if it exists, else the default config path
+pub fn get_openmw_config_path(config: &Option<PathBuf>) -> PathBuf {
+    match config {
+        Some(path) if path.exists() => path.to_owned(),
+        _ => config_path(),
+    }
+}
+
+/// Logs the absolute game root, openmw.cfg and rules dir a command works with
+/// The openmw.cfg is only logged for commands that gather mods, i.e. have a game root
+pub fn log_resolved_paths(
+    game: ESupportedGame,
+    root: Option<&Path>,
+    config: &Option<PathBuf>,
+    rules_dir: Option<&Path>,
+) {
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Some(root) = root {
+        info!("Game root: {}", absolute(root).display());
+        if game == ESupportedGame::Openmw {
+            info!(
+                "Config: {}",
+                absolute(&get_openmw_config_path(config)).display()
+            );
+        }
+    }
+    if let Some(rules_dir) = rules_dir {
+        info!("Rules dir: {}", absolute(rules_dir).display());
+    }
+}
+
+/// Gets the version of the base rules file in the rules directory
+/// Reads the version comment at the top of the file, e.g. ";[Version 2017-15-10 11:11:11 (UTC)]"
+/// and falls back to the file modification date
+pub fn get_rules_version(game: ESupportedGame, rules_dir: &Path) -> Option<String> {
+    let path = match game {
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => rules_dir.join("mlox_base.txt"),
+        ESupportedGame::Cyberpunk => rules_dir.join("plox_base.txt"),
+    };
+
+    // version comment
+    let re = Regex::new(r"(?i)^[;\s]*\[version\s+(.+)\]").unwrap();
+    if let Ok(lines) = read_lines(&path) {
+        for line in lines.map_while(Result::ok).take(50) {
+            if let Some(caps) = re.captures(&line) {
+                return Some(caps[1].trim().to_owned());
+            }
+        }
+    }
+
+    // file date
+    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let secs = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs() as i64;
+    Some(format_unix_date(secs))
+}
+
+/// Formats a unix timestamp as a date (YYYY-MM-DD)
+fn format_unix_date(secs: i64) -> String {
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = secs.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Download latest rules from the internet, optionally from a custom repo base url
+pub fn download_latest_rules(game: ESupportedGame, rules_dir: &PathBuf, rules_url: Option<&str>) {
     match game {
-        ESupportedGame::Morrowind | ESupportedGame::Openmw => download_mlox_rules(rules_dir),
-        ESupportedGame::Cyberpunk => download_plox_rules(rules_dir),
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => {
+            download_mlox_rules(rules_dir, rules_url)
+        }
+        ESupportedGame::Cyberpunk => download_plox_rules(rules_dir, rules_url),
+    }
+}
+
+/// Timeout for connecting to and reading from the rules server
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(15);
+/// How often a download is attempted before the local file is used
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+/// Wait time before the first retry, each further retry waits one delay longer
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Sends an HTTP GET request to the URL and returns the response body, retries on failure
+fn download_with_retry(url: &str, retry_delay: Duration) -> Result<Vec<u8>, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(DOWNLOAD_TIMEOUT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?;
+
+    let mut attempt = 1;
+    loop {
+        match client
+            .get(url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+        {
+            Ok(body) => return Ok(body.to_vec()),
+            // e.g. a moved file, retrying won't help
+            Err(e) if e.status().is_some_and(|s| s.is_client_error()) => return Err(e.into()),
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                warn!(
+                    "Download of {} failed (attempt {}/{}): {}, retrying",
+                    url, attempt, DOWNLOAD_ATTEMPTS, e
+                );
+                std::thread::sleep(retry_delay * attempt);
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
 }
 
-fn download_file<P>(url: &str, output_path: &P) -> Result<(), Box<dyn Error>>
+/// The first line of the mlox rules files
+const MLOX_RULES_HEADER: &str = ";; -*- mode: emacs-lisp -*-";
+
+/// Checks that a downloaded file contains rules and not e.g. an html error page
+fn validate_rules(game: ESupportedGame, body: &[u8]) -> Result<(), String> {
+    if String::from_utf8_lossy(body)
+        .trim_start()
+        .starts_with(MLOX_RULES_HEADER)
+    {
+        return Ok(());
+    }
+    match parser::get_parser(game).parse_rules_from_reader(io::Cursor::new(body)) {
+        Ok(rules) if !rules.is_empty() => Ok(()),
+        _ => Err("the downloaded file contains no valid rules".into()),
+    }
+}
+
+fn download_file<P>(
+    game: ESupportedGame,
+    url: &str,
+    output_path: &P,
+    retry_delay: Duration,
+) -> Result<(), Box<dyn Error>>
 where
     P: AsRef<Path>,
 {
-    // Send an HTTP GET request to the URL
-    let response = reqwest::blocking::get(url)?;
-
     // get response body
-    let body = &mut response.bytes().unwrap();
+    let body = download_with_retry(url, retry_delay)?;
+    store_rules_file(game, &body, output_path)
+}
+
+/// Writes downloaded rules and their hash to disk, the local file is kept if the rules are not valid
+fn store_rules_file<P>(
+    game: ESupportedGame,
+    body: &[u8],
+    output_path: &P,
+) -> Result<(), Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    validate_rules(game, body)?;
 
     // hash check
     let hash_path = output_path.as_ref().with_extension("hash");
@@ -157,7 +399,7 @@ where
     // Create a file and write
 
     let mut file = File::create(output_path)?;
-    io::copy(&mut body.as_ref(), &mut file)?;
+    file.write_all(body)?;
     info!(
         "File downloaded successfully: {}",
         output_path.as_ref().display()
@@ -170,18 +412,130 @@ where
     Ok(())
 }
 
-fn download_mlox_rules(rules_dir: &PathBuf) {
+const PLOX_LATEST_RELEASE_URL: &str = "https://api.github.com/repos/rfuzzo/plox/releases/latest";
+
+/// Queries the github releases api for the latest plox version
+///
+/// # Errors
+///
+/// This function will return an error if the request fails or the release tag is not a version
+pub fn get_latest_plox_version() -> Result<Version, Box<dyn Error>> {
+    // the github api rejects requests without a user agent
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(format!("plox/{}", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let release: serde_json::Value = client
+        .get(PLOX_LATEST_RELEASE_URL)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or("No tag in latest release")?;
+    parse_release_tag(tag).ok_or_else(|| format!("Not a version: {}", tag).into())
+}
+
+/// Parses a release tag like "v0.4.1" or "0.4.1"
+pub fn parse_release_tag(tag: &str) -> Option<Version> {
+    let version = tag.trim().trim_start_matches(['v', 'V']);
+    lenient_semver::parse(version).ok()
+}
+
+/// Checks if a newer plox version is released and logs the result.
+/// Nothing is downloaded.
+pub fn check_for_update() {
+    let Ok(current) = Version::parse(env!("CARGO_PKG_VERSION")) else {
+        return;
+    };
+    match get_latest_plox_version() {
+        Ok(latest) => {
+            if latest > current {
+                info!(
+                    "A new plox version is available: {} (current: {}). Get it at https://github.com/rfuzzo/plox/releases",
+                    latest, current
+                );
+            } else {
+                info!("plox is up to date ({})", current);
+            }
+        }
+        Err(e) => warn!("Could not check for a new plox version: {}", e),
+    }
+}
+
+fn download_mlox_rules(rules_dir: &PathBuf, rules_url: Option<&str>) {
+    let repo = rules_url.unwrap_or("https://github.com/DanaePlays/mlox-rules/raw/main/");
+    download_rules(
+        ESupportedGame::Morrowind,
+        rules_dir,
+        repo,
+        &["mlox_base.txt", "mlox_user.txt"],
+        DOWNLOAD_RETRY_DELAY,
+    );
+}
+
+fn download_plox_rules(rules_dir: &PathBuf, rules_url: Option<&str>) {
+    if let Some(repo) = rules_url {
+        download_rules(
+            ESupportedGame::Cyberpunk,
+            rules_dir,
+            repo,
+            &["plox_base.txt"],
+            DOWNLOAD_RETRY_DELAY,
+        );
+    } else {
+        // TODO CP77 download plox rules
+    }
+}
+
+/// Warns that the local rules file is used instead of the latest version
+fn warn_stale_rules(path: &Path) {
+    if !path.exists() {
+        warn!(
+            "!!! Could not download {} and no local rules found !!!",
+            path.display()
+        );
+        return;
+    }
+
+    let hash = fs::read_to_string(path.with_extension("hash"))
+        .map(|h| h.trim().to_owned())
+        .unwrap_or("unknown".to_owned());
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .map(|d| format!("{} days", d.as_secs() / 86400))
+        .unwrap_or("unknown".to_owned());
+
+    warn!(
+        "!!! Could not download the latest rules, using local rules that may be outdated: {} (age: {}, hash: {}) !!!",
+        path.display(),
+        age,
+        hash
+    );
+}
+
+/// Downloads the rules files from a repo base url into the rules directory
+fn download_rules(
+    game: ESupportedGame,
+    rules_dir: &PathBuf,
+    repo: &str,
+    files: &[&str],
+    retry_delay: Duration,
+) {
     match fs::create_dir_all(rules_dir) {
         Ok(_) => {
             // download
-            let repo = "https://github.com/DanaePlays/mlox-rules/raw/main/";
-            let files = ["mlox_base.txt", "mlox_user.txt"];
+            let repo = repo.trim_end_matches('/');
             for file in files {
                 let output_path = rules_dir.join(file); // Specify the output path here
-                let url = repo.to_owned() + file;
-                match download_file(&url, &output_path) {
+                let url = format!("{}/{}", repo, file);
+                match download_file(game, &url, &output_path, retry_delay) {
                     Ok(()) => {}
-                    Err(err) => error!("Error downloading file: {}", err),
+                    Err(err) => {
+                        error!("Error downloading file: {}", err);
+                        warn_stale_rules(&output_path);
+                    }
                 }
             }
         }
@@ -195,21 +549,6 @@ fn download_mlox_rules(rules_dir: &PathBuf) {
     }
 }
 
-fn download_plox_rules(rules_dir: &PathBuf) {
-    match fs::create_dir_all(rules_dir) {
-        Ok(_) => {
-            // TODO CP77 download plox rules
-        }
-        Err(e) => {
-            error!(
-                "Could not create rules directory at {}: {}",
-                rules_dir.display(),
-                e
-            );
-        }
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct PluginData {
     pub name: String,
@@ -218,6 +557,8 @@ pub struct PluginData {
     pub description: Option<String>,
     pub version: Option<semver::Version>,
     pub masters: Option<Vec<(String, u64)>>,
+    /// set if the plugin header could not be read
+    pub header_error: Option<String>,
 }
 
 impl PluginData {
@@ -228,6 +569,7 @@ impl PluginData {
             description: None,
             version: None,
             masters: None,
+            header_error: None,
         }
     }
 }
@@ -301,6 +643,25 @@ where
     plugins
 }
 
+/// Gets the names of all plugins in Data Files that were last modified before the unix timestamp
+pub fn get_tes3_plugins_modified_before(
+    root: &Path,
+    plugins: &[PluginData],
+    since: u64,
+) -> Vec<String> {
+    plugins
+        .iter()
+        .filter(|p| {
+            fs::metadata(root.join("Data Files").join(&p.name))
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .is_some_and(|d| d.as_secs() < since)
+        })
+        .map(|p| normalize_name(&p.name))
+        .collect()
+}
+
 pub fn gather_tes3_mods<P>(path: &P) -> Vec<PluginData>
 where
     P: AsRef<Path>,
@@ -309,7 +670,7 @@ where
     let names = files.iter().filter_map(|f| map_data(f)).collect::<Vec<_>>();
 
     // check against mw ini
-    let morrowind_ini_path = PathBuf::from("Morrowind.ini");
+    let morrowind_ini_path = path.as_ref().join("Morrowind.ini");
     if morrowind_ini_path.exists() {
         // parse ini
         if let Ok(ini) = Ini::load_from_file(morrowind_ini_path) {
@@ -339,6 +700,21 @@ where
 }
 
 pub fn gather_openmw_mods<P>(config: &Option<P>) -> Vec<PluginData>
+where
+    P: AsRef<Path>,
+{
+    gather_openmw_entries(config, "content")
+}
+
+/// Gathers the groundcover plugins of an openmw.cfg
+pub fn gather_openmw_groundcover<P>(config: &Option<P>) -> Vec<PluginData>
+where
+    P: AsRef<Path>,
+{
+    gather_openmw_entries(config, "groundcover")
+}
+
+fn gather_openmw_entries<P>(config: &Option<P>, key: &str) -> Vec<PluginData>
 where
     P: AsRef<Path>,
 {
@@ -353,8 +729,9 @@ where
     }
 
     if let Ok(cfg) = openmw_cfg::Ini::load_from_file_noescape(path) {
-        if let Ok(files) = openmw_cfg::get_plugins(&cfg) {
-            let names = files.iter().filter_map(|f| map_data(f)).collect::<Vec<_>>();
+        if let Ok(files) = get_openmw_paths(&cfg, key) {
+            let mut names = files.iter().filter_map(|f| map_data(f)).collect::<Vec<_>>();
+            remove_duplicate_plugins(&mut names);
             return names;
         }
     } else {
@@ -364,6 +741,78 @@ where
     vec![]
 }
 
+/// Resolves the content files of an openmw.cfg to paths on disk.
+/// If a plugin exists in more than one data directory the last one wins, like in OpenMW.
+///
+/// # Errors
+///
+/// This function will return an error if the data directories can't be read
+pub fn get_openmw_plugin_paths(cfg: &openmw_cfg::Ini) -> Result<Vec<PathBuf>, openmw_cfg::Error> {
+    get_openmw_paths(cfg, "content")
+}
+
+/// Resolves the groundcover files of an openmw.cfg to paths on disk
+///
+/// # Errors
+///
+/// This function will return an error if the data directories can't be read
+pub fn get_openmw_groundcover_paths(
+    cfg: &openmw_cfg::Ini,
+) -> Result<Vec<PathBuf>, openmw_cfg::Error> {
+    get_openmw_paths(cfg, "groundcover")
+}
+
+fn get_openmw_paths(cfg: &openmw_cfg::Ini, key: &str) -> Result<Vec<PathBuf>, openmw_cfg::Error> {
+    let data_dirs = openmw_cfg::get_data_dirs(cfg)?;
+    let Some(section) = cfg.section::<String>(None) else {
+        return Ok(vec![]);
+    };
+
+    let mut result = vec![];
+    for name in section.get_all(key) {
+        let paths = find_openmw_plugin(&data_dirs, name);
+        if paths.len() > 1 {
+            warn!(
+                "{} exists in more than one data directory: {}, using {}",
+                name,
+                paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                paths.last().unwrap().display()
+            );
+        }
+        if let Some(path) = paths.last() {
+            result.push(path.to_owned());
+        } else {
+            warn!("Could not find {} in any data directory", name);
+        }
+    }
+    Ok(result)
+}
+
+/// Returns all paths of a plugin in the given data directories, in load order
+pub fn find_openmw_plugin(data_dirs: &[String], name: &str) -> Vec<PathBuf> {
+    data_dirs
+        .iter()
+        .filter_map(|dir| find_file_ci(Path::new(dir.trim_matches('"')), name))
+        .collect()
+}
+
+/// Finds a file in a directory, ignoring the casing of the file name
+fn find_file_ci(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if path.is_file() {
+        return Some(path);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .map_while(Result::ok)
+        .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+        .map(|e| e.path())
+}
+
 fn map_data(f: &Path) -> Option<PluginData> {
     if let Some(file_name) = f.file_name().and_then(|n| n.to_str()) {
         let mut data = PluginData {
@@ -372,20 +821,31 @@ fn map_data(f: &Path) -> Option<PluginData> {
             description: None,
             version: None,
             masters: None,
+            header_error: None,
         };
 
-        match parse_header(f) {
-            Ok(header) => {
-                data.description = Some(header.description);
-                data.masters = header.masters;
-            }
-            Err(e) => {
-                log::debug!("Error parsing header: {}, {}", e, f.display());
-            }
-        };
+        // omwscripts don't have a header
+        let is_script = f
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("omwscripts"));
+
+        let mut header_version = None;
+        if !is_script {
+            match parse_header(f) {
+                Ok(header) => {
+                    data.description = Some(header.description);
+                    data.masters = header.masters;
+                    header_version = Some(header.version);
+                }
+                Err(e) => {
+                    warn!("Error parsing header: {}, {}", e, f.display());
+                    data.header_error = Some(e.to_string());
+                }
+            };
+        }
 
         // parse semver
-        if let Some(version) = get_version(file_name, &data.description) {
+        if let Some(version) = get_version(file_name, &data.description, header_version) {
             data.version = Some(version);
         }
 
@@ -396,8 +856,13 @@ fn map_data(f: &Path) -> Option<PluginData> {
 
 const VERSION_REGEX: &str = r"(\d+(?:[_.-]?\d+)*[a-zA-Z]?)";
 
-/// Get version from filename or description
-pub fn get_version(file_name: &str, description: &Option<String>) -> Option<Version> {
+/// Get version from description, filename or header version, in that order.
+/// The header version is only used if it isn't one of the TES3 file format versions 1.2 and 1.3
+pub fn get_version(
+    file_name: &str,
+    description: &Option<String>,
+    header_version: Option<f32>,
+) -> Option<Version> {
     let mut final_version_str = None;
 
     // try to get version from description
@@ -420,7 +885,25 @@ pub fn get_version(file_name: &str, description: &Option<String>) -> Option<Vers
         }
     }
 
-    None
+    // last resort: the numeric version of the header
+    header_version
+        .filter(|v| is_plugin_header_version(*v))
+        .and_then(|v| get_semver(&v.to_string()))
+}
+
+/// Checks if a header version was set by the plugin author. Almost all plugins have the file format version
+fn is_plugin_header_version(version: f32) -> bool {
+    const FORMAT_VERSIONS: [f32; 2] = [1.2, 1.3];
+    version.is_finite()
+        && version > 0.0
+        && !FORMAT_VERSIONS
+            .iter()
+            .any(|v| (version - v).abs() < f32::EPSILON)
+}
+
+/// Parses a game version given on the command line, e.g. "1.6.1820" or "2.1"
+pub fn parse_game_version(version: &str) -> Result<Version, String> {
+    lenient_semver::parse(version.trim()).map_err(|e| e.to_string())
 }
 
 /// Get semver from string
@@ -470,6 +953,14 @@ fn match_desc_version(desc: &str) -> Option<String> {
     None
 }
 
+/// Gathers the archives in archive/pc/mod and the REDmods in mods/ in their load order.
+///
+/// Entries are ordered by the first of these files that lists them:
+/// 1. mods/loadOrder.txt, the REDmod load order, one mod folder per line
+/// 2. r6/cache/modded/mods.json, the order of the last REDmod deployment
+/// 3. archive/pc/mod/modlist.txt, the archive load order
+///
+/// All other archives and then all other REDmods load after them in alphabetical order, like in the game.
 pub fn gather_cp77_mods<P>(root: &P) -> Vec<PluginData>
 where
     P: AsRef<Path>,
@@ -477,87 +968,438 @@ where
     // gather mods from archive/pc/mod
     let archive_path = root.as_ref().join("archive").join("pc").join("mod");
 
+    let mut entries = vec![];
     if let Ok(plugins) = fs::read_dir(archive_path) {
-        let mut entries = plugins
+        entries = plugins
             .map(|res| res.map(|e| e.path()))
             .filter_map(Result::ok)
             .filter_map(|e| {
                 if !e.is_dir() {
-                    if let Some(os_ext) = e.extension() {
-                        if let Some(ext) = os_ext.to_ascii_lowercase().to_str() {
-                            if ext.contains("archive") {
-                                if let Some(file_name) = e.file_name().and_then(|n| n.to_str()) {
-                                    let data = PluginData {
-                                        name: file_name.to_owned(),
-                                        size: e.metadata().unwrap().len(),
-                                        description: None,
-                                        version: None,
-                                        masters: None,
-                                    };
-                                    return Some(data);
-                                }
-                            }
+                    if let Some(file_name) = e.file_name().and_then(|n| n.to_str()) {
+                        if is_cp77_archive(file_name) {
+                            let data = PluginData {
+                                name: file_name.to_owned(),
+                                size: e.metadata().unwrap().len(),
+                                description: None,
+                                version: None,
+                                masters: None,
+                                header_error: None,
+                            };
+                            return Some(data);
                         }
                     }
                 }
                 None
             })
             .collect::<Vec<_>>();
-
-        // TODO CP77 support modlist
-
-        // TODO CP77 gather REDmods from mods/<NAME>
         entries.sort_by_key(|e| e.name.clone());
-        return entries;
     }
 
-    vec![]
+    // gather REDmods from mods/<NAME>
+    let mut redmods = gather_cp77_redmods(root.as_ref());
+    redmods.sort_by_key(|e| e.name.clone());
+    entries.extend(redmods);
+
+    let load_order_path = root.as_ref().join("mods").join("loadOrder.txt");
+    let deployed_path = root
+        .as_ref()
+        .join("r6")
+        .join("cache")
+        .join("modded")
+        .join("mods.json");
+    let modlist_path = root
+        .as_ref()
+        .join("archive")
+        .join("pc")
+        .join("mod")
+        .join("modlist.txt");
+
+    let mut order: Vec<PluginData> = vec![];
+    for path in [load_order_path, deployed_path, modlist_path] {
+        if !path.exists() {
+            continue;
+        }
+        info!("Reading load order from {}", path.display());
+        let list = if path.extension().is_some_and(|e| e == "json") {
+            read_cp77_deployed_mods(&path)
+        } else {
+            read_cp77_modlist(&path)
+        };
+        // earlier files take precedence
+        for entry in list {
+            if !order
+                .iter()
+                .any(|o| o.name.eq_ignore_ascii_case(&entry.name))
+            {
+                order.push(entry);
+            }
+        }
+    }
+
+    order_by_modlist(entries, &order)
+}
+
+/// Archives have the extension .archive, the check is case-insensitive
+fn is_cp77_archive(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.to_ascii_lowercase().contains("archive"))
+}
+
+/// Gathers the REDmods in mods/, each folder with an info.json is a mod.
+/// The size of a REDmod is the size of all its files
+fn gather_cp77_redmods(root: &Path) -> Vec<PluginData> {
+    let Ok(dirs) = fs::read_dir(root.join("mods")) else {
+        return vec![];
+    };
+    dirs.filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && p.join("info.json").exists())
+        .filter_map(|p| {
+            let name = p.file_name()?.to_str()?.to_owned();
+            Some(PluginData::new(name, get_dir_size(&p)))
+        })
+        .collect()
+}
+
+/// Gets the size of all files in a folder and its subfolders
+fn get_dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .map(|p| {
+            if p.is_dir() {
+                get_dir_size(&p)
+            } else {
+                p.metadata().map(|m| m.len()).unwrap_or(0)
+            }
+        })
+        .sum()
 }
 
-/// Update on disk
+/// Reads the REDmod folders in the order of the last deployment from r6/cache/modded/mods.json.
+/// Mods that are not enabled are skipped
+fn read_cp77_deployed_mods(path: &Path) -> Vec<PluginData> {
+    let json = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).map_err(|e| e.to_string()))
+    {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Could not read {}: {}", path.display(), e);
+            return vec![];
+        }
+    };
+    json["mods"]
+        .as_array()
+        .map(|mods| {
+            mods.iter()
+                .filter(|m| m["enabled"].as_bool().unwrap_or(true))
+                .filter_map(|m| m["folder"].as_str().or_else(|| m["name"].as_str()))
+                .map(|name| PluginData::new(name.to_owned(), 0))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Blank lines and lines starting with # or ; in the Cyberpunk modlist.txt are comments
+fn is_modlist_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#') || line.starts_with(';')
+}
+
+/// Reads the entries of the Cyberpunk modlist.txt or loadOrder.txt, skipping comments
+fn read_cp77_modlist<P>(modlist_path: P) -> Vec<PluginData>
+where
+    P: AsRef<Path>,
+{
+    read_file_as_list(modlist_path)
+        .into_iter()
+        .filter(|p| !is_modlist_comment(&p.name))
+        .map(|mut p| {
+            p.name = p.name.trim().to_owned();
+            p
+        })
+        .collect()
+}
+
+/// Moves the plugins listed in the mod list to the front, in the order of the mod list.
+/// Plugins not in the mod list keep their order after them, entries not found are ignored.
+fn order_by_modlist(mut plugins: Vec<PluginData>, modlist: &[PluginData]) -> Vec<PluginData> {
+    let mut result = vec![];
+    for entry in modlist {
+        if let Some(i) = plugins
+            .iter()
+            .position(|p| p.name.eq_ignore_ascii_case(&entry.name))
+        {
+            result.push(plugins.remove(i));
+        } else {
+            warn!("{} is in the modlist but could not be found", entry.name);
+        }
+    }
+    result.extend(plugins);
+    result
+}
+
+/// Update on disk. With no_redate only the Morrowind.ini is updated and the plugin file times are kept.
+/// The load order file is backed up first and the change is recorded next to the backup so it can be undone
 pub fn update_new_load_order<P: AsRef<Path>>(
     game: ESupportedGame,
+    root: &Path,
     result: &[String],
     config: Option<P>,
+    no_redate: bool,
 ) -> std::io::Result<()> {
+    let mut applied = get_applied_order(game, root, result, config.as_ref(), no_redate);
+    backup_file(&applied.path)?;
+
     match game {
-        ESupportedGame::Morrowind => update_tes3(PathBuf::from("Morrowind.ini"), result, false),
-        ESupportedGame::Openmw => update_openmw(result, config),
-        ESupportedGame::Cyberpunk => update_cp77(result),
+        ESupportedGame::Morrowind => {
+            update_tes3(root, result, no_redate, RedateOptions::default())?
+        }
+        ESupportedGame::Openmw => update_openmw(result, config)?,
+        ESupportedGame::Cyberpunk => update_cp77(root, result)?,
     }
+
+    // undo must find the file from any working dir
+    applied.path = get_absolute_path(&applied.path);
+    let json = serde_json::to_string_pretty(&applied)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(get_undo_path(&applied.path), json)
 }
 
-fn update_cp77(_result: &[String]) -> std::io::Result<()> {
-    // TODO CP77 update mods
-    panic!("Not implemented")
+/// The last load order that was written to disk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedOrder {
+    pub game: ESupportedGame,
+    /// the load order file that was overwritten, its backup is next to it
+    pub path: PathBuf,
+    /// the load order file did not exist before and has no backup, undo removes it
+    #[serde(default)]
+    pub created: bool,
+    /// (Morrowind only) the plugin file times before redating
+    #[serde(default)]
+    pub file_times: Vec<(String, i64)>,
 }
 
-fn update_openmw<P: AsRef<Path>>(result: &[String], config: Option<P>) -> std::io::Result<()> {
-    // in openMW we just update the cfg with the new order
-    let mut path = config_path();
-    if let Some(config_path) = config {
-        if config_path.as_ref().exists() {
-            path = config_path.as_ref().to_path_buf();
-        } else {
-            error!("openmw.cfg not found at {}", config_path.as_ref().display());
+fn get_applied_order<P: AsRef<Path>>(
+    game: ESupportedGame,
+    root: &Path,
+    result: &[String],
+    config: Option<P>,
+    no_redate: bool,
+) -> AppliedOrder {
+    let path = get_load_order_path(game, root, config);
+
+    let mut file_times = vec![];
+    if game == ESupportedGame::Morrowind && !no_redate {
+        for name in result {
+            if let Ok(metadata) = fs::metadata(root.join("Data Files").join(name)) {
+                let time = filetime::FileTime::from_last_modification_time(&metadata);
+                file_times.push((name.to_owned(), time.unix_seconds()));
+            }
+        }
+    }
+
+    AppliedOrder {
+        game,
+        created: !path.exists(),
+        path,
+        file_times,
+    }
+}
+
+/// Gets the file the load order of the game is written to
+pub fn get_load_order_path<P: AsRef<Path>>(
+    game: ESupportedGame,
+    root: &Path,
+    config: Option<P>,
+) -> PathBuf {
+    match game {
+        ESupportedGame::Morrowind => root.join("Morrowind.ini"),
+        ESupportedGame::Openmw => get_openmw_update_path(config),
+        ESupportedGame::Cyberpunk => root
+            .join("archive")
+            .join("pc")
+            .join("mod")
+            .join("modlist.txt"),
+    }
+}
+
+/// Resolves the parent folder of a path, the file itself may be a link
+fn get_absolute_path(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|p| p.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Gets the path of the backup plox writes before overwriting a load order file
+pub fn get_backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".plox.bak");
+    PathBuf::from(name)
+}
+
+/// Gets the path of the record of the last applied load order, next to the backup of the load order file
+pub fn get_undo_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".plox.undo.json");
+    PathBuf::from(name)
+}
+
+fn backup_file(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        fs::copy(path, get_backup_path(path))?;
+        info!("Backed up {}", path.display());
+    }
+    Ok(())
+}
+
+/// Restores the load order file and plugin file times from before the last applied load order.
+/// Each load order file (e.g. every openmw.cfg) is undone on its own
+///
+/// # Errors
+///
+/// This function will return an error if no load order was applied or its backup is missing
+pub fn undo_load_order<P: AsRef<Path>>(
+    game: ESupportedGame,
+    root: &Path,
+    config: Option<P>,
+) -> std::io::Result<AppliedOrder> {
+    let undo_path = get_undo_path(&get_load_order_path(game, root, config));
+    let applied: AppliedOrder = serde_json::from_str(&fs::read_to_string(&undo_path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    if applied.created {
+        if applied.path.exists() {
+            fs::remove_file(&applied.path)?;
+        }
+    } else {
+        let backup = get_backup_path(&applied.path);
+        if !backup.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No backup found at {}", backup.display()),
+            ));
+        }
+        fs::copy(&backup, &applied.path)?;
+    }
+
+    for (name, time) in &applied.file_times {
+        let path = root.join("Data Files").join(name);
+        if path.exists() {
+            set_file_mtime(path, filetime::FileTime::from_unix_time(*time, 0))?;
+        }
+    }
+
+    // an order can only be undone once
+    fs::remove_file(undo_path)?;
+    Ok(applied)
+}
+
+/// Writes the archive order to the modlist.txt, comments keep their position
+fn update_cp77(root: &Path, result: &[String]) -> std::io::Result<()> {
+    // TODO CP77 update REDmods
+    let path = root
+        .join("archive")
+        .join("pc")
+        .join("mod")
+        .join("modlist.txt");
+
+    // REDmods are not part of the modlist.txt
+    let result = result
+        .iter()
+        .filter(|r| is_cp77_archive(r))
+        .collect::<Vec<_>>();
+
+    let mut buf = Vec::new();
+    let mut written = false;
+    if path.exists() {
+        for line in read_lines(&path)?.map_while(Result::ok) {
+            if is_modlist_comment(&line) {
+                writeln!(buf, "{}", line)?;
+                continue;
+            }
+            // write the new order in place of the first archive line
+            if !written {
+                for r in result {
+                    writeln!(buf, "{}", r)?;
+                }
+                written = true;
+            }
         }
     }
 
+    // add filenames
+    if !written {
+        for r in result {
+            writeln!(buf, "{}", r)?;
+        }
+    }
+
+    // save
+    let mut file = File::create(path)?;
+    file.write_all(&buf)?;
+
+    Ok(())
+}
+
+fn update_openmw<P: AsRef<Path>>(result: &[String], config: Option<P>) -> std::io::Result<()> {
+    update_openmw_entries(result, config, "content")
+}
+
+/// Writes the groundcover order to the openmw.cfg, all other lines keep their position
+///
+/// # Errors
+///
+/// This function will return an error if the openmw.cfg can't be written
+pub fn update_openmw_groundcover<P: AsRef<Path>>(
+    result: &[String],
+    config: Option<P>,
+) -> std::io::Result<()> {
+    update_openmw_entries(result, config, "groundcover")
+}
+
+fn update_openmw_entries<P: AsRef<Path>>(
+    result: &[String],
+    config: Option<P>,
+    key: &str,
+) -> std::io::Result<()> {
+    // in openMW we just update the cfg with the new order
+    let path = get_openmw_update_path(config);
     if let Ok(_cfg) = openmw_cfg::Ini::load_from_file_noescape(&path) {
         // parse ini
+        // only lines of the given key are reordered, all other lines (e.g. groundcover) keep their position
+        let prefix = format!("{}=", key);
         let mut buf = Vec::new();
+        let mut written = false;
         for line in read_lines(&path)?.map_while(Result::ok) {
-            // skip plugin lines
-
-            if line.starts_with("content=") {
+            if is_openmw_key(&line, key) {
+                // write the new order in place of the first plugin line
+                if !written {
+                    for r in result {
+                        writeln!(buf, "{}{}", prefix, r)?;
+                    }
+                    written = true;
+                }
                 continue;
             }
             writeln!(buf, "{}", line)?;
         }
 
         // add filenames
-        for r in result {
-            writeln!(buf, "content={}", r)?;
+        if !written {
+            for r in result {
+                writeln!(buf, "{}{}", prefix, r)?;
+            }
         }
 
         // save
@@ -570,15 +1412,32 @@ fn update_openmw<P: AsRef<Path>>(result: &[String], config: Option<P>) -> std::i
     Ok(())
 }
 
+/// Gets the openmw.cfg to update, the default config is used if the given one doesn't exist
+fn get_openmw_update_path<P: AsRef<Path>>(config: Option<P>) -> PathBuf {
+    if let Some(config_path) = config {
+        if config_path.as_ref().exists() {
+            return config_path.as_ref().to_path_buf();
+        }
+        error!("openmw.cfg not found at {}", config_path.as_ref().display());
+    }
+    config_path()
+}
+
+/// Checks if an openmw.cfg line sets the given key, whitespace around the key is allowed
+fn is_openmw_key(line: &str, key: &str) -> bool {
+    line.split_once('=').is_some_and(|(k, _)| k.trim() == key)
+}
+
 pub fn update_tes3<P: AsRef<Path>>(
-    morrowind_ini_path: P,
+    root: P,
     result: &[String],
     no_redate: bool,
+    redate_options: RedateOptions,
 ) -> std::io::Result<()> {
     // in tes3 we first update the ini with the new order (this is technically not important but we might as well)
     // check against mw ini
-
-    if morrowind_ini_path.as_ref().exists() {
+    let morrowind_ini_path = root.as_ref().join("Morrowind.ini");
+    if morrowind_ini_path.exists() {
         // parse ini
         let mut buf = Vec::new();
         for line in read_lines(&morrowind_ini_path)?.map_while(Result::ok) {
@@ -609,9 +1468,9 @@ pub fn update_tes3<P: AsRef<Path>>(
         // redate files
         let files = result
             .iter()
-            .map(|f| PathBuf::from("Data Files").join(f))
+            .map(|f| root.as_ref().join("Data Files").join(f))
             .collect::<Vec<_>>();
-        redate_mods(&files)?;
+        redate_mods(&files, redate_options)?;
     }
 
     Ok(())
@@ -619,17 +1478,25 @@ pub fn update_tes3<P: AsRef<Path>>(
 
 /// Checks if the list of mods is in the correct order
 pub fn check_order(result: &[String], order_rules: &[EOrderRule]) -> bool {
-    let order = get_ordering_from_order_rules(order_rules);
-    let pairs = order;
-    for (a, b) in pairs {
+    check_order_detailed(result, order_rules).is_empty()
+}
+
+/// Gets all pairs of plugins that violate the order rules: the first plugin should load before the second one but doesn't
+pub fn check_order_detailed(
+    result: &[String],
+    order_rules: &[EOrderRule],
+) -> Vec<(String, String)> {
+    let mut violations = vec![];
+    for (a, b) in get_ordering_from_order_rules(order_rules) {
         if let Some(results_for_a) = wild_contains(result, &a) {
             if let Some(results_for_b) = wild_contains(result, &b) {
                 for i in &results_for_a {
                     for j in &results_for_b {
                         let pos_a = result.iter().position(|x| x == i).unwrap();
                         let pos_b = result.iter().position(|x| x == j).unwrap();
-                        if pos_a > pos_b {
-                            return false;
+                        let pair = (i.to_owned(), j.to_owned());
+                        if pos_a > pos_b && !violations.contains(&pair) {
+                            violations.push(pair);
                         }
                     }
                 }
@@ -637,7 +1504,391 @@ pub fn check_order(result: &[String], order_rules: &[EOrderRule]) -> bool {
         }
     }
 
-    true
+    violations
+}
+
+/// A plugin move: the plugin is removed at `from` and inserted at `to` of the remaining order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub plugin: String,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Gets a small list of plugin moves that make check_order pass, to disturb the order as little as possible.
+/// The moves must be applied in sequence.
+///
+/// The plugin that takes part in the most violated pairs is moved first, to the position closest to its old one
+/// that satisfies all of its rules. With cyclic rules the moves don't fix the order
+pub fn minimal_fix(order: &[String], order_rules: &[EOrderRule]) -> Vec<Move> {
+    let ordering = get_ordering_from_order_rules(order_rules);
+    let mut order = order.to_vec();
+    let mut moves = vec![];
+
+    // cyclic rules can't be fixed, stop eventually
+    let max_moves = order.len() * order.len();
+    while moves.len() < max_moves {
+        let violations = check_order_detailed(&order, order_rules);
+        if violations.is_empty() {
+            break;
+        }
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (a, b) in violations {
+            *counts.entry(a).or_default() += 1;
+            *counts.entry(b).or_default() += 1;
+        }
+        // most violations first, ties move the plugin that loads last
+        let mut candidates = (0..order.len())
+            .filter(|i| counts.contains_key(&order[*i]))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|i| std::cmp::Reverse((counts[&order[*i]], *i)));
+
+        // move the first plugin that can be placed without violating any of its rules
+        let mut fix = None;
+        for from in &candidates {
+            let mut rest = order.clone();
+            let plugin = rest.remove(*from);
+            let (lo, hi) = get_fix_range(&rest, &plugin, &ordering);
+            if lo <= hi {
+                fix = Some((*from, (*from).clamp(lo, hi)));
+                break;
+            }
+        }
+        // otherwise the other plugins have to move too, load it after all plugins it must load after
+        let (from, to) = fix.unwrap_or_else(|| {
+            let from = candidates[0];
+            let mut rest = order.clone();
+            let plugin = rest.remove(from);
+            (from, get_fix_range(&rest, &plugin, &ordering).0)
+        });
+
+        let plugin = order.remove(from);
+        order.insert(to, plugin.clone());
+        moves.push(Move { plugin, from, to });
+    }
+
+    moves
+}
+
+/// Gets the first and last index the plugin can be inserted at without violating one of its rules.
+/// If the first index is larger than the last one, there is no such index
+fn get_fix_range(order: &[String], plugin: &str, ordering: &[(String, String)]) -> (usize, usize) {
+    let plugin = [plugin.to_owned()];
+    let positions = |names: Vec<String>| {
+        names
+            .into_iter()
+            .filter_map(|n| order.iter().position(|x| *x == n))
+            .collect::<Vec<_>>()
+    };
+
+    let (mut lo, mut hi) = (0, order.len());
+    for (a, b) in ordering {
+        if wild_contains(&plugin, b).is_some() {
+            if let Some(before) = wild_contains(order, a) {
+                lo = positions(before)
+                    .into_iter()
+                    .map(|i| i + 1)
+                    .fold(lo, usize::max);
+            }
+        }
+        if wild_contains(&plugin, a).is_some() {
+            if let Some(after) = wild_contains(order, b) {
+                hi = positions(after).into_iter().fold(hi, usize::min);
+            }
+        }
+    }
+    (lo, hi)
+}
+
+/// Generates a readable markdown report of all warnings and the old and new load order
+pub fn get_report(
+    warnings: &[parser::Warning],
+    old_order: &[String],
+    new_order: &[String],
+) -> String {
+    let mut report = format!("# PLOX v{} Report\n", env!("CARGO_PKG_VERSION"));
+
+    // warnings grouped by type
+    for (title, rule_name) in [
+        ("Notes", "Note"),
+        ("Conflicts", "Conflict"),
+        ("Requires", "Requires"),
+        ("Patches", "Patch"),
+    ] {
+        let group = warnings
+            .iter()
+            .filter(|w| w.get_rule_name() == rule_name)
+            .collect::<Vec<_>>();
+        if group.is_empty() {
+            continue;
+        }
+
+        report += format!("\n## {} ({})\n", title, group.len()).as_str();
+        for warning in group {
+            report += format!("\n### [{}]\n\n", warning.get_rule_name()).as_str();
+            if !warning.get_comment().is_empty() {
+                report += format!("{}\n\n", warning.get_comment()).as_str();
+            }
+            for plugin in warning.get_plugins() {
+                report += format!("- {}\n", plugin).as_str();
+            }
+        }
+    }
+
+    // load orders
+    for (title, order) in [("Old Order", old_order), ("New Order", new_order)] {
+        report += format!("\n## {}\n\n", title).as_str();
+        for (i, plugin) in order.iter().enumerate() {
+            report += format!("{}. {}\n", i + 1, plugin).as_str();
+        }
+    }
+
+    report
+}
+
+/// Writes a report of all warnings and the old and new load order to a file
+pub fn write_report(
+    path: &Path,
+    warnings: &[parser::Warning],
+    old_order: &[String],
+    new_order: &[String],
+) -> std::io::Result<()> {
+    fs::write(path, get_report(warnings, old_order, new_order))
+}
+
+/// A warning rule that evaluated true, as written to the rule log
+#[derive(Debug, Serialize)]
+pub struct RuleLogEntry {
+    pub rule: String,
+    pub comment: String,
+    pub source: Option<parser::RuleSource>,
+    pub plugins: Vec<String>,
+}
+
+impl From<&parser::Warning> for RuleLogEntry {
+    fn from(warning: &parser::Warning) -> Self {
+        Self {
+            rule: warning.get_rule_name(),
+            comment: warning.get_comment(),
+            source: warning.source.clone(),
+            plugins: warning.get_plugins(),
+        }
+    }
+}
+
+/// Gets the rule log: one json object per line for each fired warning rule
+pub fn get_rule_log(warnings: &[parser::Warning]) -> String {
+    let mut log = String::new();
+    for warning in warnings {
+        if let Ok(json) = serde_json::to_string(&RuleLogEntry::from(warning)) {
+            log += format!("{}\n", json).as_str();
+        }
+    }
+    log
+}
+
+/// Writes the rule log of all fired warning rules to a file
+pub fn write_rule_log(path: &Path, warnings: &[parser::Warning]) -> std::io::Result<()> {
+    fs::write(path, get_rule_log(warnings))
+}
+
+/// Writes all edges of the sorting graph as json to a file
+pub fn write_edges(path: &Path, data: &sorter::GraphData) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(&data.get_named_edges())?;
+    fs::write(path, json)
+}
+
+/// Generates a LOOT userlist.yaml with a load after entry for each order edge of the graph.
+/// Master edges are skipped, LOOT resolves masters itself
+pub fn get_loot_userlist(data: &sorter::GraphData) -> String {
+    // group the plugins each plugin loads after, in the order the edges were added
+    let mut plugins: Vec<(String, Vec<String>)> = vec![];
+    for edge in data.get_named_edges() {
+        if edge.origin != "order" {
+            continue;
+        }
+        match plugins.iter_mut().find(|(name, _)| *name == edge.to) {
+            Some((_, after)) => {
+                if !after.contains(&edge.from) {
+                    after.push(edge.from);
+                }
+            }
+            None => plugins.push((edge.to, vec![edge.from])),
+        }
+    }
+
+    // yaml single quoted strings escape quotes by doubling them
+    let quote = |name: &str| format!("'{}'", name.replace('\'', "''"));
+
+    let mut userlist = "plugins:\n".to_owned();
+    for (name, after) in &plugins {
+        userlist += format!("  - name: {}\n", quote(name)).as_str();
+        userlist += "    after:\n";
+        for a in after {
+            userlist += format!("      - {}\n", quote(a)).as_str();
+        }
+    }
+    userlist
+}
+
+/// Writes the order edges of the sorting graph as a LOOT userlist.yaml
+pub fn write_loot_userlist(path: &Path, data: &sorter::GraphData) -> std::io::Result<()> {
+    fs::write(path, get_loot_userlist(data))
+}
+
+/// Maps each plugin targeted by a warning to the index of that warning
+pub fn get_plugin_warning_map(warnings: &[parser::Warning]) -> Vec<(String, usize)> {
+    let mut plugin_warning_map = vec![];
+    for (i, w) in warnings.iter().enumerate() {
+        for p in &w.get_plugins() {
+            plugin_warning_map.push((p.clone(), i));
+        }
+    }
+    plugin_warning_map
+}
+
+/// Gets all plugins that are targets of at least one warning, in the given order
+pub fn get_problem_plugins(
+    plugins: &[String],
+    plugin_warning_map: &[(String, usize)],
+) -> Vec<String> {
+    plugins
+        .iter()
+        .filter(|p| {
+            plugin_warning_map
+                .iter()
+                .any(|(w, _)| normalize_name(w) == normalize_name(p))
+        })
+        .cloned()
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////
+// CONFLICTS
+////////////////////////////////////////////////////////////////////////
+
+/// Decides which group of plugins is kept when a conflict is resolved
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EConflictStrategy {
+    /// keep the plugins of the first conflicting expression
+    #[default]
+    KeepFirst,
+    /// keep the plugins of the last conflicting expression
+    KeepLast,
+    /// keep the group with the largest total file size
+    KeepLarger,
+    /// ask on the console which group to keep (cli only)
+    Interactive,
+}
+
+impl EConflictStrategy {
+    /// Gets the index of the group to keep.
+    /// Returns None for Interactive, the caller has to ask which group to keep
+    pub fn get_group(&self, conflict: &Conflict, plugins: &[PluginData]) -> Option<usize> {
+        match self {
+            EConflictStrategy::KeepFirst => Some(0),
+            EConflictStrategy::KeepLast => Some(conflict.conflicts.len() - 1),
+            EConflictStrategy::KeepLarger => Some(get_largest_group(&conflict.conflicts, plugins)),
+            EConflictStrategy::Interactive => None,
+        }
+    }
+}
+
+/// A plugin removed from the load order to resolve a conflict
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RemovedPlugin {
+    pub name: String,
+    /// the comment of the conflict rule
+    pub reason: String,
+}
+
+/// Removes plugins until no conflict rule evaluates true.
+/// For each conflict `choose` gets the conflict and the plugins left, with lowercase names, and returns the index of the group to keep.
+/// All other groups are removed.
+/// Returns the remaining plugins and the removed plugins with the reason for removal.
+pub fn resolve_conflicts<F>(
+    game: ESupportedGame,
+    plugins: &[PluginData],
+    warning_rules: &[EWarningRule],
+    mut choose: F,
+) -> (Vec<PluginData>, Vec<RemovedPlugin>)
+where
+    F: FnMut(&Conflict, &[PluginData]) -> usize,
+{
+    // rules are evaluated against lowercase plugin names
+    let mut plugins = plugins
+        .iter()
+        .map(|p| (p.clone(), normalize_name(&p.name)))
+        .collect::<Vec<_>>();
+    let mut lowercase = get_lowercase_plugins(&plugins);
+    let mut removed: Vec<RemovedPlugin> = vec![];
+    for rule in warning_rules {
+        let EWarningRule::Conflict(conflict) = rule else {
+            continue;
+        };
+
+        // evaluate against the plugins left, earlier removals may already resolve the conflict
+        let mut conflict = conflict.clone();
+        if !conflict.eval(&expressions::EvalContext::new(game, None, &lowercase)) {
+            continue;
+        }
+
+        let keep = choose(&conflict, &lowercase).min(conflict.conflicts.len() - 1);
+        let kept = &conflict.conflicts[keep];
+        for (i, group) in conflict.conflicts.iter().enumerate() {
+            if i == keep {
+                continue;
+            }
+            for name in group.iter().filter(|n| !kept.contains(n)) {
+                if removed.iter().any(|r| &r.name == name) {
+                    continue;
+                }
+                info!(
+                    "Removing {} to resolve conflict: {}",
+                    name, conflict.comment
+                );
+                removed.push(RemovedPlugin {
+                    name: name.clone(),
+                    reason: conflict.comment.clone(),
+                });
+            }
+        }
+        plugins.retain(|(_, name)| !removed.iter().any(|r| &r.name == name));
+        lowercase = get_lowercase_plugins(&plugins);
+    }
+
+    (plugins.into_iter().map(|(p, _)| p).collect(), removed)
+}
+
+/// Copies the plugins with their lowercase names
+fn get_lowercase_plugins(plugins: &[(PluginData, String)]) -> Vec<PluginData> {
+    plugins
+        .iter()
+        .map(|(p, name)| {
+            let mut p = p.clone();
+            p.name.clone_from(name);
+            p
+        })
+        .collect()
+}
+
+/// Gets the index of the group with the largest total size, the first one wins ties
+fn get_largest_group(groups: &[Vec<String>], plugins: &[PluginData]) -> usize {
+    let size = |group: &Vec<String>| -> u64 {
+        plugins
+            .iter()
+            .filter(|p| group.contains(&p.name))
+            .map(|p| p.size)
+            .sum()
+    };
+    let mut largest = 0;
+    for (i, group) in groups.iter().enumerate() {
+        if size(group) > size(&groups[largest]) {
+            largest = i;
+        }
+    }
+    largest
 }
 
 ////////////////////////////////////////////////////////////////////////
@@ -645,6 +1896,8 @@ pub fn check_order(result: &[String], order_rules: &[EOrderRule]) -> bool {
 ////////////////////////////////////////////////////////////////////////
 #[derive(Debug, Clone, Default)]
 pub struct Tes3Header {
+    /// the numeric version of the HEDR record, usually the file format version
+    pub version: f32,
     pub description: String,
     pub masters: Option<Vec<(String, u64)>>,
 }
@@ -693,7 +1946,7 @@ fn parse_hedr<R: Read + Seek>(reader: &mut R, stream_size: u64) -> std::io::Resu
     let _header_size = reader.read_u32::<LittleEndian>()?;
 
     // next 4 bytes is the version
-    let _ = reader.read_f32::<LittleEndian>()?;
+    header.version = reader.read_f32::<LittleEndian>()?;
 
     // next 4 bytes is unused
     let _ = reader.read_u32::<LittleEndian>()?;
@@ -768,37 +2021,68 @@ fn parse_hedr<R: Read + Seek>(reader: &mut R, stream_size: u64) -> std::io::Resu
     Ok(header)
 }
 
-fn redate_mods(files: &[PathBuf]) -> Result<(), io::Error> {
-    let fixed_file_times: HashMap<String, usize> = HashMap::from([
+/// Options for redating plugin files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedateOptions {
+    /// unix time of the first plugin in the load order
+    pub base_time: i64,
+    /// seconds between two consecutive plugins
+    pub step: i64,
+}
+
+impl Default for RedateOptions {
+    fn default() -> Self {
+        Self {
+            base_time: 1024695106,
+            step: 60,
+        }
+    }
+}
+
+/// Masters with well-known file times
+fn get_fixed_file_times() -> HashMap<String, i64> {
+    HashMap::from([
+        // Bethesda masters
         ("morrowind.esm".into(), 1024695106),
         ("tribunal.esm".into(), 1035940926),
         ("bloodmoon.esm".into(), 1051807050),
-    ]);
-    let start_time = 1024695106;
-    let mut current_time = start_time;
-    for mod_path in files {
+        // Tamriel Rebuilt and Project Tamriel masters have no canonical file times and are redated with the step
+    ])
+}
+
+fn redate_mods(files: &[PathBuf], options: RedateOptions) -> Result<(), io::Error> {
+    let fixed_file_times = get_fixed_file_times();
+    let mut current_time = options.base_time;
+    for (i, mod_path) in files.iter().enumerate() {
         // Change the modification times of plugin files to be in order of file list, oldest to newest
         // check if is a fixed file time file
         let filename = mod_path.file_name().unwrap().to_str().unwrap();
-        if let Some(time) = fixed_file_times.get(&filename.to_lowercase()) {
-            let time = *time as i64;
-            current_time = time;
-            set_file_mtime(mod_path, filetime::FileTime::from_unix_time(time, 0))?;
-        } else {
-            // set the time to start time + 60
-            current_time += 60;
-            set_file_mtime(
-                mod_path,
-                filetime::FileTime::from_unix_time(current_time, 0),
-            )?;
-        }
+        let fixed_time = fixed_file_times.get(&filename.to_ascii_lowercase());
+
+        // fixed file times must never break the order, otherwise fall back to the step
+        current_time = match fixed_time {
+            Some(time) if i == 0 || *time > current_time => *time,
+            Some(_) => {
+                warn!(
+                    "Fixed file time for {} would break the load order, using the next step",
+                    filename
+                );
+                current_time + options.step
+            }
+            None => current_time + options.step,
+        };
+
+        set_file_mtime(
+            mod_path,
+            filetime::FileTime::from_unix_time(current_time, 0),
+        )?;
     }
 
     Ok(())
 }
 
 ////////////////////////////////////////////////////////////////////////
-/// HELPERS
+// HELPERS
 ////////////////////////////////////////////////////////////////////////
 
 fn generate_pair_permutations(input: &[String]) -> Vec<(String, String)> {
@@ -830,7 +2114,17 @@ pub fn get_ordering(rules: &Vec<ERule>) -> Vec<(String, String)> {
 
 /// Extracts a list of ordering-pairs from the order rules
 pub fn get_ordering_from_order_rules(rules: &[EOrderRule]) -> Vec<(String, String)> {
-    let mut orders: Vec<(String, String)> = vec![];
+    get_commented_ordering_from_order_rules(rules)
+        .into_iter()
+        .map(|(a, b, _)| (a, b))
+        .collect()
+}
+
+/// Extracts a list of ordering-pairs from the order rules, together with the comment of the originating rule
+pub fn get_commented_ordering_from_order_rules(
+    rules: &[EOrderRule],
+) -> Vec<(String, String, String)> {
+    let mut orders: Vec<(String, String, String)> = vec![];
 
     for r in rules {
         if let EOrderRule::Order(o) = r {
@@ -838,7 +2132,11 @@ pub fn get_ordering_from_order_rules(rules: &[EOrderRule]) -> Vec<(String, Strin
             if o.names.len() < 2 {
                 continue;
             }
-            orders.extend(generate_pair_permutations(&o.names));
+            orders.extend(
+                generate_pair_permutations(&o.names)
+                    .into_iter()
+                    .map(|(a, b)| (a, b, o.comment.clone())),
+            );
         }
     }
 
@@ -888,20 +2186,56 @@ where
     Ok(io::BufReader::new(file).lines())
 }
 
+/// The format of a mod list file
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq)]
+pub enum EModListFormat {
+    /// one plugin per line
+    #[default]
+    Plain,
+    /// Wrye Mash plugins list: active plugins are prefixed with `*`, lines starting with `#` are comments
+    Wrye,
+}
+
+/// Gets the plugin name of a line in a Wrye Mash plugins list, None for comments and inactive plugins
+pub fn parse_wrye_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let name = line.strip_prefix('*')?.trim();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
 /// read file line by line into vector
 pub fn read_file_as_list<P>(modlist_path: P) -> Vec<PluginData>
+where
+    P: AsRef<Path>,
+{
+    read_file_as_list_with_format(modlist_path, EModListFormat::Plain)
+}
+
+/// read file line by line into vector, mapping the lines of the given format to plugin names
+pub fn read_file_as_list_with_format<P>(modlist_path: P, format: EModListFormat) -> Vec<PluginData>
 where
     P: AsRef<Path>,
 {
     let mut result: Vec<PluginData> = vec![];
     if let Ok(lines) = read_lines(modlist_path) {
         for line in lines.map_while(Result::ok) {
+            let name = match format {
+                EModListFormat::Plain => line,
+                EModListFormat::Wrye => match parse_wrye_line(&line) {
+                    Some(name) => name,
+                    None => continue,
+                },
+            };
             let data = PluginData {
-                name: line,
-                size: 0, // TODO fix dummy size
+                name,
+                size: 0, // resolved from the game folder in read_mod_list
                 description: None,
                 version: None,
                 masters: None,
+                header_error: None,
             };
             result.push(data);
         }
@@ -909,6 +2243,247 @@ where
     result
 }
 
+/// Reads a mod list file and expands wildcard lines (e.g. `Tamriel_Data*.esm`) against the plugins on disk.
+/// The plugins on disk are only gathered if the list contains a wildcard line.
+pub fn read_mod_list<P>(
+    modlist_path: P,
+    format: EModListFormat,
+    root: &PathBuf,
+    game: ESupportedGame,
+    config: &Option<PathBuf>,
+) -> Vec<PluginData>
+where
+    P: AsRef<Path>,
+{
+    let list = resolve_mod_list(
+        read_file_as_list_with_format(modlist_path, format),
+        root,
+        game,
+        config,
+    );
+    let mut list = if list.iter().any(|p| is_wildcard(&p.name)) {
+        expand_mod_list(list, &gather_mods(root, game, config.clone()))
+    } else {
+        list
+    };
+    remove_duplicate_plugins(&mut list);
+    list
+}
+
+/// Removes plugins that are listed more than once, e.g. by a corrupted openmw.cfg, and warns about them.
+/// Names are compared case-insensitive and the first entry is kept.
+/// Returns the name and all positions of each duplicate plugin
+pub fn remove_duplicate_plugins(plugins: &mut Vec<PluginData>) -> Vec<(String, Vec<usize>)> {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut positions: Vec<(String, Vec<usize>)> = vec![];
+    for (i, plugin) in plugins.iter().enumerate() {
+        match index.get(&normalize_name(&plugin.name)) {
+            Some(idx) => positions[*idx].1.push(i),
+            None => {
+                index.insert(normalize_name(&plugin.name), positions.len());
+                positions.push((plugin.name.to_owned(), vec![i]));
+            }
+        }
+    }
+
+    let duplicates = positions
+        .into_iter()
+        .filter(|(_, p)| p.len() > 1)
+        .collect::<Vec<_>>();
+    if duplicates.is_empty() {
+        return duplicates;
+    }
+    for (name, p) in &duplicates {
+        warn!(
+            "{} is listed {} times at positions {:?}, only the first entry is kept",
+            name,
+            p.len(),
+            p
+        );
+    }
+
+    let mut seen = HashSet::new();
+    plugins.retain(|p| seen.insert(normalize_name(&p.name)));
+    duplicates
+}
+
+/// Resolves the literal entries of a mod list to their files in the game folder and reads their data.
+/// Entries that can't be found keep a size of 0.
+fn resolve_mod_list(
+    list: Vec<PluginData>,
+    root: &Path,
+    game: ESupportedGame,
+    config: &Option<PathBuf>,
+) -> Vec<PluginData> {
+    let dirs = match game {
+        ESupportedGame::Morrowind => vec![root.join("Data Files")],
+        ESupportedGame::Cyberpunk => vec![root.join("archive").join("pc").join("mod")],
+        ESupportedGame::Openmw => get_openmw_data_dirs(config),
+    };
+    if !dirs.iter().any(|d| d.is_dir()) {
+        info!("No game folder found, [SIZE], [VER] and [DESC] rules can't be evaluated for the mod list");
+        return list;
+    }
+
+    list.into_iter()
+        .map(|entry| {
+            if is_wildcard(&entry.name) {
+                return entry;
+            }
+            // the last data directory wins, like in OpenMW
+            let Some(path) = dirs
+                .iter()
+                .rev()
+                .find_map(|dir| find_file_ci(dir, &entry.name))
+            else {
+                warn!("Could not find {} in the game folder", entry.name);
+                return entry;
+            };
+            let data = match game {
+                // archives don't have a plugin header
+                ESupportedGame::Cyberpunk => path.metadata().ok().map(|m| PluginData {
+                    size: m.len(),
+                    ..entry.clone()
+                }),
+                ESupportedGame::Morrowind | ESupportedGame::Openmw => map_data(&path),
+            };
+            // keep the casing of the mod list
+            data.map(|data| PluginData {
+                name: entry.name.clone(),
+                ..data
+            })
+            .unwrap_or(entry)
+        })
+        .collect()
+}
+
+/// Returns the data directories of an openmw.cfg
+fn get_openmw_data_dirs(config: &Option<PathBuf>) -> Vec<PathBuf> {
+    openmw_cfg::Ini::load_from_file_noescape(get_openmw_config_path(config))
+        .ok()
+        .and_then(|cfg| openmw_cfg::get_data_dirs(&cfg).ok())
+        .unwrap_or_default()
+        .iter()
+        .map(|dir| PathBuf::from(dir.trim_matches('"')))
+        .collect()
+}
+
+/// Replaces wildcard entries of a mod list with all matching plugins, in the order of the available plugins.
+/// Literal entries are kept as they are, plugins already in the list are not added twice.
+pub fn expand_mod_list(list: Vec<PluginData>, available: &[PluginData]) -> Vec<PluginData> {
+    let available_names = available
+        .iter()
+        .map(|p| normalize_name(&p.name))
+        .collect::<Vec<_>>();
+
+    let mut result: Vec<PluginData> = vec![];
+    for entry in list {
+        if !is_wildcard(&entry.name) {
+            let name = normalize_name(&entry.name);
+            if !result.iter().any(|p| normalize_name(&p.name) == name) {
+                result.push(entry);
+            }
+            continue;
+        }
+
+        let Some(matches) = wild_contains(&available_names, &normalize_name(&entry.name)) else {
+            warn!("No plugin matches {}", entry.name);
+            continue;
+        };
+        for name in matches {
+            if result.iter().any(|p| normalize_name(&p.name) == name) {
+                continue;
+            }
+            if let Some(i) = available_names.iter().position(|n| n == &name) {
+                result.push(available[i].clone());
+            }
+        }
+    }
+    result
+}
+
+fn is_wildcard(name: &str) -> bool {
+    name.contains('*') || name.contains('?') || name.contains("<ver>")
+}
+
+/// Writes plugin names to a file, one per line. The file can be read with read_file_as_list
+///
+/// # Errors
+///
+/// This function will return an error if file io fails
+pub fn write_file_as_list<P>(path: P, names: &[String]) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut file = File::create(path)?;
+    for name in names {
+        writeln!(file, "{}", name)?;
+    }
+    Ok(())
+}
+
+/// Reads an ignore list: one plugin name or wildcard pattern per line
+pub fn read_ignore_list<P>(path: P) -> Vec<String>
+where
+    P: AsRef<Path>,
+{
+    read_lines(path)
+        .map(|lines| {
+            lines
+                .map_while(Result::ok)
+                .map(|l| normalize_name(&l))
+                .filter(|l| !l.is_empty() && !l.starts_with(';'))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the lowercase names of all plugins matching a pattern of the ignore list
+pub fn get_ignored_plugins(plugins: &[PluginData], ignore_list: &[String]) -> Vec<String> {
+    let names = plugins
+        .iter()
+        .map(|p| normalize_name(&p.name))
+        .collect::<Vec<_>>();
+    let mut ignored = vec![];
+    for pattern in ignore_list {
+        for name in wild_contains(&names, pattern).unwrap_or_default() {
+            if !ignored.contains(&name) {
+                ignored.push(name);
+            }
+        }
+    }
+    ignored
+}
+
+/// Puts the ignored plugins back at their position of the original order, with their original name.
+/// The sorted plugins fill all other positions in their new order.
+pub fn restore_ignored_plugins(
+    original: &[String],
+    sorted: &[String],
+    ignored: &[String],
+) -> Vec<String> {
+    let mut sorted = sorted.iter();
+    original
+        .iter()
+        .filter_map(|name| {
+            if ignored.contains(&normalize_name(name)) {
+                Some(name.clone())
+            } else {
+                sorted.next().cloned()
+            }
+        })
+        .collect()
+}
+
+/// Normalizes a plugin name for comparisons: trimmed, lowercase and with internal whitespace collapsed.
+/// Only ASCII characters are lowercased like in mlox, so matching doesn't depend on unicode case mappings
+pub fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_ascii_lowercase()
+}
+
 /// Checks if the list contains the str
 pub fn wild_contains(list: &[String], str: &String) -> Option<Vec<String>> {
     if str.contains('*') || str.contains('?') || str.contains("<ver>") {
@@ -951,7 +2526,7 @@ pub fn wild_contains(list: &[String], str: &String) -> Option<Vec<String>> {
 
 /// Checks if the list contains the str
 pub fn wild_contains_data(list: &[PluginData], str: &str) -> Option<Vec<PluginData>> {
-    let str = str.to_lowercase();
+    let str = normalize_name(str);
     if str.contains('*') || str.contains('?') || str.contains("<ver>") {
         let mut results = vec![];
         // Replace * with .* to match any sequence of characters
@@ -983,7 +2558,7 @@ pub fn wild_contains_data(list: &[PluginData], str: &str) -> Option<Vec<PluginDa
         return Some(results);
     }
 
-    if let Some(r) = list.iter().find(|f| f.name.to_lowercase().eq(&str)) {
+    if let Some(r) = list.iter().find(|f| normalize_name(&f.name).eq(&str)) {
         return Some(vec![r.to_owned()]);
     }
 
@@ -1059,10 +2634,22 @@ pub fn nearend2(f: &EOrderRule) -> Option<NearEnd> {
         _ => None,
     }
 }
+pub fn override_rule(f: ERule) -> Option<Override> {
+    match f {
+        ERule::EOrderRule(EOrderRule::Override(o)) => Some(o),
+        _ => None,
+    }
+}
+pub fn override_rule2(f: &EOrderRule) -> Option<Override> {
+    match f {
+        EOrderRule::Override(o) => Some(o.clone()),
+        _ => None,
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    //use std::fs::create_dir_all;
+    use std::fs::create_dir_all;
 
     use semver::{BuildMetadata, Prerelease};
 
@@ -1194,41 +2781,75 @@ mod tests {
         }
     }
 
-    // #[test]
-    // fn test_redate_mods() {
-    //     let result = [
-    //         "morrowind.esm".to_owned(),
-    //         "tribunal.esm".to_owned(),
-    //         "bloodmoon.esm".to_owned(),
-    //         "a.esp".to_owned(),
-    //         "b.esp".to_owned(),
-    //         "c.esp".to_owned(),
-    //     ];
-
-    //     // create the files in /tmp
-    //     create_dir_all("tmp").expect("copuld not create dir");
-    //     let mut files = vec![];
-    //     for r in &result {
-    //         let mod_path = PathBuf::from("tmp").join(r);
-    //         let _ = File::create(&mod_path);
-    //         files.push(mod_path.clone());
-    //     }
-
-    //     redate_mods(&files).expect("redate failed");
-
-    //     // check if the filetime is correct
-    //     for path in &files {
-    //         let metadata = fs::metadata(path).expect("metadata failed");
-    //         let modified = metadata.modified().expect("modified failed");
-    //         let unix_time = filetime::FileTime::from_system_time(modified);
-    //         eprintln!("{} - {:?}", path.display(), unix_time);
-    //     }
-
-    //     // delete the files again
-    //     for path in &files {
-    //         fs::remove_file(path).expect("remove failed");
-    //     }
-    // }
+    fn redate_and_get_times(dir: &str, result: &[&str], options: RedateOptions) -> Vec<i64> {
+        // create the files in /tmp
+        let dir = PathBuf::from("tmp").join(dir);
+        create_dir_all(&dir).expect("could not create dir");
+        let mut files = vec![];
+        for r in result {
+            let mod_path = dir.join(r);
+            let _ = File::create(&mod_path);
+            files.push(mod_path.clone());
+        }
+
+        redate_mods(&files, options).expect("redate failed");
+
+        // get the filetimes
+        let mut times = vec![];
+        for path in &files {
+            let metadata = fs::metadata(path).expect("metadata failed");
+            let unix_time = filetime::FileTime::from_last_modification_time(&metadata);
+            times.push(unix_time.unix_seconds());
+        }
+
+        // delete the files again
+        for path in &files {
+            fs::remove_file(path).expect("remove failed");
+        }
+
+        times
+    }
+
+    #[test]
+    fn test_redate_mods() {
+        let result = [
+            "morrowind.esm",
+            "tribunal.esm",
+            "bloodmoon.esm",
+            "Tamriel_Data.esm",
+            "a.esp",
+            "b.esp",
+            "c.esp",
+        ];
+
+        let times = redate_and_get_times("redate", &result, RedateOptions::default());
+
+        // check that the filetimes are monotonically increasing
+        assert!(times.windows(2).all(|w| w[0] < w[1]), "{:?}", times);
+
+        // check the fixed file times, Tamriel_Data.esm follows the step
+        assert_eq!(times[0], 1024695106);
+        assert_eq!(times[1], 1035940926);
+        assert_eq!(times[2], 1051807050);
+        assert_eq!(times[3], 1051807050 + 60);
+        assert_eq!(times[4], 1051807050 + 120);
+    }
+
+    #[test]
+    fn test_redate_mods_options() {
+        let result = ["a.esp", "b.esp", "morrowind.esm", "c.esp"];
+        let options = RedateOptions {
+            base_time: 1100000000,
+            step: 3600,
+        };
+
+        let times = redate_and_get_times("redate_options", &result, options);
+
+        // check that the filetimes are monotonically increasing
+        // morrowind.esm may not move back in time
+        assert!(times.windows(2).all(|w| w[0] < w[1]), "{:?}", times);
+        assert_eq!(times, [1100003600, 1100007200, 1100010800, 1100014400]);
+    }
 
     #[test]
     fn test_match_filename_version() {
@@ -1251,6 +2872,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_get_version_fallback() {
+        let description = Some("a plugin version 3.0".to_owned());
+        let no_description = Some("a plugin".to_owned());
+
+        // description > filename > header version
+        assert_eq!(
+            get_version("a_2.0.esp", &description, Some(4.0)),
+            Some(Version::new(3, 0, 0))
+        );
+        assert_eq!(
+            get_version("a_2.0.esp", &no_description, Some(4.0)),
+            Some(Version::new(2, 0, 0))
+        );
+        assert_eq!(
+            get_version("a.esp", &no_description, Some(2.5)),
+            Some(Version::new(2, 5, 0))
+        );
+        assert_eq!(
+            get_version("a.esp", &None, Some(4.0)),
+            Some(Version::new(4, 0, 0))
+        );
+
+        // file format versions and missing headers don't set a version
+        assert_eq!(get_version("a.esp", &no_description, Some(1.3)), None);
+        assert_eq!(get_version("a.esp", &no_description, Some(1.2)), None);
+        assert_eq!(get_version("a.esp", &no_description, Some(0.0)), None);
+        assert_eq!(get_version("a.esp", &no_description, None), None);
+    }
+
     #[test]
     fn test_match_desc_version() {
         let inputs = [
@@ -1326,4 +2977,697 @@ mod tests {
             assert_eq!(got, *expected);
         }
     }
+
+    #[test]
+    fn test_get_report() {
+        let note = Note {
+            comment: "a note".into(),
+            plugins: vec!["a.esp".into()],
+            ..Default::default()
+        };
+        let conflict = Conflict {
+            comment: "a conflict".into(),
+            plugins: vec!["a.esp".into(), "b.esp".into()],
+            ..Default::default()
+        };
+        let warnings = [
+            parser::Warning {
+                rule: conflict.into(),
+                source: None,
+            },
+            parser::Warning {
+                rule: note.into(),
+                source: None,
+            },
+        ];
+        let old_order = ["b.esp".to_owned(), "a.esp".to_owned()];
+        let new_order = ["a.esp".to_owned(), "b.esp".to_owned()];
+
+        let report = get_report(&warnings, &old_order, &new_order);
+
+        // notes come before conflicts
+        let notes = report.find("## Notes (1)").expect("no notes section");
+        let conflicts = report
+            .find("## Conflicts (1)")
+            .expect("no conflicts section");
+        assert!(notes < conflicts);
+        assert!(!report.contains("## Requires"));
+        assert!(report.contains("a conflict\n\n- a.esp\n- b.esp\n"));
+        assert!(report.contains("## Old Order\n\n1. b.esp\n2. a.esp\n"));
+        assert!(report.contains("## New Order\n\n1. a.esp\n2. b.esp\n"));
+    }
+
+    #[test]
+    fn test_write_rule_log() {
+        let dir = PathBuf::from("tmp");
+        create_dir_all(&dir).unwrap();
+        let rules_path = dir.join("rule_log_rules.txt");
+        fs::write(
+            &rules_path,
+            "[Note]\n\ta note\na.esp\n\n[Conflict]\n\ta conflict\na.esp\nb.esp\n\n[Note]\n\tnot fired\nc.esp\n",
+        )
+        .unwrap();
+
+        let mut parser = parser::new_tes3_parser();
+        parser.init_from_file(&rules_path).unwrap();
+        let plugins = [
+            PluginData::new("a.esp".into(), 0),
+            PluginData::new("b.esp".into(), 0),
+        ];
+        parser.evaluate_plugins(&plugins);
+        assert_eq!(parser.warnings.len(), 2);
+
+        let log_path = dir.join("rule_log.jsonl");
+        write_rule_log(&log_path, &parser.warnings).unwrap();
+        let log = fs::read_to_string(&log_path).unwrap();
+
+        // one json object per fired rule
+        let entries = log
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["rule"], "Note");
+        assert_eq!(entries[0]["comment"], "a note");
+        assert_eq!(entries[0]["source"]["file"], "rule_log_rules.txt");
+        assert_eq!(entries[0]["source"]["line"], 1);
+        assert_eq!(entries[1]["rule"], "Conflict");
+        assert_eq!(entries[1]["source"]["line"], 5);
+        assert_eq!(entries[1]["plugins"], serde_json::json!(["a.esp", "b.esp"]));
+    }
+
+    #[test]
+    fn test_write_edges() {
+        let dir = PathBuf::from("tmp");
+        create_dir_all(&dir).unwrap();
+
+        let mut c = PluginData::new("C.esp".into(), 0);
+        c.masters = Some(vec![("A.esm".into(), 0)]);
+        let plugins = [
+            PluginData::new("A.esm".into(), 0),
+            PluginData::new("b.esp".into(), 0),
+            c,
+        ];
+        let order_rules = [Order::from("b.esp", "c.esp").into()];
+        let data = sorter::get_graph_data(&plugins, &order_rules, &[]);
+
+        let path = dir.join("edges.json");
+        write_edges(&path, &data).unwrap();
+        let edges: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+
+        // plugin names are resolved and each edge is tagged with its origin
+        assert_eq!(
+            edges,
+            serde_json::json!([
+                { "from": "b.esp", "to": "c.esp", "origin": "order" },
+                { "from": "a.esm", "to": "c.esp", "origin": "master" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_get_loot_userlist() {
+        let mut d = PluginData::new("d.esp".into(), 0);
+        d.masters = Some(vec![("a.esm".into(), 0)]);
+        let plugins = [
+            PluginData::new("a.esm".into(), 0),
+            PluginData::new("b.esp".into(), 0),
+            PluginData::new("Jim's Mod.esp".into(), 0),
+            d,
+        ];
+        let order_rules =
+            [Order::new(vec!["b.esp".into(), "jim's mod.esp".into(), "d.esp".into()]).into()];
+        let data = sorter::get_graph_data(&plugins, &order_rules, &[]);
+
+        // one entry per plugin with all plugins it loads after, the master edge is skipped
+        assert_eq!(
+            get_loot_userlist(&data),
+            [
+                "plugins:",
+                "  - name: 'jim''s mod.esp'",
+                "    after:",
+                "      - 'b.esp'",
+                "  - name: 'd.esp'",
+                "    after:",
+                "      - 'b.esp'",
+                "      - 'jim''s mod.esp'",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_detect_game_in_parent() {
+        // tests/openmw.cfg marks the tests dir as an OpenMW root
+        let (game, root) = detect_game_in(&PathBuf::from("tests").join("mlox")).unwrap();
+        assert_eq!(game, ESupportedGame::Openmw);
+        assert_eq!(root, PathBuf::from("tests"));
+
+        assert!(detect_game_in(&PathBuf::from("src").join("commands")).is_none());
+    }
+
+    #[test]
+    fn test_detect_openmw_from_config() {
+        let config = PathBuf::from("tests").join("openmw.cfg");
+        let dir = PathBuf::from("src").join("commands");
+        let (game, root) = detect_openmw_from_config(&dir, &config).unwrap();
+        assert_eq!(game, ESupportedGame::Openmw);
+        assert_eq!(root, dir);
+
+        // no config
+        assert!(detect_openmw_from_config(&dir, &PathBuf::from("missing.cfg")).is_none());
+        // tests/Morrowind.ini marks a Morrowind install
+        assert!(detect_openmw_from_config(&PathBuf::from("tests").join("mlox"), &config).is_none());
+    }
+
+    #[test]
+    fn test_get_openmw_config_path() {
+        let config = PathBuf::from("tests").join("openmw.cfg");
+        assert_eq!(get_openmw_config_path(&Some(config.clone())), config);
+        assert_eq!(
+            get_openmw_config_path(&Some(PathBuf::from("missing.cfg"))),
+            config_path()
+        );
+        assert_eq!(get_openmw_config_path(&None), config_path());
+    }
+
+    #[test]
+    fn test_download_failure_keeps_local_rules() {
+        let dir = PathBuf::from("tmp/rules_offline");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("mlox_base.txt");
+        fs::write(&path, "[Order]\na.esp\nb.esp\n").unwrap();
+
+        // nothing listens on port 1, all attempts fail and the local file is used
+        assert!(download_with_retry("http://127.0.0.1:1/mlox_base.txt", Duration::ZERO).is_err());
+        download_rules(
+            ESupportedGame::Morrowind,
+            &dir,
+            "http://127.0.0.1:1/",
+            &["mlox_base.txt"],
+            Duration::ZERO,
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[Order]\na.esp\nb.esp\n"
+        );
+    }
+
+    #[test]
+    fn test_store_invalid_rules_keeps_local_rules() {
+        let dir = PathBuf::from("tmp/rules_invalid");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("mlox_base.txt");
+        let rules = "[Order]\na.esp\nb.esp\n";
+        fs::write(&path, rules).unwrap();
+        fs::write(path.with_extension("hash"), "1").unwrap();
+
+        // e.g. the 404 page of a moved repository
+        let html = b"<!DOCTYPE html>\n<html><body><h1>404 Not Found</h1></body></html>\n";
+        assert!(store_rules_file(ESupportedGame::Morrowind, html, &path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), rules);
+        assert_eq!(
+            fs::read_to_string(path.with_extension("hash")).unwrap(),
+            "1"
+        );
+
+        // valid rules replace the local file
+        let new_rules = b"[Order]\nb.esp\na.esp\n";
+        store_rules_file(ESupportedGame::Morrowind, new_rules, &path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), new_rules);
+
+        // an mlox header is enough
+        assert!(
+            validate_rules(ESupportedGame::Morrowind, b";; -*- mode: emacs-lisp -*-\n").is_ok()
+        );
+        assert!(validate_rules(ESupportedGame::Morrowind, b"").is_err());
+    }
+
+    #[test]
+    fn test_get_rules_version() {
+        let version = get_rules_version(ESupportedGame::Morrowind, &PathBuf::from("tests/mlox"));
+        assert_eq!(version, Some("2017-15-10 11:11:11 (UTC)".to_owned()));
+
+        assert_eq!(format_unix_date(0), "1970-01-01");
+        assert_eq!(format_unix_date(1024695106), "2002-06-21");
+    }
+
+    #[test]
+    fn test_write_file_as_list() {
+        let dir = PathBuf::from("tmp");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("modlist_roundtrip.txt");
+
+        let names = vec!["Morrowind.esm".to_owned(), "my mod 1.0.esp".to_owned()];
+        write_file_as_list(&path, &names).unwrap();
+        let read = read_file_as_list(&path)
+            .into_iter()
+            .map(|p| p.name)
+            .collect::<Vec<_>>();
+        assert_eq!(read, names);
+    }
+
+    #[test]
+    fn test_expand_mod_list() {
+        let available = debug_get_mods_from_ordering(&[
+            ("Tamriel_Data_2.esm".to_owned(), "a.esp".to_owned()),
+            ("Tamriel_Data_1.esm".to_owned(), "b.esp".to_owned()),
+        ]);
+        let list = [
+            "Morrowind.esm",
+            "tamriel_data*.esm",
+            "b.esp",
+            "*.esp",
+            "missing*.esp",
+            "A.esp",
+        ]
+        .map(|name| PluginData::new(name.to_owned(), 0));
+
+        let expanded = expand_mod_list(list.to_vec(), &available)
+            .into_iter()
+            .map(|p| p.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            expanded,
+            [
+                "Morrowind.esm",
+                "Tamriel_Data_2.esm",
+                "Tamriel_Data_1.esm",
+                "b.esp",
+                "a.esp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_mod_list_sizes() {
+        let root = PathBuf::from("tmp/modlist_game");
+        let data_files = root.join("Data Files");
+        create_dir_all(&data_files).unwrap();
+        fs::write(data_files.join("Sized.esp"), [0u8; 42]).unwrap();
+
+        let list_path = root.join("modlist.txt");
+        write_file_as_list(
+            &list_path,
+            &["sized.esp".to_owned(), "missing.esp".to_owned()],
+        )
+        .unwrap();
+
+        let mods = read_mod_list(
+            &list_path,
+            EModListFormat::Plain,
+            &root,
+            ESupportedGame::Morrowind,
+            &None,
+        );
+        assert_eq!(mods.len(), 2);
+        assert_eq!(mods[0].name, "sized.esp");
+        assert_eq!(mods[0].size, 42);
+        assert_eq!(mods[1].name, "missing.esp");
+        assert_eq!(mods[1].size, 0);
+
+        // without a game folder the sizes stay 0
+        let mods = read_mod_list(
+            &list_path,
+            EModListFormat::Plain,
+            &root.join("missing"),
+            ESupportedGame::Morrowind,
+            &None,
+        );
+        assert_eq!(mods[0].size, 0);
+    }
+
+    #[test]
+    fn test_cp77_modlist_comments() {
+        let root = PathBuf::from("tmp/cp77_comments");
+        let mod_dir = root.join("archive").join("pc").join("mod");
+        create_dir_all(&mod_dir).unwrap();
+        for name in ["a.archive", "b.archive", "c.archive"] {
+            fs::write(mod_dir.join(name), [0u8; 4]).unwrap();
+        }
+        fs::write(
+            mod_dir.join("modlist.txt"),
+            "# my order\n\nc.archive\n; b.archive is disabled\n  a.archive  \n",
+        )
+        .unwrap();
+
+        // comments and blank lines are skipped
+        let names = gather_cp77_mods(&root)
+            .into_iter()
+            .map(|m| m.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["c.archive", "a.archive", "b.archive"]);
+
+        // comments keep their position when the order is written
+        let result = ["a.archive", "b.archive", "c.archive"].map(String::from);
+        update_new_load_order(
+            ESupportedGame::Cyberpunk,
+            &root,
+            &result,
+            None::<PathBuf>,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(mod_dir.join("modlist.txt")).unwrap(),
+            "# my order\n\na.archive\nb.archive\nc.archive\n; b.archive is disabled\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_tes3_in_game_folder() {
+        // a game root that isn't the current working dir
+        let root = PathBuf::from("tmp/tes3_game");
+        let data_files = root.join("Data Files");
+        create_dir_all(&data_files).unwrap();
+        for name in ["a.esp", "b.esp", "inactive.esp"] {
+            fs::write(data_files.join(name), [0u8; 4]).unwrap();
+        }
+        fs::write(
+            root.join("Morrowind.ini"),
+            "[General]\nKey=Value\n[Game Files]\nGameFile0=a.esp\nGameFile1=b.esp\n",
+        )
+        .unwrap();
+
+        // only plugins of the ini in the game root are gathered
+        let mods = gather_tes3_mods(&root);
+        let mut names = mods.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.esp", "b.esp"]);
+
+        let result = sorter::new_stable_sorter()
+            .topo_sort(
+                ESupportedGame::Morrowind,
+                &mods,
+                &[Order::from("b.esp", "a.esp").into()],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(result, ["b.esp", "a.esp"]);
+
+        update_new_load_order(
+            ESupportedGame::Morrowind,
+            &root,
+            &result,
+            None::<PathBuf>,
+            false,
+        )
+        .unwrap();
+        let ini = fs::read_to_string(root.join("Morrowind.ini")).unwrap();
+        assert!(ini.contains("Key=Value"));
+        assert!(ini.contains("[Game Files]\nGameFile0=b.esp\nGameFile1=a.esp\n"));
+        assert!(!PathBuf::from("Morrowind.ini").exists());
+
+        // the plugins in the game root are redated
+        let mtime = |name: &str| {
+            fs::metadata(data_files.join(name))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        assert!(mtime("b.esp") < mtime("a.esp"));
+    }
+
+    #[test]
+    fn test_sort_modified_since() {
+        let root = PathBuf::from("tmp/tes3_since");
+        let data_files = root.join("Data Files");
+        create_dir_all(&data_files).unwrap();
+        for (name, time) in [("c.esp", 3000), ("a.esp", 1000), ("d.esp", 4000)] {
+            let path = data_files.join(name);
+            fs::write(&path, [0u8; 4]).unwrap();
+            set_file_mtime(&path, filetime::FileTime::from_unix_time(time, 0)).unwrap();
+        }
+        let plugins = ["c.esp", "A.esp", "d.esp"]
+            .map(|n| PluginData::new(n.into(), 4))
+            .to_vec();
+
+        let locked = get_tes3_plugins_modified_before(&root, &plugins, 2000);
+        assert_eq!(locked, ["a.esp"]);
+
+        // only the newer plugins are moved around the locked one
+        let order_rules = [Order::from("d.esp", "c.esp").into()];
+        let result = sorter::new_stable_sorter()
+            .topo_sort_locked(
+                ESupportedGame::Morrowind,
+                &plugins,
+                &locked,
+                &order_rules,
+                &[],
+            )
+            .unwrap();
+        assert_eq!(result, ["d.esp", "A.esp", "c.esp"]);
+    }
+
+    #[test]
+    fn test_update_tes3_no_redate() {
+        let root = PathBuf::from("tmp/tes3_no_redate");
+        let data_files = root.join("Data Files");
+        create_dir_all(&data_files).unwrap();
+        let time = filetime::FileTime::from_unix_time(1024695106, 0);
+        for name in ["a.esp", "b.esp"] {
+            let path = data_files.join(name);
+            fs::write(&path, [0u8; 4]).unwrap();
+            set_file_mtime(&path, time).unwrap();
+        }
+        fs::write(
+            root.join("Morrowind.ini"),
+            "[Game Files]\nGameFile0=a.esp\nGameFile1=b.esp\n",
+        )
+        .unwrap();
+
+        let result = ["b.esp".to_owned(), "a.esp".to_owned()];
+        update_new_load_order(
+            ESupportedGame::Morrowind,
+            &root,
+            &result,
+            None::<PathBuf>,
+            true,
+        )
+        .unwrap();
+
+        // the ini is updated
+        let ini = fs::read_to_string(root.join("Morrowind.ini")).unwrap();
+        assert!(ini.contains("[Game Files]\nGameFile0=b.esp\nGameFile1=a.esp\n"));
+
+        // the file times are unchanged
+        for name in ["a.esp", "b.esp"] {
+            let metadata = fs::metadata(data_files.join(name)).unwrap();
+            assert_eq!(
+                filetime::FileTime::from_last_modification_time(&metadata),
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn test_undo_load_order() {
+        let root = PathBuf::from("tmp/tes3_undo");
+        let data_files = root.join("Data Files");
+        create_dir_all(&data_files).unwrap();
+        for (name, time) in [("a.esp", 1000), ("b.esp", 2000)] {
+            let path = data_files.join(name);
+            fs::write(&path, [0u8; 4]).unwrap();
+            set_file_mtime(&path, filetime::FileTime::from_unix_time(time, 0)).unwrap();
+        }
+        let ini = "[General]\nKey=Value\n[Game Files]\nGameFile0=a.esp\nGameFile1=b.esp\n";
+        fs::write(root.join("Morrowind.ini"), ini).unwrap();
+
+        let result = ["b.esp".to_owned(), "a.esp".to_owned()];
+        update_new_load_order(
+            ESupportedGame::Morrowind,
+            &root,
+            &result,
+            None::<PathBuf>,
+            false,
+        )
+        .unwrap();
+        assert_ne!(fs::read_to_string(root.join("Morrowind.ini")).unwrap(), ini);
+
+        let applied = undo_load_order(ESupportedGame::Morrowind, &root, None::<PathBuf>).unwrap();
+        assert_eq!(
+            applied.path,
+            root.canonicalize().unwrap().join("Morrowind.ini")
+        );
+        assert!(!applied.created);
+
+        // the ini and the file times are restored
+        assert_eq!(fs::read_to_string(root.join("Morrowind.ini")).unwrap(), ini);
+        for (name, time) in [("a.esp", 1000), ("b.esp", 2000)] {
+            let metadata = fs::metadata(data_files.join(name)).unwrap();
+            assert_eq!(
+                filetime::FileTime::from_last_modification_time(&metadata),
+                filetime::FileTime::from_unix_time(time, 0)
+            );
+        }
+
+        // there is nothing left to undo
+        assert!(undo_load_order(ESupportedGame::Morrowind, &root, None::<PathBuf>).is_err());
+    }
+
+    #[test]
+    fn test_undo_load_order_per_config() {
+        let root = PathBuf::from("tmp/openmw_undo");
+        create_dir_all(&root).unwrap();
+        let configs = [root.join("a.cfg"), root.join("b.cfg")];
+        let cfg = "data=\"Data Files\"\ncontent=a.esp\ncontent=b.esp\n";
+        for config in &configs {
+            fs::write(config, cfg).unwrap();
+        }
+
+        // one sort run per config
+        let result = ["b.esp".to_owned(), "a.esp".to_owned()];
+        for config in &configs {
+            update_new_load_order(ESupportedGame::Openmw, &root, &result, Some(config), false)
+                .unwrap();
+            assert_ne!(fs::read_to_string(config).unwrap(), cfg);
+        }
+
+        // every config is restored on its own
+        for config in &configs {
+            let applied = undo_load_order(ESupportedGame::Openmw, &root, Some(config)).unwrap();
+            assert_eq!(applied.path, config.canonicalize().unwrap());
+            assert_eq!(fs::read_to_string(config).unwrap(), cfg);
+        }
+    }
+
+    #[test]
+    fn test_undo_load_order_created() {
+        let root = PathBuf::from("tmp/cp77_undo");
+        let mod_dir = root.join("archive").join("pc").join("mod");
+        create_dir_all(&mod_dir).unwrap();
+        let modlist = mod_dir.join("modlist.txt");
+        let _ = fs::remove_file(&modlist);
+
+        let result = ["b.archive".to_owned(), "a.archive".to_owned()];
+        update_new_load_order(
+            ESupportedGame::Cyberpunk,
+            &root,
+            &result,
+            None::<PathBuf>,
+            false,
+        )
+        .unwrap();
+        assert!(modlist.exists());
+
+        // the modlist.txt had no backup, undo removes it
+        let applied = undo_load_order(ESupportedGame::Cyberpunk, &root, None::<PathBuf>).unwrap();
+        assert!(applied.created);
+        assert!(applied.path.is_absolute());
+        assert!(!modlist.exists());
+    }
+
+    #[test]
+    fn test_update_openmw_keeps_other_lines() {
+        let dir = PathBuf::from("tmp");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("openmw_update.cfg");
+
+        let lines = [
+            "data=\"Data Files\"",
+            "# plugins",
+            "content=Morrowind.esm",
+            "groundcover=grass_a.esp",
+            "content=b.esp",
+            "# more plugins",
+            "content = a.esp",
+            "groundcover=grass_b.esp",
+            "fallback-archive=Morrowind.bsa",
+        ];
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let result = ["Morrowind.esm", "a.esp", "b.esp"].map(String::from);
+        update_new_load_order(ESupportedGame::Openmw, &dir, &result, Some(&path), false).unwrap();
+
+        let updated = fs::read_to_string(&path).unwrap();
+        let other = |l: &&str| !is_openmw_key(l, "content");
+        assert_eq!(
+            updated.lines().filter(other).collect::<Vec<_>>(),
+            lines.iter().copied().filter(other).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            updated
+                .lines()
+                .filter(|l| l.starts_with("content="))
+                .collect::<Vec<_>>(),
+            ["content=Morrowind.esm", "content=a.esp", "content=b.esp"]
+        );
+        // the plugins replace the first content line
+        assert_eq!(updated.lines().nth(2), Some("content=Morrowind.esm"));
+
+        // groundcover is written the same way and leaves the content lines alone
+        let groundcover = ["grass_b.esp", "grass_a.esp"].map(String::from);
+        update_openmw_groundcover(&groundcover, Some(&path)).unwrap();
+        let updated_groundcover = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            updated_groundcover
+                .lines()
+                .filter(|l| !l.starts_with("groundcover="))
+                .collect::<Vec<_>>(),
+            updated
+                .lines()
+                .filter(|l| !l.starts_with("groundcover="))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            updated_groundcover
+                .lines()
+                .filter(|l| l.starts_with("groundcover="))
+                .collect::<Vec<_>>(),
+            ["groundcover=grass_b.esp", "groundcover=grass_a.esp"]
+        );
+    }
+
+    #[test]
+    fn test_settings_from_file() {
+        let dir = PathBuf::from("tmp/settings");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("plox.toml");
+
+        // missing fields use their defaults
+        fs::write(&path, "game = \"Openmw\"\nrules_dir = \"my_rules\"\n").unwrap();
+        let settings = settings::Settings::try_from_file(&path).unwrap();
+        assert_eq!(settings.game, Some(ESupportedGame::Openmw));
+        assert_eq!(settings.rules_dir, Some("my_rules".to_owned()));
+        assert!(!settings.no_rules_download);
+        assert!(settings.log_level.is_none());
+
+        fs::write(&path, "no_rules_download = 1").unwrap();
+        assert!(settings::Settings::try_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let dir = PathBuf::from("tmp/corrupt_header");
+        create_dir_all(&dir).expect("could not create dir");
+        let path = dir.join("corrupt.esp");
+        fs::write(&path, b"not a plugin").expect("could not write file");
+
+        let data = map_data(&path).expect("no plugin data");
+        assert!(data.header_error.is_some());
+        assert!(data.masters.is_none());
+
+        let mut parser = parser::new_tes3_parser();
+        parser.evaluate_plugins(&[data]);
+        assert_eq!(1, parser.warnings.len());
+        assert!(parser.warnings[0]
+            .get_comment()
+            .starts_with("The header of corrupt.esp could not be read"));
+    }
+
+    #[test]
+    fn test_parse_game_version() {
+        assert_eq!(parse_game_version("2.1"), Ok(Version::new(2, 1, 0)));
+        assert_eq!(parse_game_version("1.6.1820"), Ok(Version::new(1, 6, 1820)));
+        assert!(parse_game_version("not a version").is_err());
+    }
+
+    #[test]
+    fn test_parse_release_tag() {
+        assert_eq!(parse_release_tag("v0.4.1"), Some(Version::new(0, 4, 1)));
+        assert_eq!(parse_release_tag("0.5"), Some(Version::new(0, 5, 0)));
+        assert!(parse_release_tag("nightly").is_none());
+    }
 }
@@ -1,10 +1,12 @@
 use std::path::PathBuf;
 use std::process::ExitCode;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use env_logger::Env;
 use log::{error, info};
 
+use plox::settings::{EnvSettings, Settings, PLOX_SETTINGS_FILE};
+use plox::sorter::ESortType;
 use plox::*;
 
 #[derive(Parser)]
@@ -14,7 +16,7 @@ struct Cli {
     #[arg(short, long)]
     log_level: Option<ELogLevel>,
 
-    /// Set the game to evaluate, if no game is specified it will attempt to deduce the game from the current working directory
+    /// Set the game to evaluate, if no game is specified it will attempt to deduce the game from the current working directory or its parents
     #[arg(short, long)]
     game: Option<ESupportedGame>,
 
@@ -22,6 +24,10 @@ struct Cli {
     #[arg(short, long)]
     non_interactive: bool,
 
+    /// Check if a newer plox version is available. Nothing is downloaded
+    #[arg(long)]
+    version_check: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -30,7 +36,7 @@ struct Cli {
 enum Command {
     /// Sorts the current mod load order according to specified rules
     Sort {
-        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is current working directory
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is the detected game root or the current working directory
         #[arg(short, long)]
         game_folder: Option<PathBuf>,
 
@@ -42,41 +48,195 @@ enum Command {
         #[arg(short, long)]
         dry_run: bool,
 
-        /// Use the potentially faster unstable sorter
+        /// Print only the final order to stdout, one plugin per line, without writing files. Implies --non-interactive
+        #[arg(long, conflicts_with = "check")]
+        stdout: bool,
+
+        /// Don't print notes. Conflicts, requires, patches and errors are still printed, the report, rule log and exit code are unaffected
         #[arg(short, long)]
+        quiet: bool,
+
+        /// (Morrowind only) Only update the [Game Files] of the Morrowind.ini, don't change the plugin file times
+        #[arg(long)]
+        no_redate: bool,
+
+        /// (Morrowind and OpenMW only) Don't add the built-in order of Morrowind.esm, Tribunal.esm and Bloodmoon.esm. Explain and graph always add it, verify only checks the rules
+        #[arg(long)]
+        no_builtin_order: bool,
+
+        /// (OpenMW only) Don't move masters (.esm and .omwgame) to the top, only the rules decide their position. Masters below other plugins are reported
+        #[arg(long)]
+        no_esm_hoist: bool,
+
+        /// Add a note if more plugins are active. Default is 255 for Morrowind and no limit for other games
+        #[arg(long)]
+        plugin_limit: Option<usize>,
+
+        /// Deprecated: use --sort-type unstable
+        #[arg(short, long, conflicts_with = "sort_type")]
         unstable: bool,
 
+        /// The sorter to use: unstable (fastest), stable (default) or full (reference implementation)
+        #[arg(long)]
+        sort_type: Option<ESortType>,
+
         /// Disable automatic downloading of latest ruleset
-        #[arg(short, long)]
+        #[arg(short, long, visible_alias = "offline")]
         no_download: bool,
 
         /// Read the input mods from a file instead of checking the root folder
         #[arg(short, long)]
         mod_list: Option<PathBuf>,
 
-        /// (OpenMW only) Path to the openmw.cfg file
+        /// The format of the mod list file: plain (one plugin per line) or wrye (Wrye Mash plugins list)
+        #[arg(long, value_enum, default_value_t = EModListFormat::Plain)]
+        mod_list_format: EModListFormat,
+
+        /// (OpenMW only) Path to the openmw.cfg file. Can be repeated to run once per config, output files can't be used then
         #[arg(short, long)]
-        config: Option<PathBuf>,
+        config: Vec<PathBuf>,
+
+        /// Only sort plugins matching this wildcard pattern, all other plugins keep their position
+        #[arg(short, long)]
+        only: Option<String>,
+
+        /// (Morrowind only) Plugins last modified before this unix timestamp keep their position, only newer plugins are sorted
+        #[arg(long, conflicts_with = "only")]
+        since: Option<u64>,
+
+        /// Write a report of all warnings and the load order to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Override the game version used to evaluate rules (e.g. "1.6.1820")
+        #[arg(long, value_parser = parse_game_version)]
+        game_version: Option<semver::Version>,
+
+        /// Download the rules from this base url instead of the default rules repository
+        #[arg(long)]
+        rules_url: Option<String>,
+
+        /// (OpenMW only) Also sort the groundcover entries of the openmw.cfg
+        #[arg(long)]
+        groundcover: bool,
+
+        /// Run both stable sorters and report if their results differ
+        #[arg(long)]
+        verify_sort: bool,
+
+        /// Read the plugins to ignore from this file instead of plox_ignore.txt in the rules dir
+        #[arg(long)]
+        ignore: Option<PathBuf>,
+
+        /// Don't sort the plugins of one side of each conflict, they keep their position. Interactive asks which plugins to keep
+        #[arg(long)]
+        conflict_strategy: Option<EConflictStrategy>,
+
+        /// Write every warning rule that fired as newline-delimited json to this file
+        #[arg(long)]
+        rule_log: Option<PathBuf>,
+
+        /// Write the order rules that apply to the sorted plugins as a LOOT userlist.yaml to this file
+        #[arg(long)]
+        loot_userlist: Option<PathBuf>,
+
+        /// Load rules with this tag even if it is disabled. Can be repeated
+        #[arg(long = "enable-tag")]
+        enable_tags: Vec<String>,
+
+        /// Don't load rules with this tag, "*" disables all tagged rules. Can be repeated
+        #[arg(long = "disable-tag")]
+        disable_tags: Vec<String>,
+
+        /// Only check the current load order and print all pairs that violate the order rules
+        #[arg(long)]
+        check: bool,
     },
     /// Lists the current mod load order
     List {
-        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is current working directory
-        #[arg(short, long)]
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is the detected game root or the current working directory
+        #[arg(short, long, visible_alias = "game-folder")]
         root: Option<PathBuf>,
 
-        /// (OpenMW only) Path to the openmw.cfg file
+        /// (OpenMW only) Path to the openmw.cfg file. Can be repeated to run once per config, output files can't be used then
         #[arg(short, long)]
-        config: Option<PathBuf>,
+        config: Vec<PathBuf>,
+
+        /// Write the load order to this file, one plugin per line. Can be read again with --mod-list
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Only list plugins that are targets of at least one warning
+        #[arg(long)]
+        problems_only: bool,
+
+        /// Mark plugins that any rule references with a *
+        #[arg(long)]
+        mark_referenced: bool,
+
+        /// Folder to read sorting rules from when listing problems or marking referenced plugins. Default is ./mlox for TES3
+        #[arg(long)]
+        rules_dir: Option<String>,
     },
     /// Verifies integrity of the specified rules
     Verify {
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). If given, also report rules that reference plugins which are not installed
+        #[arg(short, long)]
+        game_folder: Option<PathBuf>,
+
         /// Folder to read sorting rules from. Default is ./plox or ./mlox for TES3
         #[arg(short, long)]
         rules_dir: Option<String>,
+
+        /// Override the game version used to evaluate rules (e.g. "1.6.1820")
+        #[arg(long, value_parser = parse_game_version)]
+        game_version: Option<semver::Version>,
+
+        /// Print a machine-readable json report to stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Explains the placement of a plugin: its order rules, masters and warnings
+    Explain {
+        /// The plugin to explain
+        plugin: String,
+
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is the detected game root or the current working directory
+        #[arg(short, long)]
+        game_folder: Option<PathBuf>,
+
+        /// Folder to read sorting rules from. Default is ./mlox for TES3
+        #[arg(short, long)]
+        rules_dir: Option<String>,
+
+        /// Read the input mods from a file instead of checking the root folder
+        #[arg(short, long)]
+        mod_list: Option<PathBuf>,
+
+        /// The format of the mod list file: plain (one plugin per line) or wrye (Wrye Mash plugins list)
+        #[arg(long, value_enum, default_value_t = EModListFormat::Plain)]
+        mod_list_format: EModListFormat,
+
+        /// (OpenMW only) Path to the openmw.cfg file
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+
+        /// Override the game version used to evaluate rules (e.g. "1.6.1820")
+        #[arg(long, value_parser = parse_game_version)]
+        game_version: Option<semver::Version>,
+    },
+    /// Lists all rules that reference a plugin, wildcard rules included
+    Rules {
+        /// The plugin to look up
+        plugin: String,
+
+        /// Folder to read sorting rules from. Default is ./mlox for TES3
+        #[arg(short, long)]
+        rules_dir: Option<String>,
     },
     /// Outputs the rules as a graphviz dot file
     Graph {
-        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is current working directory
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is the detected game root or the current working directory
         #[arg(short, long)]
         game_folder: Option<PathBuf>,
 
@@ -88,48 +248,131 @@ enum Command {
         #[arg(short, long)]
         mod_list: Option<PathBuf>,
 
+        /// The format of the mod list file: plain (one plugin per line) or wrye (Wrye Mash plugins list)
+        #[arg(long, value_enum, default_value_t = EModListFormat::Plain)]
+        mod_list_format: EModListFormat,
+
         /// (OpenMW only) Path to the openmw.cfg file
         #[arg(short, long)]
         config: Option<PathBuf>,
+
+        /// Override the game version used to evaluate rules (e.g. "1.6.1820")
+        #[arg(long, value_parser = parse_game_version)]
+        game_version: Option<semver::Version>,
+
+        /// Write all edges of the graph as json to this file, tagged by their origin (order or master)
+        #[arg(long)]
+        dump_edges: Option<PathBuf>,
+    },
+    /// Restores the load order from before the last sort
+    Undo {
+        /// Root game folder (e.g. "Cyberpunk 2077" or "Morrowind"). Default is the detected game root or the current working directory
+        #[arg(short, long)]
+        game_folder: Option<PathBuf>,
+
+        /// (OpenMW only) Path to the openmw.cfg file. Can be repeated to undo once per config
+        #[arg(short, long)]
+        config: Vec<PathBuf>,
+    },
+    /// Reports the rules added, removed and changed between two rules files
+    Diff {
+        /// The old rules file
+        old: PathBuf,
+
+        /// The new rules file
+        new: PathBuf,
+    },
+    /// Prints a rules file in the canonical rule format
+    Format {
+        /// The rules file to format
+        file: PathBuf,
+
+        /// Write the formatted rules to this file instead of stdout. The input file can't be rewritten
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 }
 
 fn main() -> ExitCode {
+    // checked before parsing so that argument errors don't prompt either
+    let env_non_interactive = EnvSettings::non_interactive();
     let cli = match Cli::try_parse() {
         Ok(cli) => cli,
         Err(e) => {
             eprintln!("{}", e);
 
-            println!("\nPress any button to continue");
-            let mut buffer = String::new();
-            let _ = std::io::stdin().read_line(&mut buffer);
+            if !env_non_interactive {
+                println!("\nPress any button to continue");
+                let mut buffer = String::new();
+                let _ = std::io::stdin().read_line(&mut buffer);
+            }
 
             return ExitCode::FAILURE;
         }
     };
 
+    // defaults from plox.toml, command line flags take precedence
+    let settings_path = PathBuf::from(PLOX_SETTINGS_FILE);
+    let mut settings = if settings_path.exists() {
+        Settings::try_from_file(&settings_path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Settings::default()
+        })
+    } else {
+        Settings::default()
+    };
+    // environment variables take precedence over plox.toml
+    let env_settings = EnvSettings::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        EnvSettings::default()
+    });
+    settings.game = env_settings.game.or(settings.game);
+    settings.rules_dir = env_settings.rules_dir.clone().or(settings.rules_dir);
+    let command = apply_settings(cli.command.clone(), &settings);
+    // the prompt would end up in a piped order
+    let non_interactive = cli.non_interactive
+        || env_non_interactive
+        || matches!(
+            command,
+            Command::Sort { stdout: true, .. } | Command::Format { output: None, .. }
+        );
+
     // logger
     let mut level = ELogLevel::Info;
-    if let Some(lvl) = cli.log_level {
+    if let Some(lvl) = cli.log_level.or_else(|| {
+        settings
+            .log_level
+            .as_ref()
+            .and_then(|l| ELogLevel::from_str(l, true).ok())
+    }) {
         level = lvl;
     }
     let env = Env::default()
         .default_filter_or(log_level_to_str(level))
         .default_write_style_or("always");
+    // logs go to stderr so stdout only holds command output
     env_logger::Builder::from_env(env)
         .format_timestamp(None)
+        .target(env_logger::Target::Stderr)
         .init();
 
+    if cli.version_check {
+        check_for_update();
+    }
+
     // detect game
-    let game = if let Some(game) = cli.game {
+    let (game, detected_root) = if let Some(game) = cli.game.or(settings.game) {
         info!("Set game to: {:?}", game);
-        game
-    } else if let Some(g) = detect_game() {
-        info!("Detected game: {:?}", g);
-        g
+        (game, env_settings.game_root.clone())
+    } else if let Some((g, root)) = match &env_settings.game_root {
+        Some(root) => detect_game_in(root),
+        None => detect_game(),
+    } {
+        info!("Detected game: {:?} in {}", g, root.display());
+        (g, Some(root))
     } else {
         error!("No game specified or detected");
-        if !cli.non_interactive {
+        if !non_interactive {
             println!("Press any button to continue");
             let mut buffer = String::new();
             let _ = std::io::stdin().read_line(&mut buffer);
@@ -137,36 +380,170 @@ fn main() -> ExitCode {
         return ExitCode::FAILURE;
     };
 
-    let code = match &cli.command {
-        Command::List { root, config } => list_mods(root, game, config.clone()),
-        Command::Verify { rules_dir } => verify(game, rules_dir),
+    // a detected game root is used when no game folder is given
+    let game_folder_or_detected =
+        |folder: &Option<PathBuf>| folder.clone().or_else(|| detected_root.clone());
+
+    // conflicts can't be resolved interactively without user input
+    if non_interactive
+        && matches!(
+            command,
+            Command::Sort {
+                conflict_strategy: Some(EConflictStrategy::Interactive),
+                ..
+            }
+        )
+    {
+        error!("The interactive conflict strategy needs user input");
+        return ExitCode::FAILURE;
+    }
+
+    // every run would overwrite the same output file
+    if let Some(option) = get_per_config_output_option(&command) {
+        error!("{} can't be used with more than one --config", option);
+        return ExitCode::FAILURE;
+    }
+
+    let code = match &command {
+        Command::List {
+            root,
+            config,
+            output,
+            problems_only,
+            mark_referenced,
+            rules_dir,
+        } => run_per_config(config, |config| {
+            list_mods(
+                &game_folder_or_detected(root),
+                game,
+                config,
+                output,
+                *problems_only,
+                *mark_referenced,
+                rules_dir,
+            )
+        }),
+        Command::Verify {
+            game_folder,
+            rules_dir,
+            game_version,
+            json,
+        } => verify(game, game_folder, rules_dir, game_version.clone(), *json),
+        Command::Explain {
+            plugin,
+            game_folder,
+            rules_dir,
+            mod_list,
+            mod_list_format,
+            config,
+            game_version,
+        } => explain(
+            game,
+            plugin,
+            &game_folder_or_detected(game_folder),
+            rules_dir,
+            mod_list,
+            *mod_list_format,
+            config.clone(),
+            game_version.clone(),
+        ),
+        Command::Rules { plugin, rules_dir } => lookup_rules(game, plugin, rules_dir),
         Command::Graph {
             game_folder,
             rules_dir,
             mod_list,
+            mod_list_format,
             config,
-        } => graph(game, game_folder, rules_dir, mod_list, config.clone()),
+            game_version,
+            dump_edges,
+        } => graph(
+            game,
+            &game_folder_or_detected(game_folder),
+            rules_dir,
+            mod_list,
+            *mod_list_format,
+            config.clone(),
+            game_version.clone(),
+            dump_edges,
+        ),
+        Command::Format { file, output } => format(game, file, output),
+        Command::Diff { old, new } => diff(game, old, new),
+        Command::Undo {
+            game_folder,
+            config,
+        } => run_per_config(config, |config| {
+            undo(game, &game_folder_or_detected(game_folder), config)
+        }),
         Command::Sort {
             game_folder: root,
             rules_dir,
             mod_list,
+            mod_list_format,
             dry_run,
+            stdout,
+            quiet,
+            no_redate,
+            no_builtin_order,
+            no_esm_hoist,
+            plugin_limit,
             unstable,
+            sort_type,
             no_download,
             config,
-        } => sort(CliSortOptions {
-            game,
-            game_folder: root.clone(),
-            rules_dir: rules_dir.clone(),
-            mod_list: mod_list.clone(),
-            dry_run: *dry_run,
-            unstable: *unstable,
-            no_download: *no_download,
-            config: config.clone(),
+            only,
+            since,
+            report,
+            rules_url,
+            game_version,
+            groundcover,
+            verify_sort,
+            ignore,
+            conflict_strategy,
+            rule_log,
+            loot_userlist,
+            enable_tags,
+            disable_tags,
+            check,
+        } => run_per_config(config, |config| {
+            sort(CliSortOptions {
+                game,
+                game_folder: game_folder_or_detected(root),
+                rules_dir: rules_dir.clone(),
+                mod_list: mod_list.clone(),
+                mod_list_format: *mod_list_format,
+                dry_run: *dry_run,
+                stdout: *stdout,
+                quiet: *quiet,
+                no_redate: *no_redate,
+                no_builtin_order: *no_builtin_order,
+                no_esm_hoist: *no_esm_hoist,
+                plugin_limit: *plugin_limit,
+                sort_type: sort_type.unwrap_or(if *unstable {
+                    ESortType::Unstable
+                } else {
+                    ESortType::StableOpt
+                }),
+                no_download: *no_download,
+                config,
+                only: only.clone(),
+                since: *since,
+                report: report.clone(),
+                rules_url: rules_url.clone(),
+                game_version: game_version.clone(),
+                groundcover: *groundcover,
+                verify_sort: *verify_sort,
+                ignore: ignore.clone(),
+                conflict_strategy: *conflict_strategy,
+                rule_log: rule_log.clone(),
+                loot_userlist: loot_userlist.clone(),
+                enable_tags: enable_tags.clone(),
+                disable_tags: disable_tags.clone(),
+                check: *check,
+            })
         }),
     };
 
-    if !cli.non_interactive {
+    if !non_interactive {
         println!("\nPress any button to continue");
         let mut buffer = String::new();
         let _ = std::io::stdin().read_line(&mut buffer);
@@ -174,3 +551,114 @@ fn main() -> ExitCode {
 
     code
 }
+
+/// Runs a command once per config and labels the output of each run with its config path.
+/// Without a config the command runs once. The exit code of the first failed run is returned
+fn run_per_config(
+    configs: &[PathBuf],
+    mut run: impl FnMut(Option<PathBuf>) -> ExitCode,
+) -> ExitCode {
+    if configs.len() < 2 {
+        return run(configs.first().cloned());
+    }
+
+    let mut code = ExitCode::SUCCESS;
+    for config in configs {
+        info!("Running for config {}", config.display());
+        let result = run(Some(config.clone()));
+        if code == ExitCode::SUCCESS {
+            code = result;
+        }
+    }
+    code
+}
+
+/// Returns the first output file option that is set while the command runs for more than one config
+fn get_per_config_output_option(command: &Command) -> Option<&'static str> {
+    match command {
+        Command::List { config, output, .. } if config.len() > 1 => {
+            output.as_ref().map(|_| "--output")
+        }
+        Command::Sort {
+            config,
+            report,
+            rule_log,
+            loot_userlist,
+            ..
+        } if config.len() > 1 => [
+            (report, "--report"),
+            (rule_log, "--rule-log"),
+            (loot_userlist, "--loot-userlist"),
+        ]
+        .into_iter()
+        .find(|(path, _)| path.is_some())
+        .map(|(_, option)| option),
+        _ => None,
+    }
+}
+
+/// Fills all options that are not set on the command line from the settings file
+fn apply_settings(mut command: Command, settings: &Settings) -> Command {
+    match &mut command {
+        Command::Sort {
+            rules_dir,
+            no_download,
+            config,
+            rules_url,
+            ..
+        } => {
+            *rules_dir = rules_dir.take().or_else(|| settings.rules_dir.clone());
+            *no_download |= settings.no_rules_download;
+            if config.is_empty() {
+                config.extend(settings.config.clone());
+            }
+            *rules_url = rules_url.take().or_else(|| settings.rules_url.clone());
+        }
+        Command::List {
+            config, rules_dir, ..
+        } => {
+            if config.is_empty() {
+                config.extend(settings.config.clone());
+            }
+            *rules_dir = rules_dir.take().or_else(|| settings.rules_dir.clone());
+        }
+        Command::Verify { rules_dir, .. } | Command::Rules { rules_dir, .. } => {
+            *rules_dir = rules_dir.take().or_else(|| settings.rules_dir.clone());
+        }
+        Command::Undo { config, .. } => {
+            if config.is_empty() {
+                config.extend(settings.config.clone());
+            }
+        }
+        Command::Format { .. } | Command::Diff { .. } => {}
+        Command::Graph {
+            rules_dir, config, ..
+        }
+        | Command::Explain {
+            rules_dir, config, ..
+        } => {
+            *rules_dir = rules_dir.take().or_else(|| settings.rules_dir.clone());
+            *config = config.take().or_else(|| settings.config.clone());
+        }
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_alias() {
+        for flag in ["--offline", "--no-download"] {
+            let cli = Cli::try_parse_from(["plox", "sort", flag]).expect("failed to parse");
+            assert!(matches!(
+                cli.command,
+                Command::Sort {
+                    no_download: true,
+                    ..
+                }
+            ));
+        }
+    }
+}
@@ -1,13 +1,17 @@
 ////////////////////////////////////////////////////////////////////////
 
 use std::fs::File;
-use std::io::{BufRead, BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Cursor, Error, Read, Result, Seek, SeekFrom};
 use std::path::Path;
 
 use byteorder::ReadBytesExt;
+use ini::Ini;
 use log::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{expressions::*, rules::*, ESupportedGame, PluginData, TParser};
+use crate::{
+    expressions::*, normalize_name, rules::*, wild_contains, ESupportedGame, PluginData, TParser,
+};
 
 pub fn get_parser(game: ESupportedGame) -> Parser {
     match game {
@@ -41,6 +45,18 @@ pub fn new_openmw_parser() -> Parser {
     )
 }
 
+/// A rule that could not be parsed
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleParseError {
+    pub file: String,
+    pub line: usize,
+    pub reason: String,
+    pub text: String,
+}
+
+/// A parsed rule, the line it starts at and its tags
+type LineRule = (ERule, usize, Vec<String>);
+
 #[derive(Debug)]
 struct ChunkWrapper {
     data: Vec<u8>,
@@ -53,9 +69,18 @@ impl ChunkWrapper {
     }
 }
 
+/// The rules file and line a rule was parsed from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSource {
+    pub file: String,
+    pub line: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Warning {
     pub rule: EWarningRule,
+    /// where the rule was parsed from, None for built-in checks
+    pub source: Option<RuleSource>,
 }
 
 impl Warning {
@@ -75,14 +100,202 @@ impl Warning {
     }
 }
 
+/// Checks all plugins for masters that are not installed and creates a Requires warning for each
+pub fn get_missing_master_warnings(plugins: &[PluginData]) -> Vec<Warning> {
+    let names = plugins
+        .iter()
+        .map(|p| normalize_name(&p.name))
+        .collect::<Vec<_>>();
+
+    let mut warnings = vec![];
+    for plugin in plugins {
+        if let Some(masters) = &plugin.masters {
+            for (master, _size) in masters {
+                let master = normalize_name(master);
+                if wild_contains(&names, &master).is_some() {
+                    continue;
+                }
+
+                let mut rule = Requires::new(
+                    format!(
+                        "{} requires master {} which is not installed.",
+                        plugin.name, master
+                    ),
+                    Atomic::from(normalize_name(&plugin.name)).into(),
+                    Atomic::from(master).into(),
+                );
+                rule.plugins.push(normalize_name(&plugin.name));
+                warnings.push(Warning {
+                    rule: rule.into(),
+                    source: None,
+                });
+            }
+        }
+    }
+    warnings
+}
+
+/// Creates a Note warning for each plugin whose header could not be read
+pub fn get_header_error_warnings(plugins: &[PluginData]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for plugin in plugins {
+        if let Some(error) = &plugin.header_error {
+            let mut rule = Note::new(
+                format!(
+                    "The header of {} could not be read ({}), it is not guaranteed to load after its masters.",
+                    plugin.name, error
+                ),
+                &[Atomic::from(normalize_name(&plugin.name)).into()],
+            );
+            rule.plugins.push(normalize_name(&plugin.name));
+            warnings.push(Warning {
+                rule: rule.into(),
+                source: None,
+            });
+        }
+    }
+    warnings
+}
+
+/// Creates a Note warning for each plugin that has a same-named bsa in Data Files
+/// which is not registered in the [Archives] section of Morrowind.ini
+pub fn get_unregistered_bsa_warnings(root: &Path, plugins: &[PluginData]) -> Vec<Warning> {
+    let mut warnings = vec![];
+
+    // collect all bsa files in Data Files
+    let Ok(entries) = std::fs::read_dir(root.join("Data Files")) else {
+        return warnings;
+    };
+    let archives = entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_ascii_lowercase()))
+        .filter(|n| n.ends_with(".bsa"))
+        .collect::<Vec<_>>();
+    if archives.is_empty() {
+        return warnings;
+    }
+
+    // collect all registered archives
+    let mut registered = vec![];
+    if let Ok(ini) = Ini::load_from_file_noescape(root.join("Morrowind.ini")) {
+        if let Some(section) = ini
+            .sections()
+            .flatten()
+            .find(|s| s.eq_ignore_ascii_case("Archives"))
+        {
+            if let Some(props) = ini.section(Some(section)) {
+                registered = props
+                    .iter()
+                    .map(|(_, v)| v.trim().to_ascii_lowercase())
+                    .collect::<Vec<_>>();
+            }
+        }
+    }
+
+    for plugin in plugins {
+        let name = normalize_name(&plugin.name);
+        let Some(stem) = name
+            .strip_suffix(".esp")
+            .or_else(|| name.strip_suffix(".esm"))
+        else {
+            continue;
+        };
+
+        let bsa = format!("{}.bsa", stem);
+        if !archives.contains(&bsa) || registered.contains(&bsa) {
+            continue;
+        }
+
+        let mut rule = Note::new(
+            format!(
+                "{} has an archive {} which is not registered in the [Archives] section of Morrowind.ini.",
+                plugin.name, bsa
+            ),
+            &[Atomic::from(name.clone()).into()],
+        );
+        rule.plugins.push(name);
+        warnings.push(Warning {
+            rule: rule.into(),
+            source: None,
+        });
+    }
+    warnings
+}
+
+/// The number of plugins the engine can load, None if there is no practical limit
+pub fn get_default_plugin_limit(game: ESupportedGame) -> Option<usize> {
+    match game {
+        ESupportedGame::Morrowind => Some(255),
+        ESupportedGame::Openmw | ESupportedGame::Cyberpunk => None,
+    }
+}
+
+/// Creates a Note warning if more plugins than the limit are active
+pub fn get_plugin_count_warnings(plugins: &[PluginData], limit: usize) -> Vec<Warning> {
+    if plugins.len() <= limit {
+        return vec![];
+    }
+
+    let rule = Note::new(
+        format!(
+            "You have {} active plugins, more than the limit of {}. Plugins after the limit may not load.",
+            plugins.len(),
+            limit
+        ),
+        &[],
+    );
+    vec![Warning {
+        rule: rule.into(),
+        source: None,
+    }]
+}
+
+/// Order rules that are applied even if the rules don't cover them: the Bethesda masters load in release order
+pub fn get_builtin_order_rules(game: ESupportedGame) -> Vec<EOrderRule> {
+    match game {
+        ESupportedGame::Morrowind | ESupportedGame::Openmw => vec![Order::new(vec![
+            "morrowind.esm".into(),
+            "tribunal.esm".into(),
+            "bloodmoon.esm".into(),
+        ])
+        .with_comment("The expansions load after Morrowind.esm, Tribunal before Bloodmoon.")
+        .into()],
+        ESupportedGame::Cyberpunk => vec![],
+    }
+}
+
+/// The serialized rules of a parser, see Parser::to_json
+#[derive(Serialize, Deserialize)]
+struct ParserDocument {
+    game: ESupportedGame,
+    ext: Vec<String>,
+    order_rules: Vec<EOrderRule>,
+    #[serde(default)]
+    order_sources: Vec<RuleSource>,
+    warning_rules: Vec<EWarningRule>,
+    #[serde(default)]
+    warning_sources: Vec<RuleSource>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Parser {
     pub game: ESupportedGame,
+    pub game_version: Option<semver::Version>,
     pub ext: Vec<String>,
 
     pub order_rules: Vec<EOrderRule>,
+    /// the source of each parsed order rule, by index into order_rules
+    pub order_sources: Vec<RuleSource>,
     pub warning_rules: Vec<EWarningRule>,
+    /// the source of each parsed warning rule, by index into warning_rules
+    pub warning_sources: Vec<RuleSource>,
     pub warnings: Vec<Warning>,
+    /// rules that failed to parse
+    pub parse_errors: Vec<RuleParseError>,
+    /// tags of rules that are loaded even if they are disabled
+    pub enabled_tags: Vec<String>,
+    /// tags of rules that are not loaded, "*" disables all tagged rules
+    pub disabled_tags: Vec<String>,
 }
 
 impl Parser {
@@ -90,34 +303,141 @@ impl Parser {
         Self {
             ext,
             game,
+            game_version: None,
             warning_rules: vec![],
+            warning_sources: vec![],
             order_rules: vec![],
+            order_sources: vec![],
             warnings: vec![],
+            parse_errors: vec![],
+            enabled_tags: vec![],
+            disabled_tags: vec![],
+        }
+    }
+
+    /// Sets the tags that control which tagged rules are loaded. Untagged rules are always loaded,
+    /// tagged rules are loaded unless one of their tags is disabled and not enabled
+    pub fn set_tags(&mut self, enabled_tags: &[String], disabled_tags: &[String]) {
+        self.enabled_tags = enabled_tags
+            .iter()
+            .map(|t| t.to_ascii_lowercase())
+            .collect();
+        self.disabled_tags = disabled_tags
+            .iter()
+            .map(|t| t.to_ascii_lowercase())
+            .collect();
+    }
+
+    /// Checks if a rule with these tags is loaded
+    pub fn is_tag_enabled(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| {
+            let disabled = self.disabled_tags.iter().any(|t| t == tag || t == "*");
+            !disabled || self.enabled_tags.contains(tag)
+        })
+    }
+
+    /// Serializes the game, extensions and all parsed rules with their sources to a single json document
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if serialization fails
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&ParserDocument {
+            game: self.game,
+            ext: self.ext.clone(),
+            order_rules: self.order_rules.clone(),
+            order_sources: self.order_sources.clone(),
+            warning_rules: self.warning_rules.clone(),
+            warning_sources: self.warning_sources.clone(),
+        })
+    }
+
+    /// Reconstructs a parser from a json document written by to_json
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the document is not valid
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let document: ParserDocument = serde_json::from_str(json)?;
+        let mut parser = Parser::new(document.ext, document.game);
+        parser.order_rules = document.order_rules;
+        parser.order_sources = document.order_sources;
+        parser.warning_rules = document.warning_rules;
+        parser.warning_sources = document.warning_sources;
+        Ok(parser)
+    }
+
+    /// Sets the game version rules are evaluated against
+    pub fn set_game_version(&mut self, game_version: Option<semver::Version>) {
+        if let Some(version) = &game_version {
+            info!("Using game version {}", version);
         }
+        self.game_version = game_version;
     }
 
     /// Evaluates all warning rules and stores a copy of them in self
     /// Retrieve them with self.warnings
     pub fn evaluate_plugins(&mut self, plugins: &[PluginData]) {
-        // lowercase all plugin names
+        // normalize all plugin names
         let mods_cpy: Vec<_> = plugins
             .iter()
             .map(|f| {
                 let mut x = f.clone();
-                let name_lc = x.name.to_lowercase();
-                x.name = name_lc;
+                x.name = normalize_name(&x.name);
                 x
             })
             .collect();
 
+        let ctx = EvalContext::new(self.game, self.game_version.clone(), &mods_cpy);
+        let mut result = self.evaluate_warning_rules(&ctx);
+
+        // built-in checks
+        result.extend(get_missing_master_warnings(&mods_cpy));
+        result.extend(get_header_error_warnings(&mods_cpy));
+
+        self.warnings = result;
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn evaluate_warning_rules(&mut self, ctx: &EvalContext) -> Vec<Warning> {
         let mut result = vec![];
-        for rule in &mut self.warning_rules {
-            if rule.eval(&mods_cpy) {
-                result.push(Warning { rule: rule.clone() });
+        for (idx, rule) in self.warning_rules.iter_mut().enumerate() {
+            if rule.eval(ctx) {
+                result.push(Warning {
+                    rule: rule.clone(),
+                    source: self.warning_sources.get(idx).cloned(),
+                });
             }
         }
+        result
+    }
 
-        self.warnings = result;
+    /// Evaluates the warning rules in parallel, each rule only mutates itself
+    #[cfg(feature = "parallel")]
+    fn evaluate_warning_rules(&mut self, ctx: &EvalContext) -> Vec<Warning> {
+        use rayon::prelude::*;
+
+        let sources = &self.warning_sources;
+        let mut result = self
+            .warning_rules
+            .par_iter_mut()
+            .enumerate()
+            .filter_map(|(idx, rule)| {
+                rule.eval(ctx).then(|| {
+                    (
+                        idx,
+                        Warning {
+                            rule: rule.clone(),
+                            source: sources.get(idx).cloned(),
+                        },
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // keep the order of the rules files
+        result.sort_by_key(|(idx, _)| *idx);
+        result.into_iter().map(|(_, w)| w).collect()
     }
 
     /// Parse rules for a specific game from a file and stores them in self.
@@ -134,23 +454,60 @@ impl Parser {
             return Ok(());
         }
 
-        let rules = self.parse_rules_from_path(&path)?;
+        let file = File::open(&path)?;
+        let (rules, errors) = self.parse_rules_with_lines(BufReader::new(file))?;
+        let file_name = path
+            .as_ref()
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.parse_errors.extend(errors.into_iter().map(|mut e| {
+            e.file.clone_from(&file_name);
+            e
+        }));
         info!(
             "Parsed file {} with {} rules",
             path.as_ref().display(),
             rules.len()
         );
 
-        for r in rules {
+        let mut disabled = 0;
+        for (r, line, tags) in rules {
+            if !self.is_tag_enabled(&tags) {
+                debug!(
+                    "Skipping rule at {}:{} with tags {:?}",
+                    file_name, line, tags
+                );
+                disabled += 1;
+                continue;
+            }
+
             match r {
                 ERule::EOrderRule(o) => {
+                    // keep the sources aligned with rules added without one
+                    self.order_sources
+                        .resize(self.order_rules.len(), RuleSource::default());
                     self.order_rules.push(o);
+                    self.order_sources.push(RuleSource {
+                        file: file_name.clone(),
+                        line,
+                    });
                 }
                 ERule::EWarningRule(w) => {
+                    // keep the sources aligned with rules added without one
+                    self.warning_sources
+                        .resize(self.warning_rules.len(), RuleSource::default());
                     self.warning_rules.push(w);
+                    self.warning_sources.push(RuleSource {
+                        file: file_name.clone(),
+                        line,
+                    });
                 }
             }
         }
+        if disabled > 0 {
+            info!("Skipped {} rules with disabled tags", disabled);
+        }
 
         Ok(())
     }
@@ -165,14 +522,21 @@ impl Parser {
         P: AsRef<Path>,
     {
         self.warning_rules.clear();
+        self.warning_sources.clear();
         self.order_rules.clear();
+        self.order_sources.clear();
+        self.parse_errors.clear();
 
         let rules_files = match self.game {
             ESupportedGame::Morrowind | ESupportedGame::Openmw => {
-                ["mlox_base.txt", "mlox_user.txt", "mlox_my_rules.txt"].as_slice()
+                let mut files = vec!["mlox_base.txt".to_owned(), "mlox_user.txt".to_owned()];
+                files.extend(get_rule_fragments(path.as_ref(), "mlox_user_"));
+                files.push("mlox_my_rules.txt".to_owned());
+                files
             }
-            ESupportedGame::Cyberpunk => ["plox_base.txt", "plox_my_rules.txt"].as_slice(),
+            ESupportedGame::Cyberpunk => vec!["plox_base.txt".into(), "plox_my_rules.txt".into()],
         };
+        info!("Loading rules files in order: {}", rules_files.join(", "));
 
         for file in rules_files {
             let path = path.as_ref().join(file);
@@ -190,27 +554,53 @@ impl Parser {
         Ok(())
     }
 
-    /// Parse rules from a rules file
+    /// Parse rules from a reader
     ///
     /// # Errors
     ///
-    /// This function will return an error if file io or parsing fails
-    fn parse_rules_from_path<P>(&self, path: P) -> Result<Vec<ERule>>
+    /// This function will return an error if parsing fails
+    pub fn parse_rules_from_reader<R>(&self, reader: R) -> Result<Vec<ERule>>
     where
-        P: AsRef<Path>,
+        R: Read + BufRead + Seek,
     {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let rules = self.parse_rules_from_reader(reader)?;
-        Ok(rules)
+        Ok(self.parse_rules_from_reader_with_errors(reader)?.0)
     }
 
-    /// Parse rules from a reader
+    /// Parse exactly one rule from a string
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if parsing fails or the input does not contain exactly one rule
+    pub fn parse_single_rule(&self, input: &str) -> Result<ERule> {
+        let mut rules = self.parse_rules_from_reader(Cursor::new(input.as_bytes()))?;
+        match rules.len() {
+            1 => Ok(rules.remove(0)),
+            0 => Err(Error::other("Parsing error: no rule found")),
+            n => Err(Error::other(format!(
+                "Parsing error: expected one rule, found {}",
+                n
+            ))),
+        }
+    }
+
+    /// Parse rules from a reader and returns all rules that could not be parsed alongside
     ///
     /// # Errors
     ///
     /// This function will return an error if parsing fails
-    pub fn parse_rules_from_reader<R>(&self, reader: R) -> Result<Vec<ERule>>
+    pub fn parse_rules_from_reader_with_errors<R>(
+        &self,
+        reader: R,
+    ) -> Result<(Vec<ERule>, Vec<RuleParseError>)>
+    where
+        R: Read + BufRead + Seek,
+    {
+        let (rules, errors) = self.parse_rules_with_lines(reader)?;
+        Ok((rules.into_iter().map(|(rule, _, _)| rule).collect(), errors))
+    }
+
+    /// Parse rules from a reader alongside the line each rule starts at
+    fn parse_rules_with_lines<R>(&self, reader: R) -> Result<(Vec<LineRule>, Vec<RuleParseError>)>
     where
         R: Read + BufRead + Seek,
     {
@@ -218,12 +608,15 @@ impl Parser {
         let mut chunks: Vec<ChunkWrapper> = vec![];
         let mut chunk: Option<ChunkWrapper> = None;
         for (idx, line) in reader.lines().map_while(Result::ok).enumerate() {
+            // lines() only removes the \r of a CRLF line ending, strip any left over from mixed line endings
+            let line = line.trim_end_matches('\r');
+
             // ignore comments
             if line.trim_start().starts_with(';') {
                 continue;
             }
-            // lowercase all
-            let mut line = line.to_lowercase();
+            // lowercase all, only ascii like plugin names
+            let mut line = line.to_ascii_lowercase();
 
             // trim inline comments
             line = if let Some(index) = line.find(';') {
@@ -242,6 +635,7 @@ impl Parser {
                 line.starts_with("[order")
                     || line.starts_with("[nearstart")
                     || line.starts_with("[nearend")
+                    || line.starts_with("[override")
                     || line.starts_with("[note")
                     || line.starts_with("[conflict")
                     || line.starts_with("[requires")
@@ -249,7 +643,8 @@ impl Parser {
             }
 
             // we are inside a chunk
-            if chunk.is_some() && new_rule(&line) {
+            // unknown directives start a chunk of their own so they don't end up in the previous rule
+            if chunk.is_some() && (new_rule(&line) || get_unknown_keyword(&line).is_some()) {
                 // end current chunk
                 if let Some(chunk) = chunk.take() {
                     chunks.push(chunk);
@@ -273,14 +668,15 @@ impl Parser {
         }
 
         // process chunks
-        let mut rules: Vec<ERule> = vec![];
+        let mut rules: Vec<LineRule> = vec![];
+        let mut errors: Vec<RuleParseError> = vec![];
         for (idx, chunk) in chunks.into_iter().enumerate() {
             let info = &chunk.info;
 
             let cursor = Cursor::new(&chunk.data);
             match self.parse_chunk(cursor) {
-                Ok(it) => {
-                    rules.push(it);
+                Ok((it, tags)) => {
+                    rules.push((it, info.parse().unwrap_or_default(), tags));
                 }
                 Err(err) => {
                     // log error and skip chunk
@@ -290,19 +686,25 @@ impl Parser {
                     );
                     let string = String::from_utf8(chunk.data).expect("not valid utf8");
                     debug!("{}", string);
+                    errors.push(RuleParseError {
+                        file: String::new(),
+                        line: info.parse().unwrap_or_default(),
+                        reason: err.to_string(),
+                        text: string,
+                    });
                 }
             };
         }
 
-        Ok(rules)
+        Ok((rules, errors))
     }
 
-    /// Parses on rule section. Note: Order rules are returned as vec
+    /// Parses on rule section and returns the rule with its tags. Note: Order rules are returned as vec
     ///
     /// # Errors
     ///
     /// This function will return an error if parsing fails
-    fn parse_chunk<R>(&self, mut reader: R) -> Result<ERule>
+    fn parse_chunk<R>(&self, mut reader: R) -> Result<(ERule, Vec<String>)>
     where
         R: Read + BufRead + Seek,
     {
@@ -314,15 +716,26 @@ impl Parser {
                 // read until the end of the rule expression: e.g. [NOTE comment] body
                 if let Ok((mut rule_expression, ruletype)) = parse_rule_expression(&mut reader) {
                     rule_expression.pop();
+                    let (rule_expression, tags) = split_rule_tags(&rule_expression);
                     let mut rule: ERule;
                     // parse rule name
                     {
-                        if rule_expression.strip_prefix("order").is_some() {
-                            rule = Order::default().into();
-                        } else if rule_expression.strip_prefix("nearstart").is_some() {
-                            rule = NearStart::default().into();
-                        } else if rule_expression.strip_prefix("nearend").is_some() {
-                            rule = NearEnd::default().into();
+                        if let Some(rest) = rule_expression.strip_prefix("order") {
+                            rule = Order::default().with_comment(rest.trim()).into();
+                        } else if let Some(rest) = rule_expression.strip_prefix("nearstart") {
+                            rule = NearStart::default().with_comment(rest.trim()).into();
+                        } else if let Some(rest) = rule_expression.strip_prefix("nearend") {
+                            rule = NearEnd::default().with_comment(rest.trim()).into();
+                        } else if let Some(rest) = rule_expression.strip_prefix("override") {
+                            let position =
+                                match rest.trim() {
+                                    "first" => EOverridePosition::First,
+                                    "last" => EOverridePosition::Last,
+                                    _ => return Err(Error::other(
+                                        "Parsing error: override position must be first or last",
+                                    )),
+                                };
+                            rule = Override::new(position, vec![]).into();
                         } else if let Some(rest) = rule_expression.strip_prefix("note") {
                             let mut x = Note::default();
                             x.set_comment(rest.trim().to_owned());
@@ -341,10 +754,7 @@ impl Parser {
                             rule = x.into();
                         } else {
                             // unknown rule, skip
-                            return Err(Error::new(
-                                ErrorKind::Other,
-                                "Parsing error: unknown rule",
-                            ));
+                            return Err(Error::other("Parsing error: unknown rule"));
                         }
                     }
 
@@ -354,7 +764,7 @@ impl Parser {
                             // inline rules don't have comments, we just parse the resst of the chunk
                             // now parse rule body
                             ERule::parse(&mut rule, reader, self)?;
-                            Ok(rule)
+                            Ok((rule, tags))
                         }
                         ERuleType::Multiline => {
                             // construct the body out of each line with comments trimmed
@@ -382,8 +792,20 @@ impl Parser {
                                     }
 
                                     if !comment.is_empty() {
-                                        if let ERule::EWarningRule(w) = &mut rule {
-                                            w.set_comment(comment.clone().trim().into());
+                                        match &mut rule {
+                                            ERule::EWarningRule(w) => {
+                                                w.set_comment(comment.clone().trim().into());
+                                            }
+                                            ERule::EOrderRule(EOrderRule::Order(o)) => {
+                                                o.comment = comment.clone().trim().into();
+                                            }
+                                            ERule::EOrderRule(EOrderRule::NearStart(o)) => {
+                                                o.comment = comment.clone().trim().into();
+                                            }
+                                            ERule::EOrderRule(EOrderRule::NearEnd(o)) => {
+                                                o.comment = comment.clone().trim().into();
+                                            }
+                                            _ => {}
                                         }
                                         comment.clear();
                                     }
@@ -399,19 +821,16 @@ impl Parser {
                             let body = body.trim();
                             let body_cursor = Cursor::new(body);
                             ERule::parse(&mut rule, body_cursor, self)?;
-                            Ok(rule)
+                            Ok((rule, tags))
                         }
                     }
                 } else {
-                    Err(Error::new(ErrorKind::Other, "Parsing error: unknown rule"))
+                    Err(Error::other("Parsing error: unknown rule"))
                 }
             }
             _ => {
                 // error
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Parsing error: Not a rule start",
-                ))
+                Err(Error::other("Parsing error: Not a rule start"))
             }
         }
     }
@@ -475,7 +894,7 @@ impl Parser {
                 if is_quoted {
                     is_quoted = false;
                     // end token
-                    tokens.push(current_token.trim().to_owned());
+                    tokens.push(normalize_name(&current_token));
                     current_token.clear();
                 } else {
                     is_quoted = true;
@@ -485,22 +904,16 @@ impl Parser {
             current_token += c.to_string().as_str();
 
             // check if we found an end
-            if self.ends_with_vec_whitespace(&current_token) {
-                // ignore whitespace in quoted segments
-                if !is_quoted {
-                    // end token
-                    if !current_token.is_empty() {
-                        tokens.push(current_token.trim().to_owned());
-                        current_token.clear();
-                    }
-                } else {
-                    current_token += c.to_string().as_str();
-                }
+            // extensions in quoted segments never end a token
+            if !is_quoted && self.ends_with_vec_whitespace(&current_token) {
+                // end token
+                tokens.push(normalize_name(&current_token));
+                current_token.clear();
             }
         }
 
         if !current_token.is_empty() {
-            tokens.push(current_token.trim().to_owned());
+            tokens.push(normalize_name(&current_token));
         }
 
         tokens
@@ -515,8 +928,8 @@ impl Parser {
     where
         R: Read + BufRead,
     {
-        let mut buffer = vec![];
-        reader.read_to_end(&mut buffer)?;
+        let mut buffer = String::new();
+        reader.read_to_string(&mut buffer)?;
 
         // pre-parse expressions into chunks
         let mut chunks: Vec<(String, bool)> = vec![];
@@ -525,18 +938,18 @@ impl Parser {
         let mut is_token = false;
         let mut depth = 0;
 
-        for b in buffer {
+        for b in buffer.chars() {
             if is_expr {
                 // if parsing an expression, just count brackets and read the rest into the buffer
-                if b == b'[' {
+                if b == '[' {
                     depth += 1;
-                } else if b == b']' {
+                } else if b == ']' {
                     depth -= 1;
                 }
-                current_buffer += &(b as char).to_string();
+                current_buffer.push(b);
 
                 // check if really an expression
-                // valid expressions are [ANY], [ALL], [NOT], [DESC], [SIZE], [VER]
+                // valid expressions are [ANY], [ALL], [NOT], [DESC], [SIZE], [VER], [IF]
 
                 if depth == 0 {
                     // we reached the end of the current expression
@@ -547,6 +960,7 @@ impl Parser {
                         || starts_with_whitespace(trimmed, "[desc")
                         || starts_with_whitespace(trimmed, "[size")
                         || starts_with_whitespace(trimmed, "[ver")
+                        || starts_with_whitespace(trimmed, "[if")
                     {
                         is_expr = false;
                         chunks.push((trimmed.to_owned(), true));
@@ -561,7 +975,7 @@ impl Parser {
                 }
             } else if is_token {
                 // if parsing tokens, check when ".archive" was parsed into the buffer and end
-                current_buffer += &(b as char).to_string();
+                current_buffer.push(b);
 
                 if self.ends_with_vec_whitespace_or_newline(&current_buffer) {
                     is_token = false;
@@ -570,7 +984,7 @@ impl Parser {
                 }
             } else {
                 // this marks the beginning
-                if b == b'[' {
+                if b == '[' {
                     // start an expression
                     is_expr = true;
                     depth += 1;
@@ -579,7 +993,7 @@ impl Parser {
                 else if !b.is_ascii_whitespace() {
                     is_token = true;
                 }
-                current_buffer += &(b as char).to_string();
+                current_buffer.push(b);
             }
         }
 
@@ -619,10 +1033,10 @@ impl Parser {
             // is a token
             // in this case just return an atomic
             if !self.ends_with_vec(reader) {
-                return Err(Error::new(ErrorKind::Other, "Parsing error: Not an atomic"));
+                return Err(Error::other("Parsing error: Not an atomic"));
             }
 
-            return Ok(Atomic::from(reader).into());
+            return Ok(Atomic::from(normalize_name(reader)).into());
         }
 
         if reader.starts_with('[') {
@@ -639,17 +1053,16 @@ impl Parser {
                 let expr = ALL::new(expressions);
                 Ok(expr.into())
             } else if let Some(rest) = reader.strip_prefix("[not") {
-                let expressions =
+                let mut expressions =
                     self.parse_expressions(rest[..rest.len() - 1].trim_start().as_bytes())?;
-                if let Some(first) = expressions.into_iter().last() {
-                    let expr = NOT::new(first);
-                    Ok(expr.into())
-                } else {
-                    Err(Error::new(
-                        ErrorKind::Other,
-                        "Parsing error: unknown expression",
-                    ))
+                // check that it is of len 1
+                if expressions.len() != 1 {
+                    return Err(Error::other(
+                        "Parsing error: NOT expression must have exactly one child expression",
+                    ));
                 }
+                let expr = NOT::new(expressions.remove(0));
+                Ok(expr.into())
             } else if let Some(rest) = reader.strip_prefix("[desc") {
                 let body = rest[..rest.len() - 1].trim_start();
                 if let Some((expr, regex, negated)) = parse_desc(body) {
@@ -657,8 +1070,7 @@ impl Parser {
                     let expressions = self.parse_expressions(expr.as_bytes())?;
                     // check that it is of len 1
                     if expressions.len() != 1 {
-                        return Err(Error::new(
-                            ErrorKind::Other,
+                        return Err(Error::other(
                             "Parsing error: DESC expression must have exactly one child expression",
                         ));
                     }
@@ -669,15 +1081,11 @@ impl Parser {
                         return Ok(expr.into());
                     }
 
-                    return Err(Error::new(
-                        ErrorKind::Other,
+                    return Err(Error::other(
                         "Parsing error: DESC expression must have an atomic child expression",
                     ));
                 }
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Parsing error: unknown expression",
-                ))
+                Err(Error::other("Parsing error: unknown expression"))
             } else if let Some(rest) = reader.strip_prefix("[size") {
                 let body = rest[..rest.len() - 1].trim_start();
                 if let Some((expr, size, negated)) = parse_size(body) {
@@ -685,8 +1093,7 @@ impl Parser {
                     let expressions = self.parse_expressions(expr.as_bytes())?;
                     // check that it is of len 1
                     if expressions.len() != 1 {
-                        return Err(Error::new(
-                            ErrorKind::Other,
+                        return Err(Error::other(
                             "Parsing error: SIZE expression must have exactly one child expression",
                         ));
                     }
@@ -696,15 +1103,11 @@ impl Parser {
                         return Ok(expr.into());
                     }
 
-                    return Err(Error::new(
-                        ErrorKind::Other,
+                    return Err(Error::other(
                         "Parsing error: SIZE expression must have an atomic child expression",
                     ));
                 }
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Parsing error: unknown expression",
-                ))
+                Err(Error::other("Parsing error: unknown expression"))
             } else if let Some(rest) = reader.strip_prefix("[ver") {
                 let body = rest[..rest.len() - 1].trim_start();
                 if let Some((expr, operator, version)) = parse_ver(body) {
@@ -712,8 +1115,7 @@ impl Parser {
                     let expressions = self.parse_expressions(expr.as_bytes())?;
                     // check that it is of len 1
                     if expressions.len() != 1 {
-                        return Err(Error::new(
-                            ErrorKind::Other,
+                        return Err(Error::other(
                             "Parsing error: VER expression must have exactly one child expression",
                         ));
                     }
@@ -724,29 +1126,130 @@ impl Parser {
                         return Ok(expr.into());
                     }
 
-                    return Err(Error::new(
-                        ErrorKind::Other,
+                    return Err(Error::other(
                         "Parsing error: VER expression must have an atomic child expression",
                     ));
                 }
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Parsing error: unknown expression",
-                ))
+                Err(Error::other("Parsing error: unknown expression"))
+            } else if let Some(rest) = reader.strip_prefix("[if") {
+                let body = rest[..rest.len() - 1].trim_start();
+                let Some((game, expr)) = body.split_once(|c: char| c.is_ascii_whitespace()) else {
+                    return Err(Error::other(
+                        "Parsing error: IF expression must have a game",
+                    ));
+                };
+                let game = match game.to_lowercase().as_str() {
+                    "morrowind" => ESupportedGame::Morrowind,
+                    "openmw" => ESupportedGame::Openmw,
+                    "cyberpunk" => ESupportedGame::Cyberpunk,
+                    _ => {
+                        return Err(Error::other(
+                            "Parsing error: IF expression has an unknown game",
+                        ))
+                    }
+                };
+                let expressions = self.parse_expressions(expr.trim_start().as_bytes())?;
+                // check that it is of len 1
+                if expressions.len() != 1 {
+                    return Err(Error::other(
+                        "Parsing error: IF expression must have exactly one child expression",
+                    ));
+                }
+                if let Some(first) = expressions.into_iter().last() {
+                    let expr = IF::new(game, first);
+                    return Ok(expr.into());
+                }
+                Err(Error::other("Parsing error: unknown expression"))
             } else {
                 // unknown expression
-                Err(Error::new(
-                    ErrorKind::Other,
-                    "Parsing error: unknown expression",
-                ))
+                Err(Error::other("Parsing error: unknown expression"))
             }
         } else {
-            Err(Error::new(
-                ErrorKind::Other,
-                "Parsing error: Not an expression",
-            ))
+            Err(Error::other("Parsing error: Not an expression"))
+        }
+    }
+}
+
+/// Gets the names of all rule fragments `<prefix>*.txt` in the rules dir, sorted by file name
+fn get_rule_fragments(rules_dir: &Path, prefix: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(rules_dir) else {
+        return vec![];
+    };
+    let mut fragments = entries
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| e.file_name().to_str().map(|s| s.to_owned()))
+        .filter(|n| n.starts_with(prefix) && n.ends_with(".txt"))
+        .collect::<Vec<_>>();
+    fragments.sort();
+    fragments
+}
+
+/// The keywords of all supported rules
+const RULE_KEYWORDS: [&str; 8] = [
+    "order",
+    "nearstart",
+    "nearend",
+    "override",
+    "note",
+    "conflict",
+    "requires",
+    "patch",
+];
+/// The keywords of all supported expressions
+const EXPRESSION_KEYWORDS: [&str; 7] = ["any", "all", "not", "desc", "size", "ver", "if"];
+
+/// Gets the keyword of a line starting with a directive that is neither a rule nor an expression, e.g. [pin]
+pub fn get_unknown_keyword(line: &str) -> Option<String> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find(|c: char| !c.is_ascii_alphabetic())?;
+    let (keyword, rest) = rest.split_at(end);
+    // a plugin name like [x].esp is not a directive
+    let is_directive = rest.starts_with(char::is_whitespace)
+        || rest
+            .strip_prefix(']')
+            .is_some_and(|r| r.is_empty() || r.starts_with(char::is_whitespace));
+    let keyword = keyword.to_ascii_lowercase();
+    if keyword.is_empty()
+        || !is_directive
+        || RULE_KEYWORDS.contains(&keyword.as_str())
+        || EXPRESSION_KEYWORDS.contains(&keyword.as_str())
+    {
+        return None;
+    }
+    Some(keyword)
+}
+
+/// Splits the `@tag` words directly after the rule keyword off a rule expression,
+/// e.g. "note @experimental some comment" is split into "note some comment" and ["experimental"]
+fn split_rule_tags(rule_expression: &str) -> (String, Vec<String>) {
+    let keyword_end = rule_expression
+        .find(char::is_whitespace)
+        .unwrap_or(rule_expression.len());
+    let (keyword, mut rest) = rule_expression.split_at(keyword_end);
+
+    let mut tags = vec![];
+    while let Some(tag_start) = rest.trim_start().strip_prefix('@') {
+        let tag_end = tag_start
+            .find(char::is_whitespace)
+            .unwrap_or(tag_start.len());
+        if tag_end == 0 {
+            break;
         }
+        tags.push(tag_start[..tag_end].to_owned());
+        rest = &tag_start[tag_end..];
     }
+
+    (format!("{}{}", keyword, rest), tags)
+}
+
+/// Gets the tags of a rule that starts on this line, e.g. "[Order @experimental]"
+pub fn get_rule_tags(line: &str) -> Vec<String> {
+    let Some(rest) = line.strip_prefix('[') else {
+        return vec![];
+    };
+    let rule_expression = rest.split(']').next().unwrap_or_default();
+    split_rule_tags(&rule_expression.to_ascii_lowercase()).1
 }
 
 fn starts_with_whitespace(current_buffer: &str, arg: &str) -> bool {
@@ -982,13 +1485,16 @@ mod tests {
             vec![" a.archive", "\"mod with spaces.archive\"", "\"c.archive\""],
             vec!["a mod with spaces.archive"],
             vec!["a.archive"],
+            vec!["\"my .archive backup mod.archive\"", "b.archive"],
+            vec!["a.archive", "\"x.archive  y.archive\""],
         ];
 
         for input_vec in inputs {
             let input = input_vec.join(" ");
+            // tokens are normalized, whitespace in quoted names is collapsed
             let expected = input_vec
                 .iter()
-                .map(|f| f.trim().trim_matches('"').trim())
+                .map(|f| normalize_name(f.trim().trim_matches('"')))
                 .collect::<Vec<_>>();
             assert_eq!(expected, parser.tokenize(input.to_owned()).as_slice());
         }
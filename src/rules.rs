@@ -3,13 +3,13 @@
 ////////////////////////////////////////////////////////////////////////
 use std::{
     fmt::Display,
-    io::{BufRead, Error, ErrorKind, Read, Result, Seek},
+    io::{BufRead, Error, Read, Result, Seek},
 };
 
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::{expressions::*, parser, PluginData};
+use crate::{expressions::*, parser};
 
 ///////////////////////////////////////////////////
 // ENUMS
@@ -25,6 +25,7 @@ pub enum EOrderRule {
     Order(Order),
     NearStart(NearStart),
     NearEnd(NearEnd),
+    Override(Override),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,7 +47,7 @@ pub trait TWarningRule {
 
     fn set_comment(&mut self, comment: String);
     /// every rule may be evaluated
-    fn eval(&mut self, items: &[PluginData]) -> bool;
+    fn eval(&mut self, ctx: &EvalContext) -> bool;
 }
 
 impl TWarningRule for EWarningRule {
@@ -77,12 +78,38 @@ impl TWarningRule for EWarningRule {
         }
     }
 
-    fn eval(&mut self, items: &[PluginData]) -> bool {
+    fn eval(&mut self, ctx: &EvalContext) -> bool {
         match self {
-            EWarningRule::Note(o) => o.eval(items),
-            EWarningRule::Conflict(o) => o.eval(items),
-            EWarningRule::Requires(o) => o.eval(items),
-            EWarningRule::Patch(o) => o.eval(items),
+            EWarningRule::Note(o) => o.eval(ctx),
+            EWarningRule::Conflict(o) => o.eval(ctx),
+            EWarningRule::Requires(o) => o.eval(ctx),
+            EWarningRule::Patch(o) => o.eval(ctx),
+        }
+    }
+}
+
+impl EWarningRule {
+    /// Gets all expressions of the rule
+    pub fn get_expressions(&self) -> Vec<&Expression> {
+        match self {
+            EWarningRule::Note(x) => x.expressions.iter().collect(),
+            EWarningRule::Conflict(x) => x.expressions.iter().collect(),
+            EWarningRule::Requires(x) => {
+                x.expression_a.iter().chain(x.expression_b.iter()).collect()
+            }
+            EWarningRule::Patch(x) => x.expression_a.iter().chain(x.expression_b.iter()).collect(),
+        }
+    }
+}
+
+impl EOrderRule {
+    /// Gets the plugin names of the rule
+    pub fn get_names(&self) -> &[String] {
+        match self {
+            EOrderRule::Order(x) => &x.names,
+            EOrderRule::NearStart(x) => &x.names,
+            EOrderRule::NearEnd(x) => &x.names,
+            EOrderRule::Override(x) => &x.names,
         }
     }
 }
@@ -133,6 +160,7 @@ impl TParser<EOrderRule> for EOrderRule {
             EOrderRule::Order(rule) => Order::parse(rule, reader, parser),
             EOrderRule::NearStart(rule) => NearStart::parse(rule, reader, parser),
             EOrderRule::NearEnd(rule) => NearEnd::parse(rule, reader, parser),
+            EOrderRule::Override(rule) => Override::parse(rule, reader, parser),
         }
     }
 }
@@ -166,6 +194,11 @@ impl From<NearEnd> for ERule {
         ERule::EOrderRule(val.into())
     }
 }
+impl From<Override> for ERule {
+    fn from(val: Override) -> Self {
+        ERule::EOrderRule(val.into())
+    }
+}
 
 impl From<Order> for EOrderRule {
     fn from(val: Order) -> Self {
@@ -182,6 +215,11 @@ impl From<NearEnd> for EOrderRule {
         EOrderRule::NearEnd(val)
     }
 }
+impl From<Override> for EOrderRule {
+    fn from(val: Override) -> Self {
+        EOrderRule::Override(val)
+    }
+}
 
 // Warnings
 impl From<Note> for ERule {
@@ -236,18 +274,30 @@ impl From<Patch> for EWarningRule {
 /// The [Order] rule specifies the order of plugins.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Order {
+    /// the comment explaining why this order is needed
+    #[serde(default)]
+    pub comment: String,
     pub names: Vec<String>,
 }
 impl Order {
     pub fn new(names: Vec<String>) -> Self {
-        Self { names }
+        Self {
+            comment: String::new(),
+            names,
+        }
     }
 
     pub fn from(name_a: &str, name_b: &str) -> Self {
         Self {
+            comment: String::new(),
             names: [name_a.to_owned(), name_b.to_owned()].to_vec(),
         }
     }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = comment.to_owned();
+        self
+    }
 }
 impl TParser<Order> for Order {
     fn parse<R: Read + BufRead + Seek>(
@@ -266,10 +316,7 @@ impl TParser<Order> for Order {
             // each line gets tokenized
             for token in parser.tokenize(line) {
                 if !token.ends_with(']') && !parser.ends_with_vec(&token) {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        "Parsing error: tokenize failed",
-                    ));
+                    return Err(Error::other("Parsing error: tokenize failed"));
                 }
                 names.push(token);
             }
@@ -279,8 +326,7 @@ impl TParser<Order> for Order {
 
         if this.names.len() < 2 {
             warn!("Malformed Order rule: less than 2 expressions");
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Malformed Order rule: less than 2 expressions",
             ));
         }
@@ -289,17 +335,34 @@ impl TParser<Order> for Order {
     }
 }
 
+impl Display for Order {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_order_block(f, "ORDER", &self.comment, &self.names)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 // NEARSTART
 
 /// The [NearStart] rule specifies that one or more plugins should appear as near as possible to the Start of the load order.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct NearStart {
+    /// the comment explaining why this placement is needed
+    #[serde(default)]
+    pub comment: String,
     pub names: Vec<String>,
 }
 impl NearStart {
     pub fn new(names: Vec<String>) -> Self {
-        Self { names }
+        Self {
+            comment: String::new(),
+            names,
+        }
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = comment.to_owned();
+        self
     }
 }
 impl TParser<NearStart> for NearStart {
@@ -319,10 +382,7 @@ impl TParser<NearStart> for NearStart {
             // each line gets tokenized
             for token in parser.tokenize(line) {
                 if !token.ends_with(']') && !parser.ends_with_vec(&token) {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        "Parsing error: tokenize failed",
-                    ));
+                    return Err(Error::other("Parsing error: tokenize failed"));
                 }
                 names.push(token);
             }
@@ -334,17 +394,34 @@ impl TParser<NearStart> for NearStart {
     }
 }
 
+impl Display for NearStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_order_block(f, "NEARSTART", &self.comment, &self.names)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////
 // NEAREND
 
 /// The [NearEnd] rule specifies that one or more plugins should appear as near as possible to the End of the load order.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct NearEnd {
+    /// the comment explaining why this placement is needed
+    #[serde(default)]
+    pub comment: String,
     pub names: Vec<String>,
 }
 impl NearEnd {
     pub fn new(names: Vec<String>) -> Self {
-        Self { names }
+        Self {
+            comment: String::new(),
+            names,
+        }
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = comment.to_owned();
+        self
     }
 }
 impl TParser<NearEnd> for NearEnd {
@@ -364,10 +441,65 @@ impl TParser<NearEnd> for NearEnd {
             // each line gets tokenized
             for token in parser.tokenize(line) {
                 if !token.ends_with(']') && !parser.ends_with_vec(&token) {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        "Parsing error: tokenize failed",
-                    ));
+                    return Err(Error::other("Parsing error: tokenize failed"));
+                }
+                names.push(token);
+            }
+        }
+
+        this.names = names;
+
+        Ok(())
+    }
+}
+
+impl Display for NearEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_order_block(f, "NEAREND", &self.comment, &self.names)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////
+// OVERRIDE
+
+/// The position an [Override] rule pins plugins to
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EOverridePosition {
+    #[default]
+    First,
+    Last,
+}
+
+/// The [Override] rule pins one or more plugins to the very start or end of the load order.
+/// It is applied after all other sorting and takes precedence over [NearStart] and [NearEnd].
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Override {
+    pub position: EOverridePosition,
+    pub names: Vec<String>,
+}
+impl Override {
+    pub fn new(position: EOverridePosition, names: Vec<String>) -> Self {
+        Self { position, names }
+    }
+}
+impl TParser<Override> for Override {
+    fn parse<R: Read + BufRead + Seek>(
+        this: &mut Override,
+        reader: R,
+        parser: &parser::Parser,
+    ) -> Result<()> {
+        // parse each line
+        let mut names: Vec<String> = vec![];
+        for line in reader
+            .lines()
+            .map_while(Result::ok)
+            .map(|l| l.trim().to_owned())
+        {
+            // HANDLE RULE PARSE
+            // each line gets tokenized
+            for token in parser.tokenize(line) {
+                if !token.ends_with(']') && !parser.ends_with_vec(&token) {
+                    return Err(Error::other("Parsing error: tokenize failed"));
                 }
                 names.push(token);
             }
@@ -379,10 +511,86 @@ impl TParser<NearEnd> for NearEnd {
     }
 }
 
+impl Display for Override {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let position = match self.position {
+            EOverridePosition::First => "FIRST",
+            EOverridePosition::Last => "LAST",
+        };
+        let mut block = format!("[OVERRIDE {}]\n", position);
+        for name in &self.names {
+            block += format!("{}\n", name).as_str();
+        }
+        write!(f, "{}", block)
+    }
+}
+
+/// The plugin extensions the parsers of all games end a plugin name at
+const PLUGIN_EXTENSIONS: [&str; 6] = [
+    ".esp",
+    ".esm",
+    ".omwgame",
+    ".omwaddon",
+    ".omwscripts",
+    ".archive",
+];
+
+/// Quotes a plugin name that would be parsed as more than one plugin, e.g. "my .esp backup mod.esp"
+fn quote_name(name: &str) -> String {
+    let needs_quotes = name
+        .split_whitespace()
+        .rev()
+        .skip(1)
+        .any(|word| PLUGIN_EXTENSIONS.iter().any(|ext| word.ends_with(ext)));
+    if needs_quotes {
+        format!("\"{}\"", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Writes an order rule block: the rule name, the comment and one plugin per line
+fn write_order_block(
+    f: &mut std::fmt::Formatter<'_>,
+    rule: &str,
+    comment: &str,
+    names: &[String],
+) -> std::fmt::Result {
+    let mut block = format!("[{}]\n", rule);
+    // rules without a comment have no comment line
+    if !comment.is_empty() {
+        block += format!("\t{}\n", comment).as_str();
+    }
+    for name in names {
+        block += format!("{}\n", quote_name(name)).as_str();
+    }
+    write!(f, "{}", block)
+}
+
 ////////////////////////////////////////////////////////////////////////
 // IMPLEMENTATIONS WARNINGS
 ////////////////////////////////////////////////////////////////////////
 
+impl Display for ERule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ERule::EOrderRule(x) => x.fmt(f),
+            ERule::EWarningRule(x) => x.fmt(f),
+        }
+    }
+}
+
+impl Display for EOrderRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EOrderRule::Order(x) => x.fmt(f),
+            EOrderRule::NearStart(x) => x.fmt(f),
+            EOrderRule::NearEnd(x) => x.fmt(f),
+            EOrderRule::Override(x) => x.fmt(f),
+        }
+    }
+}
+
 impl Display for EWarningRule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -428,10 +636,10 @@ impl TWarningRule for Note {
         self.comment = comment;
     }
     /// Notes evaluate as true if any of the containing expressions evaluates as true
-    fn eval(&mut self, items: &[PluginData]) -> bool {
+    fn eval(&mut self, ctx: &EvalContext) -> bool {
         let mut result = false;
         for expr in &self.expressions {
-            if let Some(plugins) = expr.eval(items) {
+            if let Some(plugins) = expr.eval(ctx) {
                 result = true;
                 // track plugins
                 self.plugins.extend(plugins);
@@ -455,10 +663,7 @@ impl TParser<Note> for Note {
 
         if this.expressions.is_empty() {
             warn!("Malformed Note rule: no expressions parsed");
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Malformed Note rule: no expressions parsed",
-            ));
+            return Err(Error::other("Malformed Note rule: no expressions parsed"));
         }
 
         Ok(())
@@ -497,6 +702,26 @@ impl Conflict {
             conflicts: vec![],
         }
     }
+
+    /// Gets the conflicting groups of the last evaluation named A, B, C, ...
+    /// Each group holds the plugins matched by one expression
+    pub fn get_groups(&self) -> Vec<(String, &Vec<String>)> {
+        self.conflicts
+            .iter()
+            .enumerate()
+            .map(|(i, group)| (get_group_name(i), group))
+            .collect()
+    }
+}
+
+/// Gets the name of a conflict group: A, B, ..., Z, AA, AB, ...
+fn get_group_name(index: usize) -> String {
+    let letter = (b'A' + (index % 26) as u8) as char;
+    if index < 26 {
+        letter.to_string()
+    } else {
+        format!("{}{}", get_group_name(index / 26 - 1), letter)
+    }
 }
 impl TWarningRule for Conflict {
     fn get_comment(&self) -> &str {
@@ -509,18 +734,31 @@ impl TWarningRule for Conflict {
         self.comment = comment;
     }
 
-    /// Conflicts evaluate as true if both expressions evaluate as true
-    fn eval(&mut self, items: &[PluginData]) -> bool {
-        let mut i = 0;
+    /// Conflicts evaluate as true if two different installed plugins match two different expressions
+    /// The plugins matched by each expression are recorded as one conflict group
+    fn eval(&mut self, ctx: &EvalContext) -> bool {
+        self.plugins.clear();
+        self.conflicts.clear();
+
         for e in &self.expressions {
-            if let Some(plugins) = e.eval(items) {
-                self.plugins.extend(plugins.clone());
+            if let Some(plugins) = e.eval(ctx) {
+                for plugin in &plugins {
+                    if !self.plugins.contains(plugin) {
+                        self.plugins.push(plugin.clone());
+                    }
+                }
                 self.conflicts.push(plugins);
-                i += 1;
             }
         }
 
-        i > 1
+        // overlapping expressions may match the same single plugin, that plugin doesn't conflict with itself
+        if self.conflicts.len() > 1 && self.plugins.len() > 1 {
+            true
+        } else {
+            self.plugins.clear();
+            self.conflicts.clear();
+            false
+        }
     }
 }
 impl TParser<Conflict> for Conflict {
@@ -534,8 +772,7 @@ impl TParser<Conflict> for Conflict {
 
         if this.expressions.is_empty() {
             warn!("Malformed Conflict rule: no expressions parsed");
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Malformed Conflict rule: no expressions parsed",
             ));
         }
@@ -589,12 +826,12 @@ impl TWarningRule for Requires {
         self.comment = comment;
     }
     /// Requires evaluates as true if A is true and B is not true
-    fn eval(&mut self, items: &[PluginData]) -> bool {
+    fn eval(&mut self, ctx: &EvalContext) -> bool {
         let mut result = false;
         if let Some(expr_a) = &self.expression_a {
             if let Some(expr_b) = &self.expression_b {
-                if let Some(plugins) = expr_a.eval(items) {
-                    if expr_b.eval(items).is_none() {
+                if let Some(plugins) = expr_a.eval(ctx) {
+                    if expr_b.eval(ctx).is_none() {
                         result = true;
                         self.plugins.extend(plugins);
                     }
@@ -617,16 +854,15 @@ impl TParser<Requires> for Requires {
 
         // add all parsed expressions
         let expressions = parser.parse_expressions(reader)?;
-        if expressions.len() != 2 {
-            warn!("Malformed Requires rule: more than 2 expressions");
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Malformed Requires rule: more than 2 expressions",
+        let Some((a, b)) = split_dependency_expressions(expressions) else {
+            warn!("Malformed Requires rule: less than 2 expressions");
+            return Err(Error::other(
+                "Malformed Requires rule: less than 2 expressions",
             ));
-        }
+        };
 
-        this.expression_a = Some(expressions[0].clone());
-        this.expression_b = Some(expressions[1].clone());
+        this.expression_a = Some(a);
+        this.expression_b = Some(b);
 
         Ok(())
     }
@@ -645,6 +881,25 @@ impl Display for Requires {
     }
 }
 
+/// Splits the expressions of a [Requires] or [Patch] rule into the two sides of the rule.
+/// The first expression is the dependant, all following expressions are required: `A B C` is read as `A [ALL B C]`
+fn split_dependency_expressions(
+    mut expressions: Vec<Expression>,
+) -> Option<(Expression, Expression)> {
+    if expressions.len() < 2 {
+        return None;
+    }
+
+    let rest = expressions.split_off(1);
+    let a = expressions.remove(0);
+    let b = if rest.len() == 1 {
+        rest[0].clone()
+    } else {
+        ALL::new(rest).into()
+    };
+    Some((a, b))
+}
+
 ////////////////////////////////////////////////////////////////////////
 // PATCH
 
@@ -681,19 +936,19 @@ impl TWarningRule for Patch {
         self.comment = comment;
     }
     /// Patch evaluates as true if A is true and B is not true or if B is true and A is not true
-    fn eval(&mut self, items: &[PluginData]) -> bool {
+    fn eval(&mut self, ctx: &EvalContext) -> bool {
         let mut result = false;
         if let Some(expr_a) = &self.expression_a {
             if let Some(expr_b) = &self.expression_b {
-                if let Some(plugins_a) = expr_a.eval(items) {
-                    if expr_b.eval(items).is_none() {
+                if let Some(plugins_a) = expr_a.eval(ctx) {
+                    if expr_b.eval(ctx).is_none() {
                         result = true;
                         self.plugins.extend(plugins_a);
                     }
                 }
 
-                if let Some(plugins_b) = expr_b.eval(items) {
-                    if expr_a.eval(items).is_none() {
+                if let Some(plugins_b) = expr_b.eval(ctx) {
+                    if expr_a.eval(ctx).is_none() {
                         result = true;
                         self.plugins.extend(plugins_b);
                     }
@@ -716,16 +971,15 @@ impl TParser<Patch> for Patch {
 
         // add all parsed expressions
         let expressions = parser.parse_expressions(reader)?;
-        if expressions.len() != 2 {
-            warn!("Malformed Patch rule: not exactly 2 expressions");
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Malformed Patch rule: not exactly 2 expressions",
+        let Some((a, b)) = split_dependency_expressions(expressions) else {
+            warn!("Malformed Patch rule: less than 2 expressions");
+            return Err(Error::other(
+                "Malformed Patch rule: less than 2 expressions",
             ));
-        }
+        };
 
-        this.expression_a = Some(expressions[0].clone());
-        this.expression_b = Some(expressions[1].clone());
+        this.expression_a = Some(a);
+        this.expression_b = Some(b);
 
         Ok(())
     }
@@ -0,0 +1,190 @@
+////////////////////////////////////////////////////////////////////////
+// SETTINGS
+////////////////////////////////////////////////////////////////////////
+
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{EConflictStrategy, ESupportedGame};
+
+/// The name of the settings file read by the cli and the gui
+pub const PLOX_SETTINGS_FILE: &str = "plox.toml";
+
+/// Environment variable for the game to evaluate, parsed like --game
+pub const PLOX_GAME_ENV: &str = "PLOX_GAME";
+/// Environment variable for the root game folder
+pub const PLOX_GAME_ROOT_ENV: &str = "PLOX_GAME_ROOT";
+/// Environment variable for the folder to read sorting rules from
+pub const PLOX_RULES_DIR_ENV: &str = "PLOX_RULES_DIR";
+/// Environment variable to disable user input, like --non-interactive but also for argument errors
+pub const PLOX_NON_INTERACTIVE_ENV: &str = "PLOX_NON_INTERACTIVE";
+
+/// Settings shared by the cli and the gui, read from plox.toml
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Specifies an openmw config file to use
+    pub config: Option<PathBuf>,
+
+    /// Specifies the game to use
+    pub game: Option<ESupportedGame>,
+
+    /// Specifies the folder to read sorting rules from
+    pub rules_dir: Option<String>,
+
+    /// set to not download rules
+    pub no_rules_download: bool,
+
+    /// download the rules from this base url instead of the default rules repository
+    pub rules_url: Option<String>,
+
+    /// log level
+    pub log_level: Option<String>,
+
+    /// use a log file bool (gui only)
+    pub log_to_file: bool,
+
+    /// ignore warnings (gui only)
+    pub ignore_warnings: bool,
+
+    /// which plugins to keep when conflicts are ignored (gui only)
+    pub conflict_strategy: EConflictStrategy,
+}
+
+impl Settings {
+    /// Reads the settings from a toml file, logs errors and returns the default settings on failure
+    pub fn from_file(path: &Path) -> Self {
+        match Self::try_from_file(path) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("{}", e);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Reads the settings from a toml file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or deserialized
+    pub fn try_from_file(path: &Path) -> Result<Self, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading settings file: {}", e))?;
+        toml::from_str(&s).map_err(|e| format!("Error deserializing settings: {}", e))
+    }
+}
+
+/// Defaults read from the environment for scripted use, explicit flags take precedence
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvSettings {
+    /// the game to evaluate
+    pub game: Option<ESupportedGame>,
+
+    /// the root game folder
+    pub game_root: Option<PathBuf>,
+
+    /// the folder to read sorting rules from
+    pub rules_dir: Option<String>,
+}
+
+impl EnvSettings {
+    /// Reads the settings from the PLOX_ environment variables, empty variables are ignored
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if PLOX_GAME is not a supported game
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Reads the settings from the PLOX_ variables returned by the lookup, empty variables are ignored
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if PLOX_GAME is not a supported game
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let var = |key: &str| lookup(key).filter(|v| !v.is_empty());
+        let game = match var(PLOX_GAME_ENV) {
+            Some(game) => Some(
+                ESupportedGame::from_str(&game, true)
+                    .map_err(|e| format!("Invalid {}: {}", PLOX_GAME_ENV, e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            game,
+            game_root: var(PLOX_GAME_ROOT_ENV).map(PathBuf::from),
+            rules_dir: var(PLOX_RULES_DIR_ENV),
+        })
+    }
+
+    /// Checks if user input is disabled in the environment. This is read before the arguments are parsed.
+    /// Any value except an empty one, "0" or "false" disables user input
+    pub fn non_interactive() -> bool {
+        Self::non_interactive_from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Checks if user input is disabled by the PLOX_NON_INTERACTIVE variable returned by the lookup
+    pub fn non_interactive_from_lookup(lookup: impl Fn(&str) -> Option<String>) -> bool {
+        lookup(PLOX_NON_INTERACTIVE_ENV).is_some_and(|v| {
+            let v = v.trim();
+            !v.is_empty() && v != "0" && !v.eq_ignore_ascii_case("false")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_settings_from_lookup() {
+        let mut vars = HashMap::from([
+            (PLOX_GAME_ENV, "OpenMW"),
+            (PLOX_GAME_ROOT_ENV, "tmp/env_game"),
+            (PLOX_RULES_DIR_ENV, ""),
+        ]);
+
+        // the game is parsed case insensitive like --game, empty variables are ignored
+        let env_settings =
+            EnvSettings::from_lookup(|key| vars.get(key).map(|v| v.to_string())).unwrap();
+        assert_eq!(env_settings.game, Some(ESupportedGame::Openmw));
+        assert_eq!(env_settings.game_root, Some(PathBuf::from("tmp/env_game")));
+        assert_eq!(env_settings.rules_dir, None);
+
+        vars.insert(PLOX_RULES_DIR_ENV, "my_rules");
+        let env_settings =
+            EnvSettings::from_lookup(|key| vars.get(key).map(|v| v.to_string())).unwrap();
+        assert_eq!(env_settings.rules_dir, Some("my_rules".to_owned()));
+
+        vars.insert(PLOX_GAME_ENV, "oblivion");
+        assert!(EnvSettings::from_lookup(|key| vars.get(key).map(|v| v.to_string())).is_err());
+
+        assert_eq!(
+            EnvSettings::from_lookup(|_| None).unwrap(),
+            EnvSettings::default()
+        );
+    }
+
+    #[test]
+    fn test_non_interactive_from_lookup() {
+        for (value, expected) in [("1", true), ("yes", true), ("0", false), ("False", false)] {
+            assert_eq!(
+                EnvSettings::non_interactive_from_lookup(|key| {
+                    (key == PLOX_NON_INTERACTIVE_ENV).then(|| value.to_owned())
+                }),
+                expected,
+                "{}",
+                value
+            );
+        }
+        assert!(!EnvSettings::non_interactive_from_lookup(|_| None));
+    }
+}
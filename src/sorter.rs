@@ -1,20 +1,63 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
-use log::warn;
+use clap::ValueEnum;
+use log::{trace, warn};
 use petgraph::{graph::NodeIndex, stable_graph::StableGraph};
+use serde::Serialize;
 
 use crate::{
-    get_ordering_from_order_rules, nearend2, nearstart2, wild_contains, EOrderRule, ESupportedGame,
-    EWarningRule, PluginData,
+    get_commented_ordering_from_order_rules, nearend2, nearstart2, normalize_name, override_rule2,
+    wild_contains, EOrderRule, EOverridePosition, ESupportedGame, EWarningRule, PluginData,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum ESortType {
     Unstable,
+    #[value(name = "stable")]
     StableOpt,
+    #[value(name = "full")]
     StableFull,
 }
 
+/// Errors returned by the sorter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortError {
+    /// The order rules contain cycles, each entry lists the plugins of one strongly connected component
+    Cycle(Vec<Vec<String>>),
+    /// Several plugins have the same normalized name, e.g. when they are gathered from more than one data folder
+    Duplicates(Vec<String>),
+    /// The sorter didn't converge, the order rules most likely contain a cycle the graph check missed
+    OutOfIterations,
+    /// Any other sorting error
+    Other(&'static str),
+}
+
+impl std::fmt::Display for SortError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortError::Cycle(cycles) => {
+                write!(f, "Graph contains a cycle: ")?;
+                let cycles = cycles
+                    .iter()
+                    .map(|c| format!("[{}]", c.join(", ")))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", cycles.join(", "))
+            }
+            SortError::Duplicates(names) => {
+                write!(f, "Plugins found more than once: {}", names.join(", "))
+            }
+            SortError::OutOfIterations => write!(f, "Out of iterations"),
+            SortError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<&'static str> for SortError {
+    fn from(msg: &'static str) -> Self {
+        SortError::Other(msg)
+    }
+}
+
 pub fn new_unstable_sorter() -> Sorter {
     Sorter::new(ESortType::Unstable, 0)
 }
@@ -23,15 +66,140 @@ pub fn new_stable_sorter() -> Sorter {
     Sorter::new(ESortType::StableOpt, 100)
 }
 
+pub fn new_full_sorter() -> Sorter {
+    Sorter::new(ESortType::StableFull, 1000)
+}
+
+/// Creates a sorter of the given type with its default iterations
+pub fn new_sorter(sort_type: ESortType) -> Sorter {
+    match sort_type {
+        ESortType::Unstable => new_unstable_sorter(),
+        ESortType::StableOpt => new_stable_sorter(),
+        ESortType::StableFull => new_full_sorter(),
+    }
+}
+
+/// The origin of an edge in the sorting graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EEdgeOrigin {
+    /// The edge comes from an Order rule, with the comment of that rule
+    Order(String),
+    /// The edge comes from a plugin depending on its master
+    Master,
+}
+
+/// An edge of the sorting graph with resolved plugin names: from loads before to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NamedEdge {
+    pub from: String,
+    pub to: String,
+    /// "order" or "master"
+    pub origin: String,
+    /// the comment of the order rule, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
 pub struct GraphData {
     pub index_dict: HashMap<String, usize>,
     pub index_dict_rev: HashMap<usize, String>,
     pub edges: Vec<(usize, usize)>,
+    /// The origin of each edge, the first rule adding an edge wins
+    pub edge_origins: HashMap<(usize, usize), EEdgeOrigin>,
+    /// Names of plugins found more than once, the first plugin keeps the name
+    pub duplicates: Vec<String>,
+}
+
+/// The order edges of a single plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginEdges {
+    /// Plugins that load before this plugin
+    pub incoming: Vec<(String, EEdgeOrigin)>,
+    /// Plugins that load after this plugin
+    pub outgoing: Vec<(String, EEdgeOrigin)>,
+}
+
+impl GraphData {
+    /// Returns all edges touching the plugin, if it is part of the graph
+    pub fn get_plugin_edges(&self, plugin: &str) -> Option<PluginEdges> {
+        let idx = *self.index_dict.get(&normalize_name(plugin))?;
+        let mut edges = PluginEdges {
+            incoming: vec![],
+            outgoing: vec![],
+        };
+        for edge in &self.edges {
+            let origin = self.edge_origins[edge].clone();
+            if edge.1 == idx {
+                edges
+                    .incoming
+                    .push((self.index_dict_rev[&edge.0].clone(), origin));
+            } else if edge.0 == idx {
+                edges
+                    .outgoing
+                    .push((self.index_dict_rev[&edge.1].clone(), origin));
+            }
+        }
+        Some(edges)
+    }
+
+    /// Returns all edges with resolved plugin names, in the order they were added
+    pub fn get_named_edges(&self) -> Vec<NamedEdge> {
+        self.edges
+            .iter()
+            .map(|edge| {
+                let (origin, comment) = match &self.edge_origins[edge] {
+                    EEdgeOrigin::Order(comment) if comment.is_empty() => ("order", None),
+                    EEdgeOrigin::Order(comment) => ("order", Some(comment.clone())),
+                    EEdgeOrigin::Master => ("master", None),
+                };
+                NamedEdge {
+                    from: self.index_dict_rev[&edge.0].clone(),
+                    to: self.index_dict_rev[&edge.1].clone(),
+                    origin: origin.to_owned(),
+                    comment,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the origin of the edge a -> b, if it exists
+    pub fn get_edge_origin(&self, a: &str, b: &str) -> Option<&EEdgeOrigin> {
+        let idx_a = self.index_dict.get(&normalize_name(a))?;
+        let idx_b = self.index_dict.get(&normalize_name(b))?;
+        self.edge_origins.get(&(*idx_a, *idx_b))
+    }
+
+    /// Returns all order edges violated by the given order as (a, b, comment): a needs to be moved before b
+    pub fn get_move_reasons(&self, order: &[String]) -> Vec<(String, String, String)> {
+        let positions = order
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (normalize_name(name), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut reasons = vec![];
+        for (a, b) in self.edges.iter() {
+            let name_a = &self.index_dict_rev[a];
+            let name_b = &self.index_dict_rev[b];
+            if let (Some(pos_a), Some(pos_b)) = (positions.get(name_a), positions.get(name_b)) {
+                if pos_a > pos_b {
+                    if let Some(EEdgeOrigin::Order(comment)) = self.edge_origins.get(&(*a, *b)) {
+                        reasons.push((name_a.to_owned(), name_b.to_owned(), comment.to_owned()));
+                    }
+                }
+            }
+        }
+        reasons
+    }
 }
 
 pub struct Sorter {
     pub sort_type: ESortType,
     pub max_iterations: usize,
+    /// Sort the edges by their source before the stable sort, default is true
+    pub presort_edges: bool,
+    /// (Morrowind and OpenMW only) Move the game file and masters to the start after the stable sort, default is true
+    pub hoist_masters: bool,
 }
 
 impl Sorter {
@@ -39,9 +207,21 @@ impl Sorter {
         Self {
             sort_type,
             max_iterations,
+            presort_edges: true,
+            hoist_masters: true,
         }
     }
 
+    pub fn with_presort_edges(mut self, presort_edges: bool) -> Self {
+        self.presort_edges = presort_edges;
+        self
+    }
+
+    pub fn with_hoist_masters(mut self, hoist_masters: bool) -> Self {
+        self.hoist_masters = hoist_masters;
+        self
+    }
+
     /// Sorts the input mods topologically. Mods input is case sensitive!
     ///
     /// # Panics
@@ -57,14 +237,18 @@ impl Sorter {
         plugins: &[PluginData],
         order_rules: &[EOrderRule],
         warn_rules: &[EWarningRule],
-    ) -> Result<Vec<String>, &'static str> {
-        // early out
+    ) -> Result<Vec<String>, SortError> {
+        // early out, without rules the order is left untouched
         if order_rules.is_empty() {
             log::info!("No order rules found, nothing to sort");
-            return Err("No order rules found");
+            return Ok(plugins.iter().map(|p| p.name.to_owned()).collect());
         }
 
         let data = get_graph_data(plugins, order_rules, warn_rules);
+        if !data.duplicates.is_empty() {
+            log::error!("Plugins found more than once: {:?}", data.duplicates);
+            return Err(SortError::Duplicates(data.duplicates));
+        }
         let g = build_graph(&data);
 
         let GraphData {
@@ -74,97 +258,61 @@ impl Sorter {
             ..
         } = data;
 
-        // cycle check
-        if self.sort_type == ESortType::Unstable {
-            let s = petgraph::algo::toposort(&g, None);
-            let sort;
-            if let Ok(result) = s {
-                sort = result;
-            } else {
-                return Err("Graph contains a cycle");
-            }
+        // cycle check, the stable sorters would only find a cycle by running out of iterations
+        let Ok(sort) = petgraph::algo::toposort(&g, None) else {
+            let cycles = get_cycles(&g);
+            log::error!("Graph contains a cycle: {:?}", cycles);
+            return Err(SortError::Cycle(cycles));
+        };
 
+        if self.sort_type == ESortType::Unstable {
             // map sorted index back to mods
             let mut result = vec![];
             for idx in sort {
                 let plugin = &plugins[idx.index()];
                 result.push(plugin.name.to_owned());
             }
+            apply_overrides(&mut result, order_rules);
             return Ok(result);
         }
 
         // sort
         let mut mods = plugins
             .iter()
-            .map(|f| f.name.to_lowercase())
+            .map(|f| normalize_name(&f.name))
             .collect::<Vec<String>>();
 
-        // nearstart rules
-        for nearstart in order_rules
-            .iter()
-            .filter_map(nearstart2)
-            .flat_map(|f| f.names)
-            .rev()
-        {
-            if let Some(results) = wild_contains(&mods, &nearstart) {
-                // push to start of mods
-                for r in results {
-                    let index = mods.iter().position(|f| f == &r).unwrap();
-                    let element = mods.remove(index);
-                    mods.insert(0, element);
-                }
-            }
-        }
+        // nearstart and nearend groups, ordered by the order rules among them
+        let (near_start, near_end) = get_near_groups(&mods, order_rules);
+        let edge_set = edges.iter().copied().collect::<HashSet<_>>();
+        let near_start = order_by_edges(&near_start, &edge_set, &index_dict);
+        let near_end = order_by_edges(&near_end, &edge_set, &index_dict);
 
-        // nearend rules
-        for nearend in order_rules
-            .iter()
-            .filter_map(nearend2)
-            .flat_map(|f| f.names)
-            .rev()
-        {
-            if let Some(results) = wild_contains(&mods, &nearend) {
-                // push to end of mods
-                for r in results {
-                    let index = mods.iter().position(|f| f == &r).unwrap();
-                    let element = mods.remove(index);
-                    mods.push(element);
-                }
-            }
-        }
+        mods.retain(|m| !near_start.contains(m) && !near_end.contains(m));
+        mods.splice(0..0, near_start);
+        mods.extend(near_end);
 
         let n = plugins.len();
 
         let mut index = 0;
 
-        edges.sort_by_key(|k| k.0);
+        if self.presort_edges {
+            edges.sort_by_key(|k| k.0);
+        }
 
         for i in 1..self.max_iterations {
-            let any_change = self.stable_topo_sort_inner(
-                n,
-                &edges,
-                &index_dict,
-                &index_dict_rev,
-                &mut mods,
-                &mut index,
-            );
+            let any_change =
+                self.stable_topo_sort_inner(n, &edges, &index_dict, &mut mods, &mut index);
 
             // sort again
             if !any_change {
                 // sort esms now?
-                if game == ESupportedGame::Morrowind || game == ESupportedGame::Openmw {
-                    // put all items in mods_copy ending with .esm at the start
-                    let mut esms = vec![];
-                    for (i, m) in mods.iter().enumerate() {
-                        if m.ends_with(".esm") || m.ends_with(".omwgame") {
-                            esms.push(i);
-                        }
-                    }
-                    // now sort the mods_copy list
-                    for (last_i, i) in esms.iter().enumerate() {
-                        let element = mods.remove(*i);
-                        mods.insert(last_i, element);
-                    }
+                if self.hoist_masters
+                    && (game == ESupportedGame::Morrowind || game == ESupportedGame::Openmw)
+                {
+                    // the game file (.omwgame) loads first, then all masters (.esm)
+                    // .omwaddon and .omwscripts are regular content files and keep their sorted position
+                    mods.sort_by_key(|m| get_master_rank(m));
 
                     // put standard tes3 esms at the start
                     // if mods_copy.contains(&"bloodmoon.esm".into()) {
@@ -182,10 +330,14 @@ impl Sorter {
                     if mods.contains(&"morrowind.esm".into()) {
                         let index = mods.iter().position(|f| f == "morrowind.esm").unwrap();
                         let element = mods.remove(index);
-                        mods.insert(0, element);
+                        let games = mods.iter().filter(|m| m.ends_with(".omwgame")).count();
+                        mods.insert(games, element);
                     }
                 }
 
+                // pinned plugins win over all other rules
+                apply_overrides(&mut mods, order_rules);
+
                 // Return the sorted vector
                 // map sorted index back to mods
                 let mut result = vec![];
@@ -207,7 +359,173 @@ impl Sorter {
         }
 
         log::error!("Out of iterations");
-        Err("Out of iterations")
+        Err(SortError::OutOfIterations)
+    }
+
+    /// Sorts only the plugins matching the wildcard pattern, all other plugins keep their position.
+    /// The sorted plugins are interleaved back into the full order without violating order rules that cross the boundary.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if sorting fails or if the order rules crossing the boundary can't be satisfied
+    pub fn topo_sort_only(
+        &mut self,
+        game: ESupportedGame,
+        plugins: &[PluginData],
+        only: &str,
+        order_rules: &[EOrderRule],
+        warn_rules: &[EWarningRule],
+    ) -> Result<Vec<String>, SortError> {
+        let mods = plugins
+            .iter()
+            .map(|f| normalize_name(&f.name))
+            .collect::<Vec<String>>();
+        let Some(matches) = wild_contains(&mods, &normalize_name(only)) else {
+            log::info!("No plugins match {}, nothing to sort", only);
+            return Err("No plugins match the pattern".into());
+        };
+
+        // sort all plugins, sorting the subset alone loses rules that run through pinned plugins
+        let sorted_subset = self
+            .topo_sort(game, plugins, order_rules, warn_rules)?
+            .into_iter()
+            .filter(|p| matches.contains(&normalize_name(p)))
+            .collect::<Vec<_>>();
+
+        // positions of the pinned plugins and the gap each subset plugin was in originally
+        let data = get_graph_data(plugins, order_rules, warn_rules);
+        let mut pinned: Vec<usize> = vec![];
+        let mut pinned_pos: HashMap<usize, usize> = HashMap::new();
+        let mut slots: Vec<usize> = vec![];
+        for (i, name) in mods.iter().enumerate() {
+            if matches.contains(name) {
+                slots.push(pinned.len());
+            } else {
+                pinned_pos.insert(i, pinned.len());
+                pinned.push(i);
+            }
+        }
+
+        let bounds = get_gap_bounds(&data, &sorted_subset, &pinned_pos, pinned.len());
+
+        // the sorted subset fills the original slots where possible
+        let mut gaps: Vec<Vec<usize>> = vec![vec![]; pinned.len() + 1];
+        let mut last_gap = 0;
+        for ((i, lo, hi), slot) in bounds.into_iter().zip(slots) {
+            let lo = lo.max(last_gap);
+            if lo > hi {
+                log::error!(
+                    "Order rules crossing the boundary can't be satisfied for {}",
+                    plugins[i].name
+                );
+                return Err("Order rules crossing the boundary can't be satisfied".into());
+            }
+            let gap = slot.clamp(lo, hi);
+            gaps[gap].push(i);
+            last_gap = gap;
+        }
+
+        // interleave
+        let mut result = vec![];
+        for (g, subset_in_gap) in gaps.iter().enumerate() {
+            for i in subset_in_gap {
+                result.push(plugins[*i].name.to_owned());
+            }
+            if let Some(p) = pinned.get(g) {
+                result.push(plugins[*p].name.to_owned());
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Sorts the plugins while all locked plugins keep their exact index, everything else is arranged around them.
+    /// Locked plugins are matched case-insensitive and may contain wildcards.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if sorting fails or if an order rule would require moving a locked plugin
+    pub fn topo_sort_locked(
+        &mut self,
+        game: ESupportedGame,
+        plugins: &[PluginData],
+        locked: &[String],
+        order_rules: &[EOrderRule],
+        warn_rules: &[EWarningRule],
+    ) -> Result<Vec<String>, SortError> {
+        let mods = plugins
+            .iter()
+            .map(|f| normalize_name(&f.name))
+            .collect::<Vec<String>>();
+        let locked = locked
+            .iter()
+            .filter_map(|l| wild_contains(&mods, &normalize_name(l)))
+            .flatten()
+            .collect::<Vec<_>>();
+        if locked.is_empty() {
+            return self.topo_sort(game, plugins, order_rules, warn_rules);
+        }
+
+        let data = get_graph_data(plugins, order_rules, warn_rules);
+        let mut pinned: Vec<usize> = vec![];
+        let mut pinned_pos: HashMap<usize, usize> = HashMap::new();
+        let mut slots: Vec<usize> = vec![];
+        for (i, name) in mods.iter().enumerate() {
+            if locked.contains(name) {
+                pinned_pos.insert(i, pinned.len());
+                pinned.push(i);
+            } else {
+                slots.push(pinned.len());
+            }
+        }
+
+        // rules between two locked plugins must already be satisfied
+        for (a, b) in &data.edges {
+            if let (Some(pa), Some(pb)) = (pinned_pos.get(a), pinned_pos.get(b)) {
+                if pa > pb {
+                    log::error!(
+                        "Order rule would require moving locked plugin {} before {}",
+                        plugins[*a].name,
+                        plugins[*b].name
+                    );
+                    return Err("Order rules would require moving a locked plugin".into());
+                }
+            }
+        }
+
+        // sort all plugins, sorting the unlocked plugins alone loses rules that run through locked plugins
+        let sorted_unlocked = self
+            .topo_sort(game, plugins, order_rules, warn_rules)?
+            .into_iter()
+            .filter(|p| !locked.contains(&normalize_name(p)))
+            .collect::<Vec<_>>();
+
+        // every unlocked plugin takes the next free slot so locked plugins keep their index
+        let bounds = get_gap_bounds(&data, &sorted_unlocked, &pinned_pos, pinned.len());
+        let mut gaps: Vec<Vec<usize>> = vec![vec![]; pinned.len() + 1];
+        for ((i, lo, hi), slot) in bounds.into_iter().zip(slots) {
+            if slot < lo || slot > hi {
+                log::error!(
+                    "Order rules would require moving a locked plugin around {}",
+                    plugins[i].name
+                );
+                return Err("Order rules would require moving a locked plugin".into());
+            }
+            gaps[slot].push(i);
+        }
+
+        // interleave
+        let mut result = vec![];
+        for (g, unlocked_in_gap) in gaps.iter().enumerate() {
+            for i in unlocked_in_gap {
+                result.push(plugins[*i].name.to_owned());
+            }
+            if let Some(p) = pinned.get(g) {
+                result.push(plugins[*p].name.to_owned());
+            }
+        }
+
+        Ok(result)
     }
 
     pub fn stable_topo_sort_inner(
@@ -215,14 +533,13 @@ impl Sorter {
         n: usize,
         edges: &[(usize, usize)],
         index_dict: &HashMap<String, usize>,
-        index_dict_rev: &HashMap<usize, String>,
         result: &mut Vec<String>,
         last_index: &mut usize,
     ) -> bool {
         match self.sort_type {
             ESortType::Unstable => panic!("not supported"),
             ESortType::StableOpt => {
-                Self::stable_topo_sort_opt2(n, edges, index_dict_rev, result, last_index)
+                Self::stable_topo_sort_opt2(n, edges, index_dict, result, last_index)
             }
             ESortType::StableFull => {
                 Self::stable_topo_sort_full(n, edges, index_dict, result, last_index)
@@ -256,29 +573,36 @@ impl Sorter {
     }
 
     pub fn stable_topo_sort_opt2(
-        _n: usize,
+        n: usize,
         edges: &[(usize, usize)],
-        index_dict_rev: &HashMap<usize, String>,
-        result: &mut Vec<String>,
+        index_dict: &HashMap<String, usize>,
+        result: &mut [String],
         last_index: &mut usize,
     ) -> bool {
+        // current position of each plugin and the plugin at each position, updated on every move
+        let mut nodes = result
+            .iter()
+            .map(|f| index_dict[f.as_str()])
+            .collect::<Vec<_>>();
+        let mut positions = vec![0; n];
+        for (p, node) in nodes.iter().enumerate() {
+            positions[*node] = p;
+        }
+
         // optimize B: only check edges
         let mut b = false;
         for (idx, edge) in edges.iter().enumerate() {
-            let i = edge.0;
-            let j = edge.1;
-
-            let x = &index_dict_rev[&i];
-            let y = &index_dict_rev[&j];
-
-            let idx_of_x = result.iter().position(|f| f == x).unwrap();
-            let idx_of_y = result.iter().position(|f| f == y).unwrap();
+            let idx_of_x = positions[edge.0];
+            let idx_of_y = positions[edge.1];
 
             // if i not before j x should be before y
             if idx_of_x > idx_of_y {
-                let t = result[idx_of_x].to_owned();
-                result.remove(idx_of_x);
-                result.insert(idx_of_y, t);
+                // move x to the position of y and shift everything in between
+                result[idx_of_y..=idx_of_x].rotate_right(1);
+                nodes[idx_of_y..=idx_of_x].rotate_right(1);
+                for (p, node) in nodes.iter().enumerate().take(idx_of_x + 1).skip(idx_of_y) {
+                    positions[*node] = p;
+                }
 
                 *last_index = idx;
 
@@ -302,11 +626,27 @@ pub fn get_graph_data(
     let mut index_dict: HashMap<String, usize> = HashMap::new();
     let mut index_dict_rev: HashMap<usize, String> = HashMap::default();
     let mut plugin_map: HashMap<usize, PluginData> = HashMap::default();
+    let mut duplicates: Vec<String> = vec![];
 
     for (i, plugin_data) in plugins.iter().enumerate() {
-        let lower_case = plugin_data.name.to_lowercase();
+        let lower_case = normalize_name(&plugin_data.name);
 
-        index_dict.insert(lower_case.clone(), i);
+        match index_dict.entry(lower_case.clone()) {
+            Entry::Vacant(e) => {
+                e.insert(i);
+            }
+            Entry::Occupied(e) => {
+                warn!(
+                    "{} was found more than once (sizes {} and {}), only the first one is used",
+                    plugin_data.name,
+                    plugins[*e.get()].size,
+                    plugin_data.size
+                );
+                if !duplicates.contains(&lower_case) {
+                    duplicates.push(lower_case.clone());
+                }
+            }
+        }
         index_dict_rev.insert(i, lower_case.clone());
 
         plugin_map.insert(i, plugin_data.to_owned());
@@ -315,27 +655,28 @@ pub fn get_graph_data(
     // add edges from order rules
     let mods = plugins
         .iter()
-        .map(|f| f.name.to_lowercase())
+        .map(|f| normalize_name(&f.name))
         .collect::<Vec<String>>();
 
-    let order_pairs = get_ordering_from_order_rules(order_rules);
+    let order_pairs = get_commented_ordering_from_order_rules(order_rules);
     let mut edges: Vec<(usize, usize)> = vec![];
-    for (a, b) in order_pairs {
+    let mut edge_origins: HashMap<(usize, usize), EEdgeOrigin> = HashMap::default();
+    for (a, b, comment) in order_pairs {
         if let Some(results_for_a) = wild_contains(&mods, &a) {
             if let Some(results_for_b) = wild_contains(&mods, &b) {
-                // foreach esp i, add an edge to all esps j
-                for i in &results_for_a {
-                    for j in &results_for_b {
-                        if i == j {
-                            warn!("Skipping circular edge: {}", i);
-                            continue;
-                        }
-                        let idx_a = index_dict[i.as_str()];
-                        let idx_b = index_dict[j.as_str()];
-
-                        if !edges.contains(&(idx_a, idx_b)) {
-                            edges.push((idx_a, idx_b));
-                        }
+                for (idx_a, idx_b) in get_wildcard_order_edges(&results_for_a, &results_for_b) {
+                    let edge = (index_dict[idx_a], index_dict[idx_b]);
+                    if let Entry::Vacant(e) = edge_origins.entry(edge) {
+                        trace!(
+                            "Adding edge {} -> {} from order rule [{} -> {}]: {}",
+                            idx_a,
+                            idx_b,
+                            a,
+                            b,
+                            comment
+                        );
+                        edges.push(edge);
+                        e.insert(EEdgeOrigin::Order(comment.clone()));
                     }
                 }
             }
@@ -345,17 +686,31 @@ pub fn get_graph_data(
     // add edges from masters
     for mod_data in plugins.iter() {
         // add an edge from the mod to all its masters
-        let idx = index_dict[&mod_data.name.to_lowercase()];
+        let idx = index_dict[&normalize_name(&mod_data.name)];
+        if let Some(error) = &mod_data.header_error {
+            warn!(
+                "Could not read the header of {}, it is not guaranteed to load after its masters: {}",
+                mod_data.name, error
+            );
+        }
         if let Some(masters) = &mod_data.masters {
             for (master, _hash) in masters {
-                let master = master.to_lowercase();
-                if let Some(results) = wild_contains(&mods, &master) {
-                    for result in results {
-                        let idx_master = index_dict[&result];
-                        let edge = (idx_master, idx);
-                        if !edges.contains(&edge) {
-                            edges.push(edge);
-                        }
+                // masters are plain file names, match them literally and case-insensitive
+                let master = normalize_name(master);
+                if let Some(idx_master) = index_dict.get(&master) {
+                    let edge = (*idx_master, idx);
+                    if edge.0 == edge.1 {
+                        warn!("Skipping circular master: {}", master);
+                        continue;
+                    }
+                    if let Entry::Vacant(e) = edge_origins.entry(edge) {
+                        trace!(
+                            "Adding edge {} -> {} from master dependency",
+                            master,
+                            normalize_name(&mod_data.name)
+                        );
+                        edges.push(edge);
+                        e.insert(EEdgeOrigin::Master);
                     }
                 }
             }
@@ -367,6 +722,198 @@ pub fn get_graph_data(
         index_dict,
         index_dict_rev,
         edges,
+        edge_origins,
+        duplicates,
+    }
+}
+
+/// Returns the minimal edges for an order pair where both sides may match several plugins.
+///
+/// All plugins matching only the first side load before all plugins matching only the second side.
+/// Plugins matching both sides load in between and are not ordered among each other.
+/// If such plugins exist, the edges between both outer sets are implied and skipped.
+fn get_wildcard_order_edges<'a>(a: &'a [String], b: &'a [String]) -> Vec<(&'a str, &'a str)> {
+    let both = a.iter().filter(|i| b.contains(i)).collect::<Vec<_>>();
+    let only_a = a.iter().filter(|i| !both.contains(i));
+    let only_b = b.iter().filter(|j| !both.contains(j));
+
+    let mut edges = vec![];
+    if both.is_empty() {
+        for i in only_a {
+            for j in only_b.clone() {
+                edges.push((i.as_str(), j.as_str()));
+            }
+        }
+    } else {
+        for i in only_a {
+            for k in &both {
+                edges.push((i.as_str(), k.as_str()));
+            }
+        }
+        for k in &both {
+            for j in only_b.clone() {
+                edges.push((k.as_str(), j.as_str()));
+            }
+        }
+    }
+    edges
+}
+
+/// Returns the plugins matched by NearStart rules and by NearEnd rules, in the order they are pushed to the start and the end.
+/// NearEnd rules win over NearStart rules.
+fn get_near_groups(mods: &[String], order_rules: &[EOrderRule]) -> (Vec<String>, Vec<String>) {
+    let mut near_start: Vec<String> = vec![];
+    for nearstart in order_rules
+        .iter()
+        .filter_map(nearstart2)
+        .flat_map(|f| f.names)
+        .rev()
+    {
+        if let Some(results) = wild_contains(mods, &nearstart) {
+            // push to start
+            for r in results {
+                near_start.retain(|f| f != &r);
+                near_start.insert(0, r);
+            }
+        }
+    }
+
+    let mut near_end: Vec<String> = vec![];
+    for nearend in order_rules
+        .iter()
+        .filter_map(nearend2)
+        .flat_map(|f| f.names)
+        .rev()
+    {
+        if let Some(results) = wild_contains(mods, &nearend) {
+            // push to end
+            for r in results {
+                near_end.retain(|f| f != &r);
+                near_end.push(r);
+            }
+        }
+    }
+    near_start.retain(|f| !near_end.contains(f));
+
+    (near_start, near_end)
+}
+
+/// Orders a group of plugins by the edges among them, plugins without edges keep their order
+fn order_by_edges(
+    group: &[String],
+    edges: &HashSet<(usize, usize)>,
+    index_dict: &HashMap<String, usize>,
+) -> Vec<String> {
+    let mut rest = group.to_vec();
+    let mut result = vec![];
+    while !rest.is_empty() {
+        // take the first plugin that no other remaining plugin needs to load before
+        let next = rest
+            .iter()
+            .position(|b| {
+                !rest
+                    .iter()
+                    .any(|a| edges.contains(&(index_dict[a], index_dict[b])))
+            })
+            .unwrap_or(0);
+        result.push(rest.remove(next));
+    }
+    result
+}
+
+/// Returns the plugins of all strongly connected components that form a cycle
+fn get_cycles(g: &StableGraph<String, ()>) -> Vec<Vec<String>> {
+    petgraph::algo::tarjan_scc(g)
+        .into_iter()
+        .filter(|scc| scc.len() > 1)
+        .map(|scc| scc.into_iter().map(|idx| g[idx].clone()).collect())
+        .collect()
+}
+
+/// Computes for each sorted plugin the range of gaps between pinned plugins it may be placed in.
+/// A gap g means the plugin is inserted before the pinned plugin at pinned position g.
+fn get_gap_bounds(
+    data: &GraphData,
+    sorted: &[String],
+    pinned_pos: &HashMap<usize, usize>,
+    pinned_len: usize,
+) -> Vec<(usize, usize, usize)> {
+    let mut bounds = vec![];
+    for name in sorted {
+        let i = data.index_dict[&normalize_name(name)];
+        let mut lo = 0;
+        let mut hi = pinned_len;
+        for (a, b) in &data.edges {
+            if *b == i {
+                if let Some(p) = pinned_pos.get(a) {
+                    lo = lo.max(p + 1);
+                }
+            }
+            if *a == i {
+                if let Some(p) = pinned_pos.get(b) {
+                    hi = hi.min(*p);
+                }
+            }
+        }
+        bounds.push((i, lo, hi));
+    }
+    // later plugins may not be placed before earlier ones
+    for k in (0..bounds.len().saturating_sub(1)).rev() {
+        bounds[k].2 = bounds[k].2.min(bounds[k + 1].2);
+    }
+    bounds
+}
+
+/// Gets the load position of a content file in OpenMW: the game file, masters, then all other content
+fn get_master_rank(name: &str) -> u8 {
+    if name.ends_with(".omwgame") {
+        0
+    } else if name.ends_with(".esm") {
+        1
+    } else {
+        2
+    }
+}
+
+/// Gets the game files and masters that load after a regular content file
+pub fn get_misplaced_masters(order: &[String]) -> Vec<String> {
+    let mut misplaced = vec![];
+    let mut content_found = false;
+    for name in order {
+        if get_master_rank(&normalize_name(name)) == 2 {
+            content_found = true;
+        } else if content_found {
+            misplaced.push(name.to_owned());
+        }
+    }
+    misplaced
+}
+
+/// Moves plugins pinned by Override rules to the very start or end of the load order
+fn apply_overrides(mods: &mut Vec<String>, order_rules: &[EOrderRule]) {
+    for rule in order_rules.iter().filter_map(override_rule2) {
+        let names = match rule.position {
+            EOverridePosition::First => rule.names.into_iter().rev().collect::<Vec<_>>(),
+            EOverridePosition::Last => rule.names,
+        };
+        for name in names {
+            let lower_case = mods.iter().map(|m| normalize_name(m)).collect::<Vec<_>>();
+            if let Some(mut results) = wild_contains(&lower_case, &name) {
+                // wildcard matches keep their order at the start
+                if rule.position == EOverridePosition::First {
+                    results.reverse();
+                }
+                for r in results {
+                    // mods changes with every move, look up the current index
+                    let index = mods.iter().position(|m| normalize_name(m) == r).unwrap();
+                    let element = mods.remove(index);
+                    match rule.position {
+                        EOverridePosition::First => mods.insert(0, element),
+                        EOverridePosition::Last => mods.push(element),
+                    }
+                }
+            }
+        }
     }
 }
 
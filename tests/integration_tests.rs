@@ -1,13 +1,14 @@
 #[cfg(test)]
 mod integration_tests {
-    use std::path::PathBuf;
-    use std::{fs::create_dir_all, io::Write};
+    use std::path::{Path, PathBuf};
+    use std::process::ExitCode;
+    use std::{fs::create_dir_all, io::Cursor, io::Write};
 
     use log::warn;
-    use plox::{parser::*, sorter::*, *};
+    use plox::{expressions::EvalContext, parser::*, sorter::*, *};
     use rand::seq::SliceRandom;
     use rand::thread_rng;
-    use rules::{EWarningRule, TWarningRule};
+    use rules::{EOrderRule, ERule, EWarningRule, Order, TWarningRule};
     use semver::Version;
 
     fn init() {
@@ -27,7 +28,7 @@ mod integration_tests {
         for rule in warning_rules.iter_mut() {
             // only conflict rules
             if let EWarningRule::Conflict(ref mut conflict) = rule {
-                if conflict.eval(plugins) {
+                if conflict.eval(&EvalContext::new(ESupportedGame::Morrowind, None, plugins)) {
                     // remove mods
                     // switch on the len of conflict.conflicts
                     let groups_size = conflict.conflicts.len();
@@ -81,6 +82,31 @@ mod integration_tests {
         )
     }
 
+    #[test]
+    fn test_read_mods_wrye() {
+        init();
+
+        // comments and inactive plugins are skipped, the active markers are stripped
+        let mods_path = "./tests/modlist_wrye.txt";
+        let mods_data = read_file_as_list_with_format(mods_path, EModListFormat::Wrye);
+        assert_eq!(
+            mods_data
+                .iter()
+                .map(|s| s.name.to_owned())
+                .collect::<Vec<_>>(),
+            vec![
+                "Morrowind.esm",
+                "Tribunal.esm",
+                "Bloodmoon.esm",
+                "My Mod 1.0.esp",
+                "Spaced.esp"
+            ]
+        );
+
+        // the plain format keeps every line
+        assert_eq!(9, read_file_as_list(mods_path).len());
+    }
+
     #[test]
     fn test_parse_order() {
         init();
@@ -168,6 +194,46 @@ mod integration_tests {
         assert_eq!(1, parser.warning_rules.len());
     }
 
+    #[test]
+    fn test_get_referencing_rules() {
+        init();
+
+        let mut parser = new_tes3_parser();
+        parser
+            .init_from_file("./tests/plox/rules_order.txt")
+            .expect("failed rule parsing");
+        parser
+            .init_from_file("./tests/plox/rules_requires.txt")
+            .expect("failed rule parsing");
+
+        let lines = |plugin: &str| {
+            get_referencing_rules(&parser, plugin)
+                .into_iter()
+                .map(|(_, source)| source.expect("no source").line)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![1, 18, 22, 32], lines("C.esp"));
+        // wildcard names
+        assert_eq!(vec![8], lines("Creatures (Fixed).esp"));
+        // names nested in expressions
+        assert_eq!(vec![1], lines("TR_Factions.esp"));
+        assert!(lines("x.esp").is_empty());
+
+        let plugins = [
+            "x.esp",
+            "C.esp",
+            "Creatures (Fixed).esp",
+            "y.esp",
+            "TR_Factions.esp",
+        ]
+        .map(String::from);
+        assert_eq!(
+            get_referenced_plugins(&parser, &plugins),
+            ["c.esp", "creatures (fixed).esp", "tr_factions.esp"]
+        );
+    }
+
     #[test]
     fn test_dump_rules() -> std::io::Result<()> {
         init();
@@ -209,6 +275,321 @@ mod integration_tests {
         }
     }
 
+    #[test]
+    fn test_parser_json_roundtrip() -> std::io::Result<()> {
+        init();
+
+        let mut parser = new_tes3_parser();
+        parser.init_from_file("./tests/mlox/mlox_base.txt")?;
+
+        let json = parser.to_json()?;
+        let loaded = Parser::from_json(&json)?;
+
+        assert_eq!(parser.game, loaded.game);
+        assert_eq!(parser.ext, loaded.ext);
+        assert_eq!(parser.order_rules.len(), loaded.order_rules.len());
+        assert_eq!(parser.warning_rules.len(), loaded.warning_rules.len());
+        assert_eq!(parser.warning_sources, loaded.warning_sources);
+
+        // a sample rule of each kind
+        let last = parser.warning_rules.len() - 1;
+        assert_eq!(
+            parser.order_rules[0].to_string(),
+            loaded.order_rules[0].to_string()
+        );
+        assert_eq!(
+            parser.warning_rules[last].to_string(),
+            loaded.warning_rules[last].to_string()
+        );
+
+        // rules serialized before order rules had comments still load
+        let rules: Vec<EOrderRule> = serde_json::from_str(
+            r#"[{"Order":{"names":["a.esp","b.esp"]}},{"NearStart":{"names":["a.esp"]}},{"NearEnd":{"names":["b.esp"]}}]"#,
+        )?;
+        assert_eq!(3, rules.len());
+        let Some(EOrderRule::Order(order)) = rules.first() else {
+            panic!("Not an order rule");
+        };
+        assert_eq!("", order.comment);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_fragments() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/rule_fragments");
+        create_dir_all(&dir)?;
+        std::fs::write(dir.join("mlox_base.txt"), "[Order]\na.esp\nb.esp\n")?;
+        std::fs::write(dir.join("mlox_my_rules.txt"), "[Order]\nd.esp\ne.esp\n")?;
+        // fragments are loaded in file name order
+        std::fs::write(dir.join("mlox_user_b.txt"), "[Order]\nc.esp\nd.esp\n")?;
+        std::fs::write(dir.join("mlox_user_a.txt"), "[Order]\nb.esp\nc.esp\n")?;
+        std::fs::write(dir.join("mlox_user_c.bak"), "[Order]\nx.esp\ny.esp\n")?;
+
+        let mut parser = new_tes3_parser();
+        parser.parse(&dir)?;
+
+        assert_eq!(
+            parser
+                .order_sources
+                .iter()
+                .map(|s| s.file.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "mlox_base.txt",
+                "mlox_user_a.txt",
+                "mlox_user_b.txt",
+                "mlox_my_rules.txt"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tags() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/rule_tags");
+        create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb.esp\n\n[Order @Experimental]\nc.esp\nd.esp\n\n[Note @beta @experimental some comment]\ne.esp\n",
+        )?;
+
+        // tagged rules are loaded by default, the tags are not part of the comment
+        let mut parser = new_tes3_parser();
+        parser.parse(&dir)?;
+        assert_eq!(parser.order_rules.len(), 2);
+        assert_eq!(parser.warning_rules.len(), 1);
+        assert_eq!(parser.warning_rules[0].get_comment(), "some comment");
+
+        let mut parser = new_tes3_parser();
+        parser.set_tags(&[], &["experimental".to_owned()]);
+        parser.parse(&dir)?;
+        assert_eq!(parser.order_rules.len(), 1);
+        assert!(parser.warning_rules.is_empty());
+        assert_eq!(parser.order_sources[0].line, 1);
+
+        // enabled tags win over disabled ones
+        let mut parser = new_tes3_parser();
+        parser.set_tags(&["Beta".to_owned()], &["*".to_owned()]);
+        parser.parse(&dir)?;
+        assert_eq!(parser.order_rules.len(), 1);
+        assert!(parser.warning_rules.is_empty());
+        parser.set_tags(
+            &["beta".to_owned(), "experimental".to_owned()],
+            &["*".to_owned()],
+        );
+        parser.parse(&dir)?;
+        assert_eq!(parser.order_rules.len(), 2);
+        assert_eq!(parser.warning_rules.len(), 1);
+
+        assert_eq!(
+            parser::get_rule_tags("[Note @Beta @experimental some comment]"),
+            ["beta", "experimental"]
+        );
+        assert!(parser::get_rule_tags("[Note contact me @ nexus]").is_empty());
+
+        Ok(())
+    }
+
+    fn get_sort_options(game_folder: &Path, rules_dir: &Path) -> CliSortOptions {
+        CliSortOptions {
+            game: ESupportedGame::Morrowind,
+            game_folder: Some(game_folder.to_path_buf()),
+            rules_dir: Some(rules_dir.display().to_string()),
+            mod_list: None,
+            mod_list_format: EModListFormat::Plain,
+            dry_run: true,
+            stdout: false,
+            quiet: true,
+            no_redate: true,
+            no_builtin_order: true,
+            no_esm_hoist: false,
+            plugin_limit: None,
+            sort_type: ESortType::StableOpt,
+            no_download: true,
+            config: None,
+            only: None,
+            since: None,
+            report: None,
+            rules_url: None,
+            game_version: None,
+            groundcover: false,
+            verify_sort: false,
+            ignore: None,
+            conflict_strategy: None,
+            rule_log: None,
+            loot_userlist: None,
+            enable_tags: vec![],
+            disable_tags: vec![],
+            check: false,
+        }
+    }
+
+    #[test]
+    fn test_exit_codes() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/exit_codes");
+        let game_folder = dir.join("game");
+        let rules_dir = dir.join("rules");
+        create_dir_all(game_folder.join("Data Files"))?;
+        create_dir_all(&rules_dir)?;
+        let _ = std::fs::remove_file(rules_dir.join("mlox_base.txt"));
+        for name in ["a.esp", "b.esp"] {
+            let _ = std::fs::remove_file(game_folder.join("Data Files").join(name));
+        }
+
+        // no plugins
+        assert_eq!(
+            sort(get_sort_options(&game_folder, &rules_dir)),
+            ExitCode::from(EExitCode::NoMods)
+        );
+
+        // no rules is not an error for sort, the order is left untouched
+        for name in ["a.esp", "b.esp"] {
+            std::fs::write(game_folder.join("Data Files").join(name), [0u8; 4])?;
+        }
+        assert_eq!(
+            sort(get_sort_options(&game_folder, &rules_dir)),
+            ExitCode::SUCCESS
+        );
+        let rules = Some(rules_dir.display().to_string());
+        assert_eq!(
+            verify(ESupportedGame::Morrowind, &None, &rules, None, false),
+            ExitCode::from(EExitCode::NoRules)
+        );
+
+        // cycle
+        std::fs::write(
+            rules_dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb.esp\n\n[Order]\nb.esp\na.esp\n\n[Note]\n  note\na.esp\n",
+        )?;
+        assert_eq!(
+            sort(get_sort_options(&game_folder, &rules_dir)),
+            ExitCode::from(EExitCode::Cycle)
+        );
+        assert_eq!(
+            verify(ESupportedGame::Morrowind, &None, &rules, None, false),
+            ExitCode::from(EExitCode::Cycle)
+        );
+
+        // success
+        std::fs::write(
+            rules_dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb.esp\n\n[Note]\n  note\na.esp\n",
+        )?;
+        assert_eq!(
+            sort(get_sort_options(&game_folder, &rules_dir)),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            verify(ESupportedGame::Morrowind, &None, &rules, None, false),
+            ExitCode::SUCCESS
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uninstalled_references() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/uninstalled_references");
+        create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb*.esp\n\n[Order]\na.esp\nx.esp\n\n[Note]\n  note\n[ALL a.esp [NOT y.esp]]\n",
+        )?;
+
+        let mut parser = new_tes3_parser();
+        parser.parse(&dir)?;
+
+        let installed = ["A.esp", "b 1.esp"]
+            .iter()
+            .map(|n| PluginData::new(n.to_string(), 0))
+            .collect::<Vec<_>>();
+        let references = get_uninstalled_references(&parser, &installed);
+
+        // wildcards matching an installed plugin are not reported
+        assert_eq!(2, references.len());
+        assert_eq!("Order", references[0].rule);
+        assert_eq!(vec!["x.esp"], references[0].plugins);
+        assert_eq!(5, references[0].source.as_ref().unwrap().line);
+        assert_eq!("Note", references[1].rule);
+        assert_eq!(vec!["y.esp"], references[1].plugins);
+        assert_eq!(9, references[1].source.as_ref().unwrap().line);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_unknown_keywords() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/verify_unknown_keywords");
+        create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb.esp\n[Pin] a.esp\n[Pin]\nb.esp\n\n[Group x]\nc.esp\n",
+        )?;
+
+        let mut parser = new_tes3_parser();
+        parser.parse(&dir)?;
+
+        // the unknown directives don't end up in the previous rule
+        assert_eq!(1, parser.order_rules.len());
+        assert_eq!(2, parser.order_rules[0].get_names().len());
+
+        let report = get_verify_report(&parser, true);
+        assert_eq!(2, report.unknown_keywords.len());
+        assert_eq!("pin", report.unknown_keywords[0].keyword);
+        assert_eq!(2, report.unknown_keywords[0].count);
+        assert_eq!("mlox_base.txt", report.unknown_keywords[0].file);
+        assert_eq!(4, report.unknown_keywords[0].line);
+        assert_eq!("group", report.unknown_keywords[1].keyword);
+        assert_eq!(1, report.unknown_keywords[1].count);
+        assert_eq!(8, report.unknown_keywords[1].line);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_report() -> std::io::Result<()> {
+        init();
+
+        let dir = PathBuf::from("tmp/verify_report");
+        create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("mlox_base.txt"),
+            "[Order]\na.esp\nb.esp\n\n[Order]\nc.esp\n\n[Note]\n  bad regex\n[DESC /(unclosed/ a.esp]\n",
+        )?;
+
+        let mut parser = new_tes3_parser();
+        parser.parse(&dir)?;
+
+        let report = get_verify_report(&parser, true);
+        assert_eq!(1, report.order);
+        assert_eq!(1, report.notes);
+
+        // the single-plugin order rule is malformed
+        assert_eq!(1, report.malformed_rules.len());
+        assert_eq!("mlox_base.txt", report.malformed_rules[0].file);
+        assert_eq!(5, report.malformed_rules[0].line);
+
+        // the regex can't be compiled
+        assert_eq!(1, report.invalid_expressions.len());
+
+        let json = serde_json::to_string(&report)?;
+        assert!(json.contains("\"malformed_rules\""));
+
+        Ok(())
+    }
+
     #[test]
     fn test_dump_display_rules() -> std::io::Result<()> {
         init();
@@ -244,6 +625,43 @@ mod integration_tests {
         }
     }
 
+    #[test]
+    fn test_format_rules_roundtrip() -> std::io::Result<()> {
+        init();
+
+        let parser = new_tes3_parser();
+        let input = std::fs::read_to_string("./tests/mlox/mlox_user.txt")?;
+        let (rules, _errors) =
+            parser.parse_rules_from_reader_with_errors(Cursor::new(input.as_bytes()))?;
+        assert!(!rules.is_empty());
+
+        // re-parsing the formatted rules gives the same rules
+        let formatted = format_rules(&rules);
+        let (reparsed, reparsed_errors) =
+            parser.parse_rules_from_reader_with_errors(Cursor::new(formatted.as_bytes()))?;
+        assert!(reparsed_errors.is_empty());
+        assert_eq!(rules.len(), reparsed.len());
+        for (a, b) in rules.iter().zip(&reparsed) {
+            assert_eq!(a.to_string(), b.to_string());
+        }
+        assert_eq!(formatted, format_rules(&reparsed));
+        assert!(parses_back(&parser, &formatted));
+
+        // an unquoted name with an extension inside parses as two plugins
+        let rule: ERule = Order::from("my .esp backup mod.esp", "b.esp").into();
+        assert!(parses_back(&parser, &format_rules(&[rule])));
+        assert!(!parses_back(
+            &parser,
+            "[ORDER]\nmy .esp backup mod.esp\nb.esp\n"
+        ));
+
+        // rules without a comment have no comment line
+        let rule: EOrderRule = Order::from("a.esp", "b.esp").into();
+        assert_eq!(rule.to_string(), "[ORDER]\na.esp\nb.esp\n");
+
+        Ok(())
+    }
+
     #[test]
     fn graphviz() -> std::io::Result<()> {
         init();
@@ -369,6 +787,30 @@ mod integration_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mlox_base_warnings_in_rule_order() -> std::io::Result<()> {
+        init();
+
+        let mut parser = new_tes3_parser();
+        parser.init_from_file("./tests/mlox/mlox_base.txt")?;
+
+        let mut mods = debug_get_mods_from_order_rules(&parser.order_rules);
+        let mut rng = thread_rng();
+        mods.shuffle(&mut rng);
+
+        // warnings are in the order of the rules file, also when evaluated in parallel
+        parser.evaluate_plugins(&mods);
+        let lines = parser
+            .warnings
+            .iter()
+            .filter_map(|w| w.source.as_ref().map(|s| s.line))
+            .collect::<Vec<_>>();
+        assert!(!lines.is_empty());
+        assert!(lines.windows(2).all(|w| w[0] < w[1]));
+
+        Ok(())
+    }
+
     #[test]
     fn test_mlox_base_rules_unstable() -> std::io::Result<()> {
         init();
@@ -562,9 +1004,116 @@ mod integration_tests {
                 "b.archive".into(),
                 "c.archive".into()
             ]
+        );
+
+        // archives in modlist.txt load first
+        let mods = gather_mods(&"./tests/cp77_modlist", ESupportedGame::Cyberpunk, None);
+        assert_eq!(
+            mods.iter().map(|s| s.name.to_owned()).collect::<Vec<_>>(),
+            vec![
+                "c.archive".to_owned(),
+                "a.archive".into(),
+                "b.archive".into()
+            ]
         )
     }
 
+    #[test]
+    fn test_gather_cp77_redmods() {
+        init();
+
+        // mods/loadOrder.txt, then the deployed mods.json, then modlist.txt, then alphabetical
+        let mods = gather_mods(&"./tests/cp77_redmod", ESupportedGame::Cyberpunk, None);
+        assert_eq!(
+            mods.iter().map(|s| s.name.to_owned()).collect::<Vec<_>>(),
+            vec!["ModC", "ModB", "b.archive", "a.archive", "ModA"]
+        );
+    }
+
+    #[test]
+    fn test_cp77_game_folder() {
+        init();
+
+        // the game is detected from the exe in the game folder, not the working dir
+        let root = PathBuf::from("tests").join("cp77_root");
+        let (game, detected_root) = detect_game_in(&root.join("archive")).unwrap();
+        assert_eq!(game, ESupportedGame::Cyberpunk);
+        assert_eq!(detected_root, root);
+
+        let mods = gather_mods(&root, ESupportedGame::Cyberpunk, None);
+        assert_eq!(
+            mods.iter().map(|s| s.name.to_owned()).collect::<Vec<_>>(),
+            vec!["a.archive".to_owned()]
+        );
+
+        // the version is read from the exe in the game folder
+        let version = get_game_version(ESupportedGame::Cyberpunk, &root).unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (2, 12, 0));
+        assert!(get_game_version(ESupportedGame::Cyberpunk, Path::new("tests")).is_none());
+        assert!(get_game_version(ESupportedGame::Morrowind, &root).is_none());
+    }
+
+    #[test]
+    fn test_gather_openmw_duplicates() -> std::io::Result<()> {
+        init();
+
+        let data_a = PathBuf::from("tests").join("openmw").join("data_a");
+        let data_b = PathBuf::from("tests").join("openmw").join("data_b");
+        let data_dirs = [&data_a, &data_b]
+            .iter()
+            .map(|d| d.canonicalize().unwrap().display().to_string())
+            .collect::<Vec<_>>();
+
+        // the plugin resolves to both data dirs
+        let paths = find_openmw_plugin(&data_dirs, "Duplicate.esp");
+        assert_eq!(2, paths.len());
+
+        // the last data dir wins
+        create_dir_all("tmp")?;
+        let cfg_path = PathBuf::from("tmp").join("openmw_duplicates.cfg");
+        let mut file = std::fs::File::create(&cfg_path)?;
+        for dir in &data_dirs {
+            writeln!(file, "data={}", dir)?;
+        }
+        writeln!(file, "content=duplicate.esp")?;
+
+        let mods = gather_openmw_mods(&Some(&cfg_path));
+        assert_eq!(1, mods.len());
+        assert_eq!(mods[0].size, data_b.join("duplicate.esp").metadata()?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gather_openmw_listed_twice() -> std::io::Result<()> {
+        init();
+
+        let data_a = PathBuf::from("tests").join("openmw").join("data_a");
+        create_dir_all("tmp")?;
+        let cfg_path = PathBuf::from("tmp").join("openmw_listed_twice.cfg");
+        let mut file = std::fs::File::create(&cfg_path)?;
+        writeln!(file, "data={}", data_a.canonicalize()?.display())?;
+        writeln!(file, "content=duplicate.esp")?;
+        writeln!(file, "content=Duplicate.esp")?;
+
+        // the plugin is only gathered once
+        let mods = gather_openmw_mods(&Some(&cfg_path));
+        assert_eq!(1, mods.len());
+        assert_eq!(mods[0].name, "duplicate.esp");
+
+        let mut plugins = ["a.esp", "b.esp", "A.esp", "c.esp", "a.esp"]
+            .map(|n| PluginData::new(n.into(), 0))
+            .to_vec();
+        let duplicates = remove_duplicate_plugins(&mut plugins);
+        assert_eq!(duplicates, [("a.esp".to_owned(), vec![0, 2, 4])]);
+        assert_eq!(
+            plugins.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            ["a.esp", "b.esp", "c.esp"]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_parse_header() {
         init();
@@ -585,6 +1134,7 @@ mod integration_tests {
             let got = get_version(
                 plugin_test_path.file_name().unwrap().to_str().unwrap(),
                 &Some(header.description),
+                Some(header.version),
             );
             let expected = Version::new(5, 3, 0);
             assert_eq!(got.unwrap(), expected);
@@ -609,6 +1159,7 @@ mod integration_tests {
             let got = get_version(
                 plugin_test_path.file_name().unwrap().to_str().unwrap(),
                 &Some(header.description),
+                Some(header.version),
             );
             let expected = Version::new(1, 1, 0);
             assert_eq!(got.unwrap(), expected);
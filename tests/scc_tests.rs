@@ -5,7 +5,7 @@ mod scc_tests {
 
     use log::warn;
     use petgraph::stable_graph::StableGraph;
-    use plox::{parser::*, *};
+    use plox::{expressions::EvalContext, parser::*, *};
     use rand::seq::SliceRandom;
     use rand::thread_rng;
     use rules::{EWarningRule, TWarningRule};
@@ -40,7 +40,7 @@ mod scc_tests {
         for rule in warning_rules.iter_mut() {
             // only conflict rules
             if let EWarningRule::Conflict(ref mut conflict) = rule {
-                if conflict.eval(plugins) {
+                if conflict.eval(&EvalContext::new(ESupportedGame::Morrowind, None, plugins)) {
                     // remove mods
                     // switch on the len of conflict.conflicts
                     let groups_size = conflict.conflicts.len();
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod unit_tests {
-    use plox::{expressions::*, rules::TWarningRule, PluginData};
+    use plox::{expressions::*, rules::TWarningRule, ESupportedGame, PluginData};
 
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -33,25 +33,49 @@ mod unit_tests {
         // [ALL] is true if A and B are true
         {
             let expr = ALL::new(vec![e(A), e(B)]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // [ALL] is false if A is true and B is not true
         {
             let expr = ALL::new(vec![e(A), e(X)]);
-            assert!(expr.eval(&get_mods()).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none());
         }
 
         // [ALL] is false if A is not true and B is true
         {
             let expr = ALL::new(vec![e(X), e(A)]);
-            assert!(expr.eval(&get_mods()).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none());
         }
 
         // [ALL] is false if A is not true and B is not true
         {
             let expr = ALL::new(vec![e(X), e(Y)]);
-            assert!(expr.eval(&get_mods()).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none());
         }
     }
 
@@ -62,25 +86,49 @@ mod unit_tests {
         // [ANY] is true if A and B are true
         {
             let expr = ANY::new(vec![e(A), e(B)]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // [ANY] is true if A is true and B is not true
         {
             let expr = ANY::new(vec![e(A), e(X)]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // [ANY] is true if A is not true and B is true
         {
             let expr = ANY::new(vec![e(X), e(A)]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // [ANY] is false if A is not true and B is not true
         {
             let expr = ANY::new(vec![e(X), e(Y)]);
-            assert!(expr.eval(&get_mods()).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none());
         }
     }
 
@@ -91,13 +139,54 @@ mod unit_tests {
         // [NOT] is true if A is not true
         {
             let expr = NOT::new(e(X));
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // [NOT] is false if A is true
         {
             let expr = NOT::new(e(A));
-            assert!(expr.eval(&get_mods()).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn evaluate_if() {
+        init();
+
+        // [IF] is true if the game matches and A is true
+        {
+            let expr = IF::new(ESupportedGame::Openmw, e(A));
+            let mods = get_mods();
+            let ctx = EvalContext::new(ESupportedGame::Openmw, None, &mods);
+            assert!(expr.eval(&ctx).is_some());
+        }
+
+        // [IF] is false if the game matches and A is not true
+        {
+            let expr = IF::new(ESupportedGame::Openmw, e(X));
+            let mods = get_mods();
+            let ctx = EvalContext::new(ESupportedGame::Openmw, None, &mods);
+            assert!(expr.eval(&ctx).is_none());
+        }
+
+        // [IF] is false if the game does not match
+        {
+            let expr = IF::new(ESupportedGame::Openmw, e(A));
+            let mods = get_mods();
+            let ctx = EvalContext::new(ESupportedGame::Morrowind, None, &mods);
+            assert!(expr.eval(&ctx).is_none());
         }
     }
 
@@ -114,19 +203,25 @@ mod unit_tests {
         // [SIZE] is true if the plugin size matches the given size
         {
             let expr = SIZE::new(Atomic::from(A), 1_u64, false);
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [SIZE] is true if the plugin size does not matches the given size and is negated
         {
             let expr = SIZE::new(Atomic::from(A), 2_u64, true);
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [SIZE] is false if the plugin size does not match the given size
         {
             let expr = SIZE::new(Atomic::from(A), 2_u64, false);
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
     }
 
@@ -142,36 +237,47 @@ mod unit_tests {
                 description: Some("description".to_string()),
                 version: None,
                 masters: None,
+                header_error: None,
             })
             .collect::<Vec<_>>();
 
         // [DESC] is true if the plugin description matches the given description
         {
             let expr = DESC::new(Atomic::from(A), "description".to_string(), false);
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
         // [DESC] is true if the plugin description matches the given description with regex
         {
             let expr = DESC::new(Atomic::from(A), "des*".to_string(), false);
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [DESC] is false if the plugin description does not match the given description
         {
             let expr = DESC::new(Atomic::from(A), "another description".to_string(), false);
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
 
         // [DESC] is true if the plugin description does not matches the given description and is negated is true
         {
             let expr = DESC::new(Atomic::from(A), "another description".to_string(), true);
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [DESC] is false if the plugin description does match the given description and is negated is true
         {
             let expr = DESC::new(Atomic::from(A), "description".to_string(), true);
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
     }
 
@@ -187,6 +293,7 @@ mod unit_tests {
                 description: None,
                 masters: None,
                 version: Some(lenient_semver::parse("1.0").unwrap()),
+                header_error: None,
             })
             .collect::<Vec<_>>();
 
@@ -194,13 +301,17 @@ mod unit_tests {
         // [VER] equals is true if the plugin version matches the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Equal, "1.0.0".to_string());
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [VER] equals is false if the plugin version does not matches the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Equal, "1.1.0".to_string());
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
 
         // Check greater
@@ -208,19 +319,25 @@ mod unit_tests {
         // [VER] greater is true if the plugin version is greater than the rule version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Greater, "0.1.0".to_string());
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [VER] greater is false if the plugin version is less than the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Greater, "1.2.0".to_string());
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
 
         // [VER] greater is false if the plugin version is equal to the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Greater, "1.0.0".to_string());
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
 
         // Check less
@@ -228,19 +345,46 @@ mod unit_tests {
         // [VER] less is true if the plugin version is less than the rule version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Less, "1.2.0".to_string());
-            assert!(expr.eval(&mods).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_some());
         }
 
         // [VER] less is false if the plugin version is greater than the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Less, "0.1.0".to_string());
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
         }
 
         // [VER] less is false if the plugin version is equal to the given version
         {
             let expr = VER::new(Atomic::from(A), EVerOperator::Less, "1.0.0".to_string());
-            assert!(expr.eval(&mods).is_none());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods))
+                .is_none());
+        }
+
+        // [VER] <game> compares the game version
+        {
+            let expr = VER::new(
+                Atomic::from(VER_GAME),
+                EVerOperator::Greater,
+                "2.0.0".to_string(),
+            );
+            let newer = Some(lenient_semver::parse("2.1").unwrap());
+            let older = Some(lenient_semver::parse("1.6").unwrap());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Cyberpunk, newer, &mods))
+                .is_some());
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Cyberpunk, older, &mods))
+                .is_none());
+            // an unknown game version never matches
+            assert!(expr
+                .eval(&EvalContext::new(ESupportedGame::Cyberpunk, None, &mods))
+                .is_none());
         }
     }
 
@@ -259,6 +403,7 @@ mod unit_tests {
                 description: None,
                 masters: None,
                 version: None,
+                header_error: None,
             })
             .collect::<Vec<_>>();
 
@@ -268,7 +413,7 @@ mod unit_tests {
         //         EVerOperator::Less,
         //         "3.0.0".to_string(),
         //     );
-        //     assert!(expr.eval(&mods).is_some());
+        //     assert!(expr.eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods)).is_some());
         // }
 
         {
@@ -279,7 +424,7 @@ mod unit_tests {
                 "2.0.0".to_string(),
             );
             let mut rule = plox::rules::Conflict::new("".into(), &[expr1.into(), expr2.into()]);
-            assert!(!rule.eval(&mods));
+            assert!(!rule.eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods)));
         }
     }
 
@@ -291,13 +436,25 @@ mod unit_tests {
         {
             let nested = ALL::new(vec![e(A), e(X)]);
             let expr = NOT::new(nested.into());
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
         // check that (a and b) are not present in the modlist
         {
             let nested = ALL::new(vec![e(A), e(B)]);
             let expr = NOT::new(nested.into());
-            assert!(expr.eval(&get_mods()).is_none()); // should fail
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_none()); // should fail
         }
 
         // check that (a and b) are present and that either (x and y) are not present
@@ -305,7 +462,13 @@ mod unit_tests {
             let nested1 = ALL::new(vec![e(A), e(B)]);
             let nested2 = NOT::new(ANY::new(vec![e(X), e(Y)]).into());
             let expr = ALL::new(vec![nested1.into(), nested2.into()]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
 
         // check that (a and b) are present and that either (x and y) are present
@@ -313,7 +476,41 @@ mod unit_tests {
             let nested1 = ALL::new(vec![e(A), e(B)]);
             let nested2 = ANY::new(vec![e(A), e(Y)]);
             let expr = ALL::new(vec![nested1.into(), nested2.into()]);
-            assert!(expr.eval(&get_mods()).is_some());
+            assert!(expr
+                .eval(&EvalContext::new(
+                    ESupportedGame::Morrowind,
+                    None,
+                    &get_mods()
+                ))
+                .is_some());
         }
     }
+
+    #[test]
+    fn collect_atomics_nested() {
+        init();
+
+        // [ALL a [NOT b] [ANY c d]]
+        let expr: Expression = ALL::new(vec![
+            e(A),
+            NOT::new(e(B)).into(),
+            ANY::new(vec![e(C), e(D)]).into(),
+        ])
+        .into();
+        let mut atomics = vec![];
+        expr.collect_atomics(&mut atomics);
+        assert_eq!(vec![A, B, C, D], atomics);
+
+        // predicates and game conditions
+        let expr: Expression = ANY::new(vec![
+            DESC::new(Atomic::from(A), "foo".into(), false).into(),
+            SIZE::new(Atomic::from(B), 0, true).into(),
+            VER::new(Atomic::from(C), EVerOperator::Less, "1.0.0".into()).into(),
+            IF::new(ESupportedGame::Openmw, NOT::new(e(D)).into()).into(),
+        ])
+        .into();
+        let mut atomics = vec![];
+        expr.collect_atomics(&mut atomics);
+        assert_eq!(vec![A, B, C, D], atomics);
+    }
 }
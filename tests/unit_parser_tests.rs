@@ -3,7 +3,11 @@ mod unit_tests {
     use core::panic;
     use std::io::Cursor;
 
-    use plox::{expressions::Expression, rules::TWarningRule, *};
+    use plox::{
+        expressions::{EvalContext, Expression, TExpression},
+        rules::{EOrderRule, ERule, TWarningRule},
+        *,
+    };
 
     fn init() {
         let env = env_logger::Env::default()
@@ -60,6 +64,34 @@ mod unit_tests {
         }
     }
 
+    #[test]
+    fn test_order_comment() {
+        init();
+
+        let inputs = [
+            "[Order this is why] a.esp b.esp",
+            "[Order this is why]\na.esp\nb.esp",
+            "[Order]\n  this is why\na.esp\nb.esp",
+        ];
+
+        for input in inputs {
+            let input = input.to_lowercase();
+            let reader = Cursor::new(input.as_bytes());
+
+            let rules = parser::new_tes3_parser()
+                .parse_rules_from_reader(reader)
+                .expect("Failed to parse rule")
+                .into_iter()
+                .filter_map(order)
+                .collect::<Vec<_>>();
+            assert_eq!(1, rules.len());
+
+            let n = rules.first().expect("No rules found");
+            assert_eq!("this is why", n.comment);
+            assert_eq!(vec!["a.esp", "b.esp"], n.names);
+        }
+    }
+
     #[test]
     fn test_invalid_rule() {
         let input = "[Order]\nab01GOTYpatch.esp\n;JoinAll*.esp";
@@ -104,6 +136,95 @@ mod unit_tests {
             assert_eq!("a.esp", n.names[0]);
             assert_eq!("b.esp", n.names[1]);
         }
+
+        let input = "[nearstart message] a.esp b.esp";
+        let rules = parser::new_tes3_parser()
+            .parse_rules_from_reader(Cursor::new(input.as_bytes()))
+            .expect("Failed to parse rule")
+            .into_iter()
+            .filter_map(nearstart)
+            .collect::<Vec<_>>();
+        assert_eq!("message", rules[0].comment);
+    }
+
+    #[test]
+    fn test_order_rules_display_roundtrip() {
+        init();
+
+        let parser = parser::new_tes3_parser();
+        let inputs = [
+            "[Order this is why] a.esp b.esp",
+            "[Order]\na.esp\nb with a whitespace.esp\nc.esp",
+            "[Order]\n\"my .esp backup mod.esp\"\nb.esp",
+            "[NearStart message] a.esp b.esp",
+            "[NearEnd]\na.esp",
+            "[Override first] a.esp b.esp",
+            "[Override last]\na.esp",
+        ];
+
+        for input in inputs {
+            let rule = parser
+                .parse_single_rule(&input.to_lowercase())
+                .expect("Failed to parse rule");
+            let displayed = rule.to_string();
+            let reparsed = parser
+                .parse_single_rule(&displayed)
+                .expect("Failed to parse displayed rule");
+            assert_eq!(displayed, reparsed.to_string());
+
+            let ERule::EOrderRule(reparsed) = reparsed else {
+                panic!("Not an order rule: {}", displayed);
+            };
+            match reparsed {
+                EOrderRule::Order(o) => {
+                    assert!(o.names.len() >= 2);
+                    assert!(o.names.iter().all(|n| n.ends_with(".esp")));
+                    // names with an extension inside are quoted and stay one plugin
+                    if input.contains("backup") {
+                        assert_eq!(vec!["my .esp backup mod.esp", "b.esp"], o.names);
+                    }
+                }
+                EOrderRule::NearStart(n) => assert_eq!("message", n.comment),
+                EOrderRule::NearEnd(n) => assert_eq!(vec!["a.esp"], n.names),
+                EOrderRule::Override(o) => assert!(!o.names.is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_single_rule() {
+        init();
+
+        let parser = parser::new_tes3_parser();
+        assert!(parser
+            .parse_single_rule("[Note message] a.esp")
+            .is_ok_and(|r| note(r).is_some()));
+
+        let rule = parser
+            .parse_single_rule("[order this is why]\na.esp\nb.esp")
+            .expect("Failed to parse rule");
+        let n = order(rule).expect("Not an order rule");
+        assert_eq!("this is why", n.comment);
+        assert_eq!(vec!["a.esp", "b.esp"], n.names);
+
+        let rule = parser
+            .parse_single_rule("[nearstart message] a.esp b.esp")
+            .expect("Failed to parse rule");
+        assert_eq!("message", nearstart(rule).expect("No rules found").comment);
+
+        // zero or more than one rule is an error
+        assert!(parser.parse_single_rule("").is_err());
+        assert!(parser.parse_single_rule("; just a comment").is_err());
+        assert!(parser
+            .parse_single_rule("[Note message] a.esp\n\n[Note other] b.esp")
+            .is_err());
+
+        // non-ascii plugin names are kept intact
+        let rule = parser
+            .parse_single_rule("[Note message]\nhübsche katzen.esp")
+            .expect("Failed to parse rule");
+        let n = note(rule).expect("Not a note rule");
+        assert_eq!("hübsche katzen.esp", n.expressions[0].to_string());
     }
 
     ////////////////////////////////////////////////////////////////////////
@@ -140,6 +261,57 @@ mod unit_tests {
         }
     }
 
+    ////////////////////////////////////////////////////////////////////////
+    // OVERRIDE
+
+    #[test]
+    fn test_override() {
+        init();
+
+        let inputs = [
+            (
+                "[Override First] a.esp b.esp",
+                rules::EOverridePosition::First,
+            ),
+            (
+                "[Override Last] a.esp\nb.esp",
+                rules::EOverridePosition::Last,
+            ),
+            (
+                "[Override last]; with a comment\na.esp\nb.esp",
+                rules::EOverridePosition::Last,
+            ),
+        ];
+
+        for (input, position) in inputs {
+            let input = input.to_lowercase();
+            let reader = Cursor::new(input.as_bytes());
+
+            let rules = parser::new_tes3_parser()
+                .parse_rules_from_reader(reader)
+                .expect("Failed to parse rule")
+                .into_iter()
+                .filter_map(override_rule)
+                .collect::<Vec<_>>();
+
+            assert_eq!(1, rules.len());
+            let n = rules.first().expect("No rules found");
+
+            assert_eq!(position, n.position);
+            assert_eq!(2, n.names.len());
+
+            assert_eq!("a.esp", n.names[0]);
+            assert_eq!("b.esp", n.names[1]);
+        }
+
+        // invalid position
+        let reader = Cursor::new("[override middle] a.esp".as_bytes());
+        let rules = parser::new_tes3_parser()
+            .parse_rules_from_reader(reader)
+            .expect("Failed to parse rule");
+        assert!(rules.is_empty());
+    }
+
     ////////////////////////////////////////////////////////////////////////
     // NOTE
 
@@ -335,6 +507,38 @@ mod unit_tests {
         }
     }
 
+    #[test]
+    fn test_requires_multiple() {
+        init();
+
+        let input = "[Requires message] a.esp b.esp c.esp";
+        let rules = parser::new_tes3_parser()
+            .parse_rules_from_reader(Cursor::new(input.as_bytes()))
+            .expect("Failed to parse rule")
+            .into_iter()
+            .filter_map(requires)
+            .collect::<Vec<_>>();
+        assert_eq!(1, rules.len());
+
+        // the first expression is the dependant, the rest are all required
+        let n = rules.first().expect("No rules found");
+        match n.expression_a.as_ref().expect("no expression a") {
+            Expression::Atomic(a) => assert_eq!("a.esp", a.get_item()),
+            _ => panic!("wrong expression"),
+        }
+        match n.expression_b.as_ref().expect("no expression b") {
+            Expression::ALL(all) => assert_eq!(2, all.expressions.len()),
+            _ => panic!("wrong expression"),
+        }
+
+        // a single expression is still an error
+        let input = "[Requires message] a.esp";
+        let rules = parser::new_tes3_parser()
+            .parse_rules_from_reader(Cursor::new(input.as_bytes()))
+            .expect("Failed to parse rule");
+        assert!(rules.is_empty());
+    }
+
     ////////////////////////////////////////////////////////////////////////
     // PATCH
 
@@ -401,8 +605,9 @@ mod unit_tests {
             "a name.esp",
         ];
 
+        // parsed names are normalized
         for a in inputs {
-            test_atomic(a, a);
+            test_atomic(a, &normalize_name(a));
         }
     }
 
@@ -660,6 +865,113 @@ mod unit_tests {
         }
     }
 
+    #[test]
+    fn test_ver_game() {
+        init();
+
+        let input = "[note]\n needs game version 2.1\n[ver < 2.1 <game>]";
+        let mut parser = parser::new_cyberpunk_parser();
+        parser.warning_rules = parser
+            .parse_rules_from_reader(Cursor::new(input.as_bytes()))
+            .expect("Failed to parse rule")
+            .into_iter()
+            .filter_map(|r| match r {
+                ERule::EWarningRule(w) => Some(w),
+                _ => None,
+            })
+            .collect();
+        let mods = [PluginData::new("a.archive".into(), 0)];
+
+        for (version, expected) in [(None, 0), (Some("2.0"), 1), (Some("2.1"), 0)] {
+            parser.set_game_version(version.map(|v| parse_game_version(v).unwrap()));
+            parser.evaluate_plugins(&mods);
+            assert_eq!(
+                expected,
+                parser.warnings.len(),
+                "game version {:?}",
+                version
+            );
+        }
+    }
+
+    // IF
+    #[test]
+    fn test_if_expr() {
+        init();
+
+        let input = "[if openmw a.esp]";
+        for (parser, active) in [
+            (parser::new_openmw_parser(), true),
+            (parser::new_tes3_parser(), false),
+        ] {
+            let expr = parser
+                .parse_expression(input, true)
+                .expect("No expressions parsed");
+
+            if let Expression::IF(e) = &expr {
+                assert_eq!(e.game, ESupportedGame::Openmw);
+                assert!(is_atomic(&e.expression, "a.esp"));
+            } else {
+                panic!("wrong type");
+            }
+            let mods = vec![PluginData::new("a.esp".into(), 0)];
+            let ctx = EvalContext::new(parser.game, None, &mods);
+            assert_eq!(active, expr.eval(&ctx).is_some());
+        }
+
+        // unknown games are an error
+        assert!(parser::new_tes3_parser()
+            .parse_expression("[if oblivion a.esp]", true)
+            .is_err());
+
+        // conditional notes in a rules file
+        let rules = parser::new_tes3_parser()
+            .parse_rules_from_reader(Cursor::new(
+                "[Note]\n MWSE only\n[IF morrowind mwse.esp]\n\n[Note]\n OpenMW only\n[IF openmw mwse.esp]\n"
+                    .as_bytes(),
+            ))
+            .expect("rule parse failed");
+        assert_eq!(2, rules.len());
+        let mut parser = parser::new_tes3_parser();
+        parser.warning_rules = rules
+            .into_iter()
+            .filter_map(|r| match r {
+                ERule::EWarningRule(w) => Some(w),
+                _ => None,
+            })
+            .collect();
+        parser.evaluate_plugins(&[PluginData::new("MWSE.esp".into(), 0)]);
+        assert_eq!(1, parser.warnings.len());
+        assert!(parser.warnings[0].get_rule_name().contains("Note"));
+    }
+
+    // NOT
+    #[test]
+    fn test_not_expr() {
+        init();
+
+        let parser = parser::new_tes3_parser();
+
+        // NOT takes exactly one child
+        assert!(parser.parse_expression("[not a.esp b.esp]", true).is_err());
+        assert!(parser.parse_expression("[not ]", true).is_err());
+
+        let expr = parser
+            .parse_expression("[not [any a.esp b.esp]]", true)
+            .expect("No expressions parsed");
+        if let Expression::NOT(e) = &expr {
+            if let Expression::ANY(any) = e.expression.as_ref() {
+                assert_eq!(2, any.expressions.len());
+                assert!(is_atomic(&any.expressions[0], "a.esp"));
+                assert!(is_atomic(&any.expressions[1], "b.esp"));
+            } else {
+                panic!("wrong type");
+            }
+        } else {
+            panic!("wrong type");
+        }
+    }
+
     // Helpers
 
     fn is_atomic(e: &Expression, expected: &str) -> bool {
@@ -702,4 +1014,105 @@ mod unit_tests {
             assert_eq!(5, expr.len());
         }
     }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        init();
+
+        let input = [
+            "; a comment",
+            "[Order]",
+            "a.esp",
+            "b.esp ; inline comment",
+            "",
+            "[Note]",
+            "\tthe comment of a note",
+            "c.esp",
+            "",
+            "[Conflict] inline comment",
+            "[ALL d.esp e.esp]",
+            "f.esp",
+            "",
+            "[NearStart]",
+            "g.esm",
+        ];
+        let lf = input.join("\n");
+        let crlf = input.join("\r\n");
+        let mixed = input
+            .iter()
+            .enumerate()
+            .map(|(i, l)| match i % 3 {
+                0 => format!("{}\n", l),
+                1 => format!("{}\r\n", l),
+                _ => format!("{}\r\r\n", l),
+            })
+            .collect::<String>();
+
+        let parser = parser::new_tes3_parser();
+        let parse = |input: &str| {
+            parser
+                .parse_rules_from_reader(Cursor::new(input.as_bytes()))
+                .expect("Failed to parse rules")
+        };
+
+        let expected = parse(&lf);
+        assert_eq!(4, expected.len());
+        for input in [crlf, mixed] {
+            let rules = parse(&input);
+            assert_eq!(format!("{:?}", expected), format!("{:?}", rules));
+        }
+
+        // no carriage returns end up in tokens or comments
+        let debug = format!("{:?}", expected);
+        assert!(!debug.contains("\\r"));
+        assert!(debug.contains("\"b.esp\""));
+        assert!(debug.contains("\"the comment of a note\""));
+    }
+
+    #[test]
+    fn test_unregistered_bsa() {
+        init();
+
+        let root = std::path::PathBuf::from("tests/tes3_bsa");
+        let mods = ["bsa_mod.esp", "Registered.esp", "no_bsa.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect::<Vec<_>>();
+
+        // only the plugin with an archive that is not in [Archives] is noted
+        let warnings = parser::get_unregistered_bsa_warnings(&root, &mods);
+        assert_eq!(1, warnings.len());
+        assert_eq!("Note", warnings[0].get_rule_name());
+        assert_eq!(vec!["bsa_mod.esp".to_owned()], warnings[0].get_plugins());
+
+        // no Data Files, no warnings
+        let warnings = parser::get_unregistered_bsa_warnings(&root.join("missing"), &mods);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_plugin_count_warnings() {
+        init();
+
+        let mods = (0..4)
+            .map(|i| PluginData::new(format!("{}.esp", i), 0))
+            .collect::<Vec<_>>();
+
+        // at the limit there is no note
+        assert!(parser::get_plugin_count_warnings(&mods, 4).is_empty());
+
+        let warnings = parser::get_plugin_count_warnings(&mods, 3);
+        assert_eq!(1, warnings.len());
+        assert_eq!("Note", warnings[0].get_rule_name());
+        assert!(warnings[0].get_comment().contains("4 active plugins"));
+
+        assert_eq!(
+            Some(255),
+            parser::get_default_plugin_limit(ESupportedGame::Morrowind)
+        );
+        assert_eq!(
+            None,
+            parser::get_default_plugin_limit(ESupportedGame::Openmw)
+        );
+    }
 }
@@ -33,31 +33,51 @@ mod unit_tests {
         // test that [Note] evaluates as true when a mod is present
         {
             let mut rule = Note::new("".into(), &[e(A)]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Note] evaluates as true when both mods is present
         {
             let mut rule = Note::new("".into(), &[e(A), e(B)]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Note] evaluates as true when one of two mods is present
         {
             let mut rule = Note::new("".into(), &[e(A), e(X)]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Note] evaluates as false when a mod is not present
         {
             let mut rule = Note::new("".into(), &[e(X)]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Note] evaluates as false when a mod is not present
         {
             let mut rule = Note::new("".into(), &[e(X), e(Y)]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
     }
 
@@ -68,31 +88,141 @@ mod unit_tests {
         // test that [Conflict] evaluates as true when both mods are present
         {
             let mut rule = Conflict::new("".into(), &[e(A), e(B)]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that the order doesn't matter
         {
             let mut rule = Conflict::new("".into(), &[e(B), e(A)]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Conflict] doesn't evaluate as true when one is missing
         {
             let mut rule = Conflict::new("".into(), &[e(B), e(X)]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that the order doesn't matter
         {
             let mut rule = Conflict::new("".into(), &[e(X), e(B)]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Conflict] doesn't evaluate as true when both are missing
         {
             let mut rule = Conflict::new("".into(), &[e(X), e(Y)]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
+        }
+    }
+
+    #[test]
+    fn test_conflict_groups() {
+        init();
+
+        // 2 groups
+        {
+            let mut rule = Conflict::new("".into(), &[e(A), ALL::new(vec![e(B), e(C)]).into()]);
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
+            let groups = rule.get_groups();
+            assert_eq!(2, groups.len());
+            assert_eq!(("A".to_owned(), &vec![A.to_owned()]), groups[0]);
+            assert_eq!(
+                ("B".to_owned(), &vec![B.to_owned(), C.to_owned()]),
+                groups[1]
+            );
+        }
+
+        // 3 groups, expressions that don't match are no group
+        {
+            let mut rule = Conflict::new("".into(), &[e(A), e(X), e(B), e(C)]);
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
+            let groups = rule
+                .get_groups()
+                .into_iter()
+                .map(|(name, group)| (name, group.clone()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                vec![
+                    ("A".to_owned(), vec![A.to_owned()]),
+                    ("B".to_owned(), vec![B.to_owned()]),
+                    ("C".to_owned(), vec![C.to_owned()]),
+                ],
+                groups
+            );
+
+            // evaluating again doesn't duplicate the groups
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
+            assert_eq!(3, rule.get_groups().len());
+            assert_eq!(3, rule.get_plugins().len());
+        }
+    }
+
+    #[test]
+    fn test_conflict_overlapping_wildcards() {
+        init();
+
+        let mods = ["mod_main.esp", "mod_extra.esp", "other.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect::<Vec<_>>();
+
+        // both expressions only match the same plugin
+        {
+            let mut rule = Conflict::new("".into(), &[e("mod_*.esp"), e("mod_main.esp")]);
+            let mods = vec![mods[0].clone(), mods[2].clone()];
+            assert!(!rule.eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods)));
+            assert!(rule.get_plugins().is_empty());
+            assert!(rule.get_groups().is_empty());
+        }
+
+        // a wildcard matching two plugins of one group is no conflict
+        {
+            let mut rule = Conflict::new("".into(), &[e("mod_*.esp"), e("x.esp")]);
+            assert!(!rule.eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods)));
+            assert!(rule.get_plugins().is_empty());
+        }
+
+        // two different plugins in overlapping groups conflict
+        {
+            let mut rule = Conflict::new("".into(), &[e("mod_*.esp"), e("mod_main.esp")]);
+            assert!(rule.eval(&EvalContext::new(ESupportedGame::Morrowind, None, &mods)));
+            assert_eq!(
+                vec!["mod_main.esp".to_owned(), "mod_extra.esp".to_owned()],
+                rule.get_plugins()
+            );
         }
     }
 
@@ -103,28 +233,101 @@ mod unit_tests {
         // test that [Requires] evaluates as true when A is true and B is not
         {
             let mut rule = Requires::new("".into(), e(A), e(X));
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that the order does matter
         {
             let mut rule = Requires::new("".into(), e(X), e(A));
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Requires] evaluates as false when both mods are missing
         {
             let mut rule = Requires::new("".into(), e(X), e(Y));
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Requires] evaluates as false when both mods are there
         {
             let mut rule = Requires::new("".into(), e(A), e(B));
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
     }
 
+    #[test]
+    fn test_requires_multiple() {
+        init();
+
+        let mut parser = plox::parser::new_tes3_parser();
+
+        // a requires b and x, x is missing
+        let rules = parser
+            .parse_rules_from_reader(std::io::Cursor::new(
+                "[requires] a.esp b.esp x.esp".as_bytes(),
+            ))
+            .expect("Failed to parse rule");
+        parser.warning_rules = rules
+            .into_iter()
+            .filter_map(plox::requires)
+            .map(|r| r.into())
+            .collect();
+        parser.evaluate_plugins(&get_mods());
+        assert_eq!(1, parser.warnings.len());
+
+        // a requires b and c, both are there
+        let rules = parser
+            .parse_rules_from_reader(std::io::Cursor::new(
+                "[requires] a.esp b.esp c.esp".as_bytes(),
+            ))
+            .expect("Failed to parse rule");
+        parser.warning_rules = rules
+            .into_iter()
+            .filter_map(plox::requires)
+            .map(|r| r.into())
+            .collect();
+        parser.evaluate_plugins(&get_mods());
+        assert!(parser.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_missing_masters() {
+        init();
+
+        let mut mods = get_mods();
+        mods[0].masters = Some(vec![("B.esp".into(), 0), ("x.esm".into(), 0)]);
+        mods[1].masters = Some(vec![("C.ESP".into(), 0)]);
+
+        let mut parser = plox::parser::new_tes3_parser();
+        parser.evaluate_plugins(&mods);
+
+        // only the missing master of a is reported
+        assert_eq!(1, parser.warnings.len());
+        let warning = &parser.warnings[0];
+        assert_eq!("Requires", warning.get_rule_name());
+        assert_eq!(vec![A.to_owned()], warning.get_plugins());
+        assert_eq!(
+            "a.esp requires master x.esm which is not installed.",
+            warning.get_comment()
+        );
+    }
+
     #[test]
     fn test_patch() {
         init();
@@ -132,25 +335,41 @@ mod unit_tests {
         // test that [Patch] evaluates as true when A is true and B is not: mod is there, but patch is missing
         {
             let mut rule = Patch::new("".into(), e(A), e(X));
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Patch] evaluates as true when B is true and A is not: patch is there, but mod is missing
         {
             let mut rule = Patch::new("".into(), e(X), e(A));
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Patch] evaluates as false when both mods are missing
         {
             let mut rule = Patch::new("".into(), e(X), e(Y));
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [Patch] evaluates as false when both mods are there
         {
             let mut rule = Patch::new("".into(), e(A), e(B));
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
     }
 
@@ -419,37 +638,90 @@ mod unit_tests {
         // test that [ALL] is true if A and B is true
         {
             let mut rule = Note::new("".into(), &[ALL::new(vec![e(A), e(B)]).into()]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [ALL] is false if A is true and B is not true
         {
             let mut rule = Note::new("".into(), &[ALL::new(vec![e(A), e(X)]).into()]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [ALL] is false if A is not true and B is true
         {
             let mut rule = Note::new("".into(), &[ALL::new(vec![e(X), e(A)]).into()]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [ALL] is false if A is not true and B is not true
         {
             let mut rule = Note::new("".into(), &[ALL::new(vec![e(X), e(Y)]).into()]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [ANY] is true if A or B is true
         {
             let mut rule = Note::new("".into(), &[ANY::new(vec![e(A), e(X)]).into()]);
-            assert!(rule.eval(&get_mods()));
+            assert!(rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
 
         // test that [ANY] is true if A and B are not true
         {
             let mut rule = Note::new("".into(), &[ANY::new(vec![e(Y), e(X)]).into()]);
-            assert!(!rule.eval(&get_mods()));
+            assert!(!rule.eval(&EvalContext::new(
+                ESupportedGame::Morrowind,
+                None,
+                &get_mods()
+            )));
         }
     }
+
+    #[test]
+    fn test_diff_rules() {
+        init();
+
+        let old: Vec<ERule> = vec![
+            Order::from(A, B).into(),
+            Order::from(B, C).with_comment("old comment").into(),
+            Note::new("note".into(), &[e(D)]).into(),
+        ];
+        let new: Vec<ERule> = vec![
+            Order::from(B, C).with_comment("new comment").into(),
+            Note::new("note".into(), &[e(D)]).into(),
+            Conflict::new("conflict".into(), &[e(E), e(F)]).into(),
+        ];
+
+        let diff = plox::diff_rules(&old, &new);
+        assert_eq!(1, diff.removed.len());
+        assert_eq!("[ORDER]\na.esp\nb.esp\n", diff.removed[0].to_string());
+        assert_eq!(1, diff.added.len());
+        assert!(matches!(
+            diff.added[0],
+            ERule::EWarningRule(EWarningRule::Conflict(_))
+        ));
+        assert_eq!(1, diff.changed.len());
+        assert!(diff.changed[0].1.to_string().contains("new comment"));
+
+        assert!(plox::diff_rules(&old, &old).is_empty());
+    }
 }
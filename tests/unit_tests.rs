@@ -2,7 +2,10 @@
 mod unit_tests {
 
     use plox::{
-        rules::Order,
+        expressions::{Atomic, IF},
+        rules::{
+            Conflict, EOrderRule, EOverridePosition, NearEnd, NearStart, Note, Order, Override,
+        },
         sorter::{self, Sorter},
         *,
     };
@@ -18,6 +21,52 @@ mod unit_tests {
         Sorter::new(sorter::ESortType::StableFull, 1000)
     }
 
+    #[test]
+    fn test_ignored_plugins() {
+        init();
+
+        let all_mods: Vec<PluginData> = ["a.esp", "x_1.esp", "b.esp", "x_2.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        let ignored = get_ignored_plugins(&all_mods, &["x_*.esp".to_owned()]);
+        assert_eq!(vec!["x_1.esp", "x_2.esp"], ignored);
+
+        let mods = all_mods
+            .iter()
+            .filter(|p| !ignored.contains(&p.name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // ignored plugins produce no warnings
+        let mut parser = parser::new_tes3_parser();
+        parser.order_rules = vec![Order::from("b.esp", "a.esp").into()];
+        parser.warning_rules =
+            vec![Note::new("note".into(), &[Atomic::from("x_1.esp").into()]).into()];
+        parser.evaluate_plugins(&mods);
+        assert!(parser.warnings.is_empty());
+
+        // ignored plugins keep their position
+        let sorted = sorter::new_stable_sorter()
+            .topo_sort(ESupportedGame::Morrowind, &mods, &parser.order_rules, &[])
+            .expect("rules contain a cycle");
+        let all_order = all_mods.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            vec!["b.esp", "x_1.esp", "a.esp", "x_2.esp"],
+            restore_ignored_plugins(&all_order, &sorted, &ignored)
+        );
+
+        // ignored plugins keep their original name
+        assert_eq!(
+            vec!["a.esp", "X_1.esp"],
+            restore_ignored_plugins(
+                &["a.esp".to_owned(), "X_1.esp".to_owned()],
+                &["a.esp".to_owned()],
+                &ignored
+            )
+        );
+    }
+
     #[test]
     fn test_cycle() {
         init();
@@ -56,6 +105,43 @@ mod unit_tests {
         );
     }
 
+    #[test]
+    fn test_cycle_fails_fast() {
+        init();
+
+        let order = [
+            Order::from("a.esp", "b.esp").into(),
+            Order::from("b.esp", "c.esp").into(),
+            Order::from("c.esp", "a.esp").into(),
+            Order::from("d.esp", "e.esp").into(),
+        ];
+
+        let mods: Vec<PluginData> = ["a.esp", "b.esp", "c.esp", "d.esp", "e.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        for mut sorter in [
+            sorter::new_unstable_sorter(),
+            sorter::new_stable_sorter(),
+            Sorter::new(sorter::ESortType::StableFull, 100_000),
+        ] {
+            let now = std::time::Instant::now();
+            let result = sorter.topo_sort(ESupportedGame::Morrowind, &mods, &order, &[]);
+            assert!(now.elapsed().as_secs() < 1, "cycle detection is not fast");
+
+            match result {
+                Err(sorter::SortError::Cycle(cycles)) => {
+                    assert_eq!(cycles.len(), 1);
+                    let mut cycle = cycles[0].clone();
+                    cycle.sort();
+                    assert_eq!(cycle, ["a.esp", "b.esp", "c.esp"]);
+                }
+                other => panic!("expected a cycle error, got {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_ordering() {
         init();
@@ -95,4 +181,802 @@ mod unit_tests {
             Err(e) => panic!("Error: {}", e),
         }
     }
+
+    #[test]
+    fn test_check_order_detailed() {
+        init();
+
+        let order = [
+            Order::from("b", "a").into(),
+            Order::from("b", "c").into(),
+            Order::from("x_*", "c").into(),
+        ];
+
+        let result = ["a", "b", "c", "x_1", "x_2"].map(String::from);
+        assert!(!check_order(&result, &order));
+        assert_eq!(
+            vec![
+                ("b".to_owned(), "a".to_owned()),
+                ("x_1".to_owned(), "c".to_owned()),
+                ("x_2".to_owned(), "c".to_owned()),
+            ],
+            check_order_detailed(&result, &order)
+        );
+
+        let result = ["b", "x_1", "a", "c"].map(String::from);
+        assert!(check_order(&result, &order));
+        assert!(check_order_detailed(&result, &order).is_empty());
+    }
+
+    #[test]
+    fn test_sort_without_applicable_rules() {
+        init();
+
+        // an openmw load order with only lua scripts
+        let mods: Vec<PluginData> = ["b.omwscripts", "A.omwscripts", "c.omwscripts"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        let names = ["b.omwscripts", "A.omwscripts", "c.omwscripts"].map(String::from);
+
+        // without rules the order is left untouched
+        let result = sorter::new_stable_sorter()
+            .topo_sort(ESupportedGame::Openmw, &mods, &[], &[])
+            .expect("sorting without rules failed");
+        assert_eq!(result, names);
+
+        // rules that don't apply to any plugin don't change it either
+        let order = [Order::from("x.esp", "y.esp").into()];
+        for mut sorter in [sorter::new_stable_sorter(), new_stable_full_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Openmw, &mods, &order, &[])
+                .expect("sorting with unrelated rules failed");
+            assert_eq!(result, names);
+        }
+    }
+
+    #[test]
+    fn test_minimal_fix() {
+        init();
+
+        let cases: Vec<(Vec<&str>, Vec<EOrderRule>, usize)> = vec![
+            (vec!["a", "b", "c"], vec![Order::from("a", "b").into()], 0),
+            (
+                vec!["b", "c", "d", "a"],
+                vec![Order::from("a", "b").into()],
+                1,
+            ),
+            (
+                vec!["c", "b", "a"],
+                vec![Order::from("a", "b").into(), Order::from("b", "c").into()],
+                2,
+            ),
+            (
+                vec!["b", "x_1", "c", "x_2", "a"],
+                vec![Order::from("a", "x_*").into()],
+                1,
+            ),
+        ];
+
+        for (order, rules, expected) in cases {
+            let order = order.into_iter().map(String::from).collect::<Vec<_>>();
+            let moves = minimal_fix(&order, &rules);
+            assert_eq!(moves.len(), expected, "{:?}", order);
+
+            // the moves fix the order
+            let mut fixed = order.clone();
+            for m in &moves {
+                assert_eq!(fixed.remove(m.from), m.plugin);
+                fixed.insert(m.to, m.plugin.clone());
+            }
+            assert!(check_order(&fixed, &rules), "{:?}", fixed);
+
+            // a full re-sort moves at least as many plugins
+            let mods = order
+                .iter()
+                .map(|e| PluginData::new(e.to_string(), 0))
+                .collect::<Vec<_>>();
+            let sorted = sorter::new_stable_sorter()
+                .topo_sort(ESupportedGame::Morrowind, &mods, &rules, &[])
+                .expect("sorting failed");
+            let moved = order.iter().zip(&sorted).filter(|(a, b)| a != b).count();
+            assert!(moves.len() <= moved, "{:?} -> {:?}", order, sorted);
+        }
+    }
+
+    #[test]
+    fn test_ordering_only() {
+        init();
+
+        let order = [
+            Order::from("tr_a.esp", "tr_b.esp").into(),
+            Order::from("z.esp", "x.esp").into(),
+        ];
+
+        let mods: Vec<PluginData> = ["x.esp", "tr_b.esp", "y.esp", "tr_a.esp", "z.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        // only the subset is sorted, the rule on x and z is ignored
+        let result = sorter::new_stable_sorter()
+            .topo_sort_only(ESupportedGame::Morrowind, &mods, "tr_*", &order, &[])
+            .expect("error sorting");
+        assert_eq!(result, ["x.esp", "tr_a.esp", "y.esp", "tr_b.esp", "z.esp"]);
+
+        // rules crossing the boundary are respected
+        let order = [
+            Order::from("tr_a.esp", "tr_b.esp").into(),
+            Order::from("tr_a.esp", "x.esp").into(),
+        ];
+        let result = sorter::new_stable_sorter()
+            .topo_sort_only(ESupportedGame::Morrowind, &mods, "tr_*", &order, &[])
+            .expect("error sorting");
+        assert_eq!(result, ["tr_a.esp", "x.esp", "y.esp", "tr_b.esp", "z.esp"]);
+
+        // rules crossing the boundary that can't be satisfied
+        let order = [
+            Order::from("tr_a.esp", "tr_b.esp").into(),
+            Order::from("tr_b.esp", "x.esp").into(),
+            Order::from("y.esp", "tr_a.esp").into(),
+        ];
+        assert!(sorter::new_stable_sorter()
+            .topo_sort_only(ESupportedGame::Morrowind, &mods, "tr_*", &order, &[])
+            .is_err());
+
+        // rules that only connect the subset through a pinned plugin are respected
+        let order = [
+            Order::from("tr_a.esp", "y.esp").into(),
+            Order::from("y.esp", "tr_b.esp").into(),
+        ];
+        let mods: Vec<PluginData> = ["tr_b.esp", "y.esp", "tr_a.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            let result = sorter
+                .topo_sort_only(ESupportedGame::Morrowind, &mods, "tr_*", &order, &[])
+                .expect("error sorting");
+            assert_eq!(result, ["tr_a.esp", "y.esp", "tr_b.esp"]);
+        }
+    }
+
+    #[test]
+    fn test_ordering_locked() {
+        init();
+
+        let order = [
+            Order::from("c.esp", "b.esp").into(),
+            Order::from("d.esp", "c.esp").into(),
+        ];
+
+        let mods: Vec<PluginData> = ["a.esp", "b.esp", "c.esp", "d.esp", "e.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        // the first and last plugin keep their index
+        let locked = ["a.esp".to_owned(), "E.esp".to_owned()];
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            let result = sorter
+                .topo_sort_locked(ESupportedGame::Morrowind, &mods, &locked, &order, &[])
+                .expect("error sorting");
+            assert_eq!(result, ["a.esp", "d.esp", "c.esp", "b.esp", "e.esp"]);
+        }
+
+        // a rule that would move a locked plugin
+        let order = [Order::from("b.esp", "a.esp").into()];
+        assert!(sorter::new_stable_sorter()
+            .topo_sort_locked(ESupportedGame::Morrowind, &mods, &locked, &order, &[])
+            .is_err());
+
+        // a rule between two locked plugins that contradicts their order
+        let order = [Order::from("e.esp", "a.esp").into()];
+        assert!(sorter::new_stable_sorter()
+            .topo_sort_locked(ESupportedGame::Morrowind, &mods, &locked, &order, &[])
+            .is_err());
+
+        // rules that only connect two unlocked plugins through a locked plugin are respected
+        let order = [
+            Order::from("u1.esp", "l.esp").into(),
+            Order::from("l.esp", "u2.esp").into(),
+        ];
+        let mods: Vec<PluginData> = ["u2.esp", "l.esp", "u1.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        let locked = ["l.esp".to_owned()];
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            let result = sorter
+                .topo_sort_locked(ESupportedGame::Morrowind, &mods, &locked, &order, &[])
+                .expect("error sorting");
+            assert_eq!(result, ["u1.esp", "l.esp", "u2.esp"]);
+        }
+    }
+
+    #[test]
+    fn test_wildcard_order_edges() {
+        init();
+
+        let mods: Vec<PluginData> = [
+            "b2.esp", "ab2.esp", "a1.esp", "b1.esp", "ab1.esp", "a2.esp", "c.esp",
+        ]
+        .iter()
+        .map(|e| PluginData::new(e.to_string(), 0))
+        .collect();
+
+        // no overlap: every a loads before every b, repeated rules add no edges
+        let order = [
+            Order::from("a?.esp", "b*.esp").into(),
+            Order::from("a?.esp", "b*.esp").into(),
+        ];
+        let data = sorter::get_graph_data(&mods, &order, &[]);
+        assert_eq!(data.edges.len(), 4);
+
+        // overlap: plugins matching both sides load in between and are not ordered among each other
+        let order = [Order::from("a*.esp", "*b*.esp").into()];
+        let data = sorter::get_graph_data(&mods, &order, &[]);
+        assert_eq!(data.edges.len(), 8);
+        assert!(data.get_edge_origin("ab1.esp", "ab2.esp").is_none());
+        assert!(data.get_edge_origin("ab2.esp", "ab1.esp").is_none());
+        assert!(data.get_edge_origin("a1.esp", "b1.esp").is_none());
+
+        let result = sorter::new_stable_sorter()
+            .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+            .expect("error sorting");
+        let pos = |n: &str| result.iter().position(|r| r == n).unwrap();
+        for a in ["a1.esp", "a2.esp"] {
+            for ab in ["ab1.esp", "ab2.esp"] {
+                assert!(pos(a) < pos(ab));
+                for b in ["b1.esp", "b2.esp"] {
+                    assert!(pos(ab) < pos(b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_near_with_order() {
+        init();
+
+        let order = [
+            NearStart::new(vec!["a.esp".into(), "b.esp".into()]).into(),
+            NearEnd::new(vec!["y.esp".into(), "z.esp".into()]).into(),
+            Order::from("b.esp", "a.esp").into(),
+            Order::from("z.esp", "y.esp").into(),
+        ];
+
+        let mods: Vec<PluginData> = ["c.esp", "y.esp", "a.esp", "z.esp", "b.esp", "d.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        // the unstable sorter ignores nearstart and nearend
+        for mut sorter in [sorter::new_stable_sorter(), new_stable_full_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+                .expect("error sorting");
+            assert_eq!(result[..2], ["b.esp", "a.esp"]);
+            assert_eq!(result[4..], ["z.esp", "y.esp"]);
+        }
+
+        // order rules with other plugins still win
+        let order = [
+            NearStart::new(vec!["a.esp".into(), "b.esp".into()]).into(),
+            Order::from("b.esp", "a.esp").into(),
+            Order::from("c.esp", "b.esp").into(),
+        ];
+        // the unstable sorter ignores nearstart and nearend
+        for mut sorter in [sorter::new_stable_sorter(), new_stable_full_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+                .expect("error sorting");
+            assert!(check_order(&result, &order));
+            // the nearstart plugins follow c directly
+            let pos = |n: &str| result.iter().position(|r| r == n).unwrap();
+            assert_eq!(pos("b.esp"), pos("c.esp") + 1);
+            assert_eq!(pos("a.esp"), pos("b.esp") + 1);
+        }
+    }
+
+    #[test]
+    fn test_order_comments() {
+        init();
+
+        let order = [
+            Order::from("a.esp", "b.esp")
+                .with_comment("a overwrites b")
+                .into(),
+            Order::from("c.esp", "d.esp").into(),
+        ];
+
+        let mods: Vec<PluginData> = ["b.esp", "a.esp", "d.esp", "c.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        let data = sorter::get_graph_data(&mods, &order, &[]);
+        assert_eq!(
+            data.get_edge_origin("a.esp", "b.esp"),
+            Some(&sorter::EEdgeOrigin::Order("a overwrites b".into()))
+        );
+        assert_eq!(data.get_edge_origin("b.esp", "a.esp"), None);
+
+        let old_order = mods.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+        let reasons = data.get_move_reasons(&old_order);
+        assert_eq!(
+            reasons,
+            [
+                ("a.esp".into(), "b.esp".into(), "a overwrites b".into()),
+                ("c.esp".into(), "d.esp".into(), String::new())
+            ]
+        );
+
+        let result = sorter::new_stable_sorter()
+            .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+            .expect("error sorting");
+        assert!(data.get_move_reasons(&result).is_empty());
+    }
+
+    #[test]
+    fn test_plugin_edges() {
+        init();
+
+        let order = [
+            Order::from("a.esp", "b.esp")
+                .with_comment("a overwrites b")
+                .into(),
+            Order::from("b.esp", "c.esp").into(),
+        ];
+
+        let mut plugin = PluginData::new("B.esp".into(), 0);
+        plugin.masters = Some(vec![("m.esm".into(), 0)]);
+        let mods = vec![
+            PluginData::new("a.esp".into(), 0),
+            plugin,
+            PluginData::new("c.esp".into(), 0),
+            PluginData::new("m.esm".into(), 0),
+        ];
+
+        let data = sorter::get_graph_data(&mods, &order, &[]);
+        let edges = data.get_plugin_edges("b.esp").expect("plugin not found");
+        assert_eq!(
+            edges.incoming,
+            [
+                (
+                    "a.esp".to_owned(),
+                    sorter::EEdgeOrigin::Order("a overwrites b".into())
+                ),
+                ("m.esm".to_owned(), sorter::EEdgeOrigin::Master)
+            ]
+        );
+        assert_eq!(
+            edges.outgoing,
+            [(
+                "c.esp".to_owned(),
+                sorter::EEdgeOrigin::Order(String::new())
+            )]
+        );
+        assert!(data.get_plugin_edges("x.esp").is_none());
+    }
+
+    #[test]
+    fn test_override() {
+        init();
+
+        let order = [
+            Order::from("a.esp", "b.esp").into(),
+            // nearend alone would move z.esp behind y.esp
+            NearEnd::new(vec!["y.esp".into(), "z.esp".into()]).into(),
+            NearStart::new(vec!["c.esp".into()]).into(),
+            Override::new(EOverridePosition::Last, vec!["y.esp".into()]).into(),
+            Override::new(EOverridePosition::First, vec!["b.esp".into()]).into(),
+        ];
+
+        let mods: Vec<PluginData> = ["y.esp", "a.esp", "z.esp", "b.esp", "c.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        // pins win over near rules and order rules
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+                .expect("error sorting");
+            assert_eq!("b.esp", result.first().unwrap());
+            assert_eq!("y.esp", result.last().unwrap());
+        }
+
+        // all wildcard matches are pinned and keep their order
+        let mods: Vec<PluginData> = ["a1.esp", "a2.esp", "x.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        for (position, expected) in [
+            (EOverridePosition::Last, ["x.esp", "a1.esp", "a2.esp"]),
+            (EOverridePosition::First, ["a1.esp", "a2.esp", "x.esp"]),
+        ] {
+            let order = [Override::new(position, vec!["a*.esp".into()]).into()];
+            let result = sorter::new_stable_sorter()
+                .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+                .expect("error sorting");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_master_casing() {
+        init();
+
+        let mut plugin = PluginData::new("My Mod.esp".into(), 0);
+        plugin.masters = Some(vec![
+            ("Morrowind.ESM".into(), 0),
+            ("TRIBUNAL.esm".into(), 0),
+            ("other*.esp".into(), 0),
+        ]);
+        let mods = vec![
+            plugin,
+            PluginData::new("Tribunal.esm".into(), 0),
+            PluginData::new("morrowind.esm".into(), 0),
+            PluginData::new("other_mod.esp".into(), 0),
+        ];
+
+        let data = sorter::get_graph_data(&mods, &[], &[]);
+        assert_eq!(
+            data.get_edge_origin("morrowind.esm", "my mod.esp"),
+            Some(&sorter::EEdgeOrigin::Master)
+        );
+        assert_eq!(
+            data.get_edge_origin("Tribunal.esm", "My Mod.esp"),
+            Some(&sorter::EEdgeOrigin::Master)
+        );
+        // masters are never wildcards
+        assert_eq!(data.get_edge_origin("other_mod.esp", "my mod.esp"), None);
+        assert_eq!(data.edges.len(), 2);
+    }
+
+    #[test]
+    fn test_openmw_content_placement() {
+        init();
+
+        let order = [
+            Order::from("scripts.omwscripts", "addon.omwaddon").into(),
+            Order::from("b.esp", "scripts.omwscripts").into(),
+        ];
+
+        let mods: Vec<PluginData> = [
+            "addon.omwaddon",
+            "b.esp",
+            "master.esm",
+            "scripts.omwscripts",
+            "template.omwgame",
+            "a.esp",
+        ]
+        .iter()
+        .map(|e| PluginData::new(e.to_string(), 0))
+        .collect();
+
+        // the game file loads first, then the masters, then all other content in rule order
+        for mut sorter in [sorter::new_stable_sorter(), sorter::new_full_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Openmw, &mods, &order, &[])
+                .expect("error sorting");
+            assert_eq!(
+                vec![
+                    "template.omwgame",
+                    "master.esm",
+                    "b.esp",
+                    "scripts.omwscripts",
+                    "addon.omwaddon",
+                    "a.esp",
+                ],
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_morrowind_esm_after_game_file() {
+        init();
+
+        let mods: Vec<PluginData> = ["a.esp", "tribunal.esm", "morrowind.esm", "base.omwgame"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+
+        let result = sorter::new_stable_sorter()
+            .topo_sort(
+                ESupportedGame::Openmw,
+                &mods,
+                &[Order::from("tribunal.esm", "a.esp").into()],
+                &[],
+            )
+            .expect("error sorting");
+        assert_eq!(
+            vec!["base.omwgame", "morrowind.esm", "tribunal.esm", "a.esp"],
+            result
+        );
+    }
+
+    #[test]
+    fn test_no_esm_hoist() {
+        init();
+
+        let mods: Vec<PluginData> = ["a.esp", "late.esm", "b.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        let order = [Order::from("a.esp", "late.esm").into()];
+
+        // masters are moved to the top by default
+        let result = sorter::new_stable_sorter()
+            .topo_sort(ESupportedGame::Openmw, &mods, &order, &[])
+            .expect("error sorting");
+        assert_eq!(vec!["late.esm", "a.esp", "b.esp"], result);
+        assert!(sorter::get_misplaced_masters(&result).is_empty());
+
+        // without hoisting only the rules decide
+        for sorter in [sorter::new_stable_sorter(), new_stable_full_sorter()] {
+            let result = sorter
+                .with_hoist_masters(false)
+                .topo_sort(ESupportedGame::Openmw, &mods, &order, &[])
+                .expect("error sorting");
+            assert_eq!(vec!["a.esp", "late.esm", "b.esp"], result);
+            assert_eq!(vec!["late.esm"], sorter::get_misplaced_masters(&result));
+        }
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        init();
+
+        let sizes = [("a.esp", 10), ("b.esp", 30), ("c.esp", 20), ("d.esp", 0)];
+        let mods: Vec<PluginData> = sizes
+            .iter()
+            .map(|(name, size)| PluginData::new(name.to_string(), *size))
+            .collect();
+        let rules = vec![Conflict::new(
+            "a, b and c conflict".into(),
+            &[
+                Atomic::from("a.esp").into(),
+                Atomic::from("b.esp").into(),
+                Atomic::from("c.esp").into(),
+            ],
+        )
+        .into()];
+
+        let names =
+            |plugins: &[PluginData]| plugins.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        for (strategy, kept, removed) in [
+            (EConflictStrategy::KeepFirst, "a.esp", ["b.esp", "c.esp"]),
+            (EConflictStrategy::KeepLast, "c.esp", ["a.esp", "b.esp"]),
+            (EConflictStrategy::KeepLarger, "b.esp", ["a.esp", "c.esp"]),
+        ] {
+            let (plugins, removals) =
+                resolve_conflicts(ESupportedGame::Morrowind, &mods, &rules, |c, p| {
+                    strategy.get_group(c, p).unwrap()
+                });
+            assert_eq!(names(&plugins), [kept, "d.esp"], "{:?}", strategy);
+            assert_eq!(
+                removals.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+                removed,
+                "{:?}",
+                strategy
+            );
+            assert!(removals.iter().all(|r| r.reason == "a, b and c conflict"));
+        }
+
+        // interactive is asked by the caller
+        let conflict = Conflict::default();
+        assert_eq!(
+            EConflictStrategy::Interactive.get_group(&conflict, &mods),
+            None
+        );
+        let mut asked = vec![];
+        let (plugins, _) = resolve_conflicts(ESupportedGame::Morrowind, &mods, &rules, |c, p| {
+            asked.push((c.comment.clone(), p.len()));
+            1
+        });
+        assert_eq!(names(&plugins), ["b.esp", "d.esp"]);
+        assert_eq!(asked, [("a, b and c conflict".to_owned(), 4)]);
+
+        // conflicts are evaluated for the given game
+        let openmw_rules = vec![Conflict::new(
+            "a and b conflict in openmw".into(),
+            &[
+                IF::new(ESupportedGame::Openmw, Atomic::from("a.esp").into()).into(),
+                Atomic::from("b.esp").into(),
+            ],
+        )
+        .into()];
+        let (plugins, _) =
+            resolve_conflicts(ESupportedGame::Morrowind, &mods, &openmw_rules, |_, _| 0);
+        assert_eq!(plugins.len(), 4);
+        let (plugins, _) =
+            resolve_conflicts(ESupportedGame::Openmw, &mods, &openmw_rules, |_, _| 0);
+        assert_eq!(names(&plugins), ["a.esp", "c.esp", "d.esp"]);
+
+        // no conflict, nothing is removed
+        let (plugins, removals) =
+            resolve_conflicts(ESupportedGame::Morrowind, &mods[2..], &rules, |_, _| 0);
+        assert_eq!(names(&plugins), ["c.esp", "d.esp"]);
+        assert!(removals.is_empty());
+    }
+
+    #[test]
+    fn test_prompt_conflict_group() {
+        let conflict = Conflict {
+            comment: "a conflict".into(),
+            conflicts: vec![vec!["a.esp".into()], vec!["b.esp".into()]],
+            ..Default::default()
+        };
+
+        // invalid selections are asked again
+        let mut input = std::io::Cursor::new("x\n3\n2\n");
+        assert_eq!(prompt_conflict_group(&conflict, &mut input), 1);
+        // no input keeps the first group
+        let mut input = std::io::Cursor::new("");
+        assert_eq!(prompt_conflict_group(&conflict, &mut input), 0);
+    }
+
+    #[test]
+    fn test_problem_plugins() {
+        init();
+
+        let mods: Vec<PluginData> = ["A.esp", "b.esp", "c.esp", "d.esp"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        let order = mods.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+
+        let mut parser = parser::new_tes3_parser();
+        parser.warning_rules = vec![
+            Note::new("a note".into(), &[Atomic::from("a.esp").into()]).into(),
+            Conflict::new(
+                "c and d conflict".into(),
+                &[Atomic::from("d.esp").into(), Atomic::from("c.esp").into()],
+            )
+            .into(),
+            Note::new("not installed".into(), &[Atomic::from("x.esp").into()]).into(),
+        ];
+        parser.evaluate_plugins(&mods);
+
+        // only plugins with warnings are kept, in load order and casing
+        let map = get_plugin_warning_map(&parser.warnings);
+        assert_eq!(3, map.len());
+        assert_eq!(
+            get_problem_plugins(&order, &map),
+            ["A.esp", "c.esp", "d.esp"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        init();
+
+        assert_eq!(normalize_name("  Mod  Name.ESP "), "mod name.esp");
+        assert_eq!(normalize_name("mod name.esp"), "mod name.esp");
+
+        for (rule_name, plugin_name) in [
+            ("  Mod  Name.ESP ", "mod name.esp"),
+            ("mod name.esp", "  Mod  Name.ESP "),
+        ] {
+            let mut parser = parser::new_tes3_parser();
+            let input = format!(
+                "[Order]\nb.esp\n{}\n\n[Note a note]\n{}\n",
+                rule_name, rule_name
+            );
+            for rule in parser
+                .parse_rules_from_reader(std::io::Cursor::new(input.as_bytes()))
+                .unwrap()
+            {
+                match rule {
+                    rules::ERule::EOrderRule(o) => parser.order_rules.push(o),
+                    rules::ERule::EWarningRule(w) => parser.warning_rules.push(w),
+                }
+            }
+
+            let mods: Vec<PluginData> = [plugin_name, "b.esp"]
+                .iter()
+                .map(|e| PluginData::new(e.to_string(), 0))
+                .collect();
+
+            // rules
+            parser.evaluate_plugins(&mods);
+            assert_eq!(1, parser.warnings.len());
+            assert_eq!(
+                vec!["mod name.esp".to_owned()],
+                parser.warnings[0].get_plugins()
+            );
+
+            // sorting keeps the original name
+            let result = sorter::new_stable_sorter()
+                .topo_sort(
+                    ESupportedGame::Morrowind,
+                    &mods,
+                    &parser.order_rules,
+                    &parser.warning_rules,
+                )
+                .unwrap();
+            assert_eq!(result, ["b.esp", plugin_name]);
+        }
+    }
+
+    #[test]
+    fn test_normalize_name_ascii_only() {
+        init();
+
+        // only ascii characters are casefolded
+        assert_eq!(normalize_name("I.ESP"), "i.esp");
+        assert_eq!(normalize_name("İ.ESP"), "İ.esp");
+        assert_ne!(normalize_name("İ.esp"), normalize_name("i.esp"));
+
+        let mut parser = parser::new_tes3_parser();
+        let input = "[Note a note]\nİ.esp\n";
+        for rule in parser
+            .parse_rules_from_reader(std::io::Cursor::new(input.as_bytes()))
+            .unwrap()
+        {
+            if let rules::ERule::EWarningRule(w) = rule {
+                parser.warning_rules.push(w);
+            }
+        }
+
+        for (plugin_name, expected) in [("İ.esp", 1), ("İ.ESP", 1), ("i.esp", 0), ("I.esp", 0)] {
+            // evaluate twice to make sure the result is deterministic
+            for _ in 0..2 {
+                let mods = vec![PluginData::new(plugin_name.to_owned(), 0)];
+                parser.evaluate_plugins(&mods);
+                assert_eq!(expected, parser.warnings.len(), "{}", plugin_name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_plugins() {
+        init();
+
+        // the same plugin in two data folders
+        let mods = vec![
+            PluginData::new("a.esp".to_owned(), 10),
+            PluginData::new("b.esp".to_owned(), 0),
+            PluginData::new("A.esp".to_owned(), 20),
+        ];
+        let order = vec![Order::from("b.esp", "a.esp").into()];
+
+        let data = sorter::get_graph_data(&mods, &order, &[]);
+        assert_eq!(data.duplicates, ["a.esp"]);
+        assert_eq!(data.index_dict["a.esp"], 0);
+
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            assert_eq!(
+                sorter.topo_sort(ESupportedGame::Morrowind, &mods, &order, &[]),
+                Err(sorter::SortError::Duplicates(vec!["a.esp".to_owned()]))
+            );
+        }
+    }
+
+    #[test]
+    fn test_builtin_order() {
+        init();
+
+        let mods: Vec<PluginData> = ["Bloodmoon.esm", "a.esp", "Tribunal.esm", "Morrowind.esm"]
+            .iter()
+            .map(|e| PluginData::new(e.to_string(), 0))
+            .collect();
+        // the rules don't cover the expansions
+        let mut order = vec![Order::from("a.esp", "b.esp").into()];
+        order.extend(parser::get_builtin_order_rules(ESupportedGame::Morrowind));
+
+        for mut sorter in [sorter::new_unstable_sorter(), sorter::new_stable_sorter()] {
+            let result = sorter
+                .topo_sort(ESupportedGame::Morrowind, &mods, &order, &[])
+                .unwrap();
+            let pos = |name: &str| result.iter().position(|p| p == name).unwrap();
+            assert!(pos("Morrowind.esm") < pos("Tribunal.esm"));
+            assert!(pos("Tribunal.esm") < pos("Bloodmoon.esm"));
+        }
+
+        assert!(parser::get_builtin_order_rules(ESupportedGame::Cyberpunk).is_empty());
+    }
 }